@@ -1,11 +1,21 @@
 use ::clap::{AppSettings, Clap};
 use std::path::PathBuf;
+use std::str::FromStr;
+
+use crate::output::{DataEncoding, Format};
+use pngme_lib::compression::Algorithm;
+use pngme_lib::png::ChunkPosition;
+use pngme_lib::repair::{AfterIend, CrcRepair};
+use pngme_lib::strip::StripPolicy;
 
 #[derive(Clap)]
 #[clap(setting = AppSettings::ColoredHelp)]
 #[clap(version = env!("CARGO_PKG_VERSION"))]
 #[clap(about = "Utility that lets you hide secret messages in PNG files")]
 pub struct Opts {
+    /// Output format: text, json or ndjson (one JSON record per line)
+    #[clap(long, global = true, default_value = "text")]
+    pub(crate) format: Format,
     #[clap(subcommand)]
     pub(crate) sub_cmd: SubCommand,
 }
@@ -16,6 +26,34 @@ pub enum SubCommand {
     Decode(Decode),
     Remove(Remove),
     Print(Print),
+    Validate(Validate),
+    Text(Text),
+    Keygen(Keygen),
+    Sign(Sign),
+    Verify(Verify),
+    Diff(Diff),
+    Repair(Repair),
+    Strip(Strip),
+    Scan(Scan),
+}
+
+/// Way of hiding a message inside a PNG file
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Method {
+    Chunk,
+    Lsb,
+}
+
+impl FromStr for Method {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "chunk" => Ok(Self::Chunk),
+            "lsb" => Ok(Self::Lsb),
+            _ => Err(format!("unknown method {:?}, expected chunk or lsb", s)),
+        }
+    }
 }
 
 /// Encodes a message into a PNG file and saves the result
@@ -23,25 +61,80 @@ pub enum SubCommand {
 pub struct Encode {
     /// Path to png file
     pub(crate) file_path: PathBuf,
-    /// Chunk type is 4 ASCII letters
-    pub(crate) chunk_type: String,
+    /// Chunk type is 4 ASCII letters, omitted with lsb method
+    pub(crate) chunk_type: Option<String>,
     /// Just any text
     pub(crate) message: Option<String>,
+    /// Where to hide message: chunk or lsb (least significant bits of pixels)
+    #[clap(short, long, default_value = "chunk")]
+    pub(crate) method: Method,
     /// Save png file with added chunk to new file
     #[clap(short, long)]
     pub(crate) output_file: Option<PathBuf>,
+    /// Keep the replaced file with this suffix added to its name, e.g. .bak
+    #[clap(long)]
+    pub(crate) backup: Option<String>,
+    /// Where to insert chunk: before-iend, after-ihdr, before-idat or chunk index
+    #[clap(short, long, default_value = "before-iend")]
+    pub(crate) position: ChunkPosition,
+    /// Split message into a sequence of chunks with data of at most this many bytes
+    #[clap(long)]
+    pub(crate) max_chunk_size: Option<usize>,
+    /// Attach label to message to find it among other messages with the same chunk type
+    #[clap(long)]
+    pub(crate) label: Option<String>,
+    /// Hide file together with its name, content type, timestamp and checksum
+    #[clap(long, conflicts_with = "message")]
+    pub(crate) file: Option<PathBuf>,
+    /// Content type of hidden file, guessed from file extension by default
+    #[clap(long, requires = "file")]
+    pub(crate) content_type: Option<String>,
+    /// Compress message with deflate, zstd or brotli before encryption
+    #[clap(long)]
+    pub(crate) compress: Option<Algorithm>,
+    /// Compression level, default depends on algorithm
+    #[clap(long, requires = "compress")]
+    pub(crate) level: Option<u32>,
+    /// Encrypt message with a passphrase
+    #[clap(long)]
+    pub(crate) encrypt: bool,
+    /// Read passphrase from file instead of PNGME_PASSPHRASE variable or prompt
+    #[clap(long)]
+    pub(crate) passphrase_file: Option<PathBuf>,
 }
 
 /// Searches for a message hidden in a PNG file and prints the message if one is found
 #[derive(Clap)]
 pub struct Decode {
-    /// Path to png file
-    pub(crate) file_path: PathBuf,
-    /// Chunk type is 4 ASCII letters
-    pub(crate) chunk_type: String,
+    /// Paths to png files, glob patterns or directories
+    #[clap(required = true)]
+    pub(crate) paths: Vec<PathBuf>,
+    /// Chunk type is 4 ASCII letters, required with chunk method
+    #[clap(short = 't', long)]
+    pub(crate) chunk_type: Option<String>,
+    /// Process png files in directories and their subdirectories
+    #[clap(short, long)]
+    pub(crate) recursive: bool,
+    /// Where message is hidden: chunk or lsb (least significant bits of pixels)
+    #[clap(short, long, default_value = "chunk")]
+    pub(crate) method: Method,
+    #[clap(flatten)]
+    pub(crate) selector: MessageSelector,
     /// Set when data should go as raw unescaped bytes to stdout
     #[clap(long)]
     pub(crate) raw: bool,
+    /// Decrypt message encrypted with a passphrase
+    #[clap(long)]
+    pub(crate) decrypt: bool,
+    /// Restore hidden file into this directory
+    #[clap(long)]
+    pub(crate) extract: Option<PathBuf>,
+    /// Read as much as possible from a damaged file and report problems as warnings
+    #[clap(long)]
+    pub(crate) lenient: bool,
+    /// Read passphrase from file instead of PNGME_PASSPHRASE variable or prompt
+    #[clap(long)]
+    pub(crate) passphrase_file: Option<PathBuf>,
 }
 
 /// Removes a chunk from a PNG file and saves the result
@@ -54,6 +147,11 @@ pub struct Remove {
     /// Save png file with removed chunk to new file
     #[clap(short, long)]
     pub(crate) output_file: Option<PathBuf>,
+    /// Keep the replaced file with this suffix added to its name, e.g. .bak
+    #[clap(long)]
+    pub(crate) backup: Option<String>,
+    #[clap(flatten)]
+    pub(crate) selector: MessageSelector,
     /// Set when data should go as raw unescaped bytes to stdout
     #[clap(long)]
     pub(crate) raw: bool,
@@ -62,11 +160,245 @@ pub struct Remove {
 /// Prints all of the chunks in a PNG file
 #[derive(Clap)]
 pub struct Print {
-    /// Path to png file
-    pub(crate) file_path: PathBuf,
+    /// Paths to png files, glob patterns or directories
+    #[clap(required = true)]
+    pub(crate) paths: Vec<PathBuf>,
+    /// List messages stored in chunks of this type instead of chunks
+    #[clap(short = 't', long)]
+    pub(crate) chunk_type: Option<String>,
+    /// Process png files in directories and their subdirectories
+    #[clap(short, long)]
+    pub(crate) recursive: bool,
     /// Get detailed information about chunks
     #[clap(long)]
     pub(crate) verbose: bool,
+    /// Include chunk data encoded as base64 or hex
+    #[clap(long)]
+    pub(crate) data: Option<DataEncoding>,
+    /// Show byte ranges of chunk header, data and crc and bytes after IEND
+    #[clap(long)]
+    pub(crate) layout: bool,
+    /// Read as much as possible from a damaged file and report problems as warnings
+    #[clap(long)]
+    pub(crate) lenient: bool,
+    #[clap(flatten)]
+    pub(crate) selector: MessageSelector,
+}
+
+/// Chooses messages among all messages with the same chunk type
+#[derive(Clap)]
+pub struct MessageSelector {
+    /// Zero based index of message among messages with the same chunk type
+    #[clap(long)]
+    pub(crate) index: Option<usize>,
+    /// Take every matching message
+    #[clap(long, conflicts_with = "index")]
+    pub(crate) all: bool,
+    /// Take only messages with this label
+    #[clap(long)]
+    pub(crate) label: Option<String>,
+}
+
+impl MessageSelector {
+    pub(crate) fn is_empty(&self) -> bool {
+        self.index.is_none() && !self.all && self.label.is_none()
+    }
+}
+
+/// Checks chunk layout of a PNG file against the specification, fails if errors are found
+#[derive(Clap)]
+pub struct Validate {
+    /// Paths to png files, glob patterns or directories
+    #[clap(required = true)]
+    pub(crate) paths: Vec<PathBuf>,
+    /// Process png files in directories and their subdirectories
+    #[clap(short, long)]
+    pub(crate) recursive: bool,
+}
+
+/// Reads and edits standard textual chunks tEXt, zTXt and iTXt
+#[derive(Clap)]
+pub struct Text {
+    #[clap(subcommand)]
+    pub(crate) sub_cmd: TextSubCommand,
+}
+
+#[derive(Clap)]
+pub enum TextSubCommand {
+    Get(TextGet),
+    Set(TextSet),
+    List(TextList),
+    Remove(TextRemove),
+}
+
+/// Prints text stored under a keyword
+#[derive(Clap)]
+pub struct TextGet {
+    /// Path to png file
+    pub(crate) file_path: PathBuf,
+    /// Keyword of textual chunk, e.g. Comment or Author
+    pub(crate) keyword: String,
+}
+
+/// Stores text under a keyword replacing existing entries with the same keyword
+#[derive(Clap)]
+pub struct TextSet {
+    /// Path to png file
+    pub(crate) file_path: PathBuf,
+    /// Keyword of textual chunk, e.g. Comment or Author
+    pub(crate) keyword: String,
+    /// Text to store, read from stdin when omitted
+    pub(crate) text: Option<String>,
+    /// Compress text with zlib, stored as zTXt or compressed iTXt
+    #[clap(long)]
+    pub(crate) compressed: bool,
+    /// Store as UTF-8 iTXt even when text fits to Latin-1
+    #[clap(long)]
+    pub(crate) international: bool,
+    /// Language tag of the text, e.g. en-US, implies iTXt
+    #[clap(long)]
+    pub(crate) language: Option<String>,
+    /// Keyword translated to the language of the text, implies iTXt
+    #[clap(long)]
+    pub(crate) translated_keyword: Option<String>,
+    /// Save png file with added chunk to new file
+    #[clap(short, long)]
+    pub(crate) output_file: Option<PathBuf>,
+    /// Keep the replaced file with this suffix added to its name, e.g. .bak
+    #[clap(long)]
+    pub(crate) backup: Option<String>,
+}
+
+/// Lists all textual chunks
+#[derive(Clap)]
+pub struct TextList {
+    /// Path to png file
+    pub(crate) file_path: PathBuf,
+}
+
+/// Removes all textual chunks with a keyword
+#[derive(Clap)]
+pub struct TextRemove {
+    /// Path to png file
+    pub(crate) file_path: PathBuf,
+    /// Keyword of textual chunk, e.g. Comment or Author
+    pub(crate) keyword: String,
+    /// Save png file with removed chunks to new file
+    #[clap(short, long)]
+    pub(crate) output_file: Option<PathBuf>,
+    /// Keep the replaced file with this suffix added to its name, e.g. .bak
+    #[clap(long)]
+    pub(crate) backup: Option<String>,
+}
+
+/// Generates Ed25519 key pair for signing PNG files
+#[derive(Clap)]
+pub struct Keygen {
+    /// Path to secret key file, public key is saved next to it with .pub extension
+    pub(crate) key_file: PathBuf,
+}
+
+/// Signs critical chunks and chosen ancillary chunks of a PNG file
+#[derive(Clap)]
+pub struct Sign {
+    /// Path to png file
+    pub(crate) file_path: PathBuf,
+    /// Path to secret key file created by keygen
+    #[clap(short, long)]
+    pub(crate) key: PathBuf,
+    /// Ancillary chunk type to cover by signature, can be repeated
+    #[clap(long, number_of_values = 1)]
+    pub(crate) include: Vec<String>,
+    /// Cover every ancillary chunk type present in the file
+    #[clap(long, conflicts_with = "include")]
+    pub(crate) include_all: bool,
+    /// Save signed png file to new file
+    #[clap(short, long)]
+    pub(crate) output_file: Option<PathBuf>,
+    /// Keep the replaced file with this suffix added to its name, e.g. .bak
+    #[clap(long)]
+    pub(crate) backup: Option<String>,
+}
+
+/// Checks signature of a PNG file and lists chunks covered by it
+#[derive(Clap)]
+pub struct Verify {
+    /// Path to png file
+    pub(crate) file_path: PathBuf,
+    /// Path to public key file created by keygen
+    #[clap(short, long)]
+    pub(crate) key: PathBuf,
+}
+
+/// Compares chunks and image data of two PNG files
+#[derive(Clap)]
+pub struct Diff {
+    /// Path to original png file
+    pub(crate) old_file: PathBuf,
+    /// Path to changed png file
+    pub(crate) new_file: PathBuf,
+}
+
+/// Fixes wrong crcs and chunk layout of a damaged PNG file and reports every change
+#[derive(Clap)]
+pub struct Repair {
+    /// Path to png file
+    pub(crate) file_path: PathBuf,
+    /// Save repaired png file to new file
+    #[clap(short, long)]
+    pub(crate) output_file: Option<PathBuf>,
+    /// Keep the replaced file with this suffix added to its name, e.g. .bak
+    #[clap(long)]
+    pub(crate) backup: Option<String>,
+    /// Which wrong crcs to recompute: all, plausible (only when chunk data looks valid) or none
+    #[clap(long, default_value = "all")]
+    pub(crate) crc: CrcRepair,
+    /// What to do with chunks after IEND: rescue (move before IEND) or drop
+    #[clap(long, default_value = "rescue")]
+    pub(crate) after_iend: AfterIend,
+}
+
+/// Removes metadata and hidden chunks together with everything after IEND
+#[derive(Clap)]
+pub struct Strip {
+    /// Paths to png files, glob patterns or directories
+    #[clap(required = true)]
+    pub(crate) paths: Vec<PathBuf>,
+    /// Process png files in directories and their subdirectories
+    #[clap(short, long)]
+    pub(crate) recursive: bool,
+    /// Chunks to remove: ancillary, private or unsafe (not safe to copy), default is ancillary
+    #[clap(long)]
+    pub(crate) policy: Option<StripPolicy>,
+    /// Remove every chunk except image chunks and this chunk type, can be repeated
+    #[clap(long, number_of_values = 1, conflicts_with = "policy")]
+    pub(crate) keep: Vec<String>,
+    /// Only report what would be removed
+    #[clap(long)]
+    pub(crate) dry_run: bool,
+    /// Save stripped png file to new file, only with a single file
+    #[clap(short, long)]
+    pub(crate) output_file: Option<PathBuf>,
+    /// Keep the replaced file with this suffix added to its name, e.g. .bak
+    #[clap(long)]
+    pub(crate) backup: Option<String>,
+}
+
+/// Looks for data hidden in chunks, after IEND, in image data and in pixels
+#[derive(Clap)]
+pub struct Scan {
+    /// Paths to png files, glob patterns or directories
+    #[clap(required = true)]
+    pub(crate) paths: Vec<PathBuf>,
+    /// Process png files in directories and their subdirectories
+    #[clap(short, long)]
+    pub(crate) recursive: bool,
+    /// Report only findings with at least this score, from 0 to 1
+    #[clap(long, default_value = "0")]
+    pub(crate) min_score: f64,
+    /// Read as much as possible from a damaged file and report problems as warnings
+    #[clap(long)]
+    pub(crate) lenient: bool,
 }
 
 pub fn parse_cli() -> Opts {
@@ -0,0 +1,115 @@
+//! Runs a subcommand on many files given as paths, glob patterns or directories
+//!
+//! Files are processed in parallel, output of every file is kept until the file is done and
+//! printed in the order files were given. The subcommand fails when any of the files fails.
+use std::collections::HashSet;
+use std::path::{Path, PathBuf};
+
+use rayon::prelude::*;
+use walkdir::WalkDir;
+
+use crate::output::{CommandError, Output};
+
+/// Files a subcommand runs on
+pub(crate) struct Inputs {
+    files: Vec<PathBuf>,
+    /// Whether results are reported per file, set for patterns, directories and many paths
+    batch: bool,
+}
+
+impl Inputs {
+    /// Expands glob patterns and, with `recursive`, directories into PNG files inside them
+    pub(crate) fn expand(paths: &[PathBuf], recursive: bool) -> crate::Result<Self> {
+        let mut files = vec![];
+        let mut batch = paths.len() > 1;
+        for path in paths {
+            if path.is_dir() {
+                batch = true;
+                files.extend(walk(path, recursive)?);
+            } else if !path.exists() && is_pattern(path) {
+                batch = true;
+                let pattern = path.to_string_lossy();
+                let mut matched = 0;
+                for entry in glob::glob(&pattern)? {
+                    let entry = entry?;
+                    matched += 1;
+                    match entry.is_dir() {
+                        true if recursive => files.extend(walk(&entry, recursive)?),
+                        true => {}
+                        false => files.push(entry),
+                    }
+                }
+                if matched == 0 {
+                    let message = format!("pattern {:?} matched no files", pattern);
+                    return Err(CommandError::new("not_found", message).into());
+                }
+            } else {
+                files.push(path.clone());
+            }
+        }
+        let mut seen = HashSet::new();
+        files.retain(|file| seen.insert(file.clone()));
+        if files.is_empty() {
+            return Err(CommandError::new("not_found", "no PNG files found").into());
+        }
+        Ok(Self { files, batch })
+    }
+
+    pub(crate) fn is_batch(&self) -> bool {
+        self.batch
+    }
+
+    /// Runs `command` on every file, a single file writes straight to `out`
+    pub(crate) fn run<F>(&self, out: &mut Output, command: F) -> crate::Result<()>
+    where
+        F: Fn(&Path, &mut Output) -> crate::Result<()> + Sync,
+    {
+        if !self.batch {
+            return command(&self.files[0], out);
+        }
+        let format = out.format();
+        let results: Vec<_> = self
+            .files
+            .par_iter()
+            .map(|path| {
+                let mut file_out = Output::buffered(format);
+                let result = command(path, &mut file_out);
+                (file_out, result)
+            })
+            .collect();
+        let mut failed = 0;
+        for (path, (file_out, result)) in self.files.iter().zip(results) {
+            failed += result.is_err() as usize;
+            out.add_file(path, file_out, result.as_ref().err())?;
+        }
+        if failed > 0 {
+            let message = format!("{} of {} files failed", failed, self.files.len());
+            return Err(CommandError::new("failed_files", message).into());
+        }
+        Ok(())
+    }
+}
+
+fn is_pattern(path: &Path) -> bool {
+    path.to_string_lossy().contains(['*', '?', '['].as_ref())
+}
+
+/// PNG files inside directory sorted by name
+fn walk(directory: &Path, recursive: bool) -> crate::Result<Vec<PathBuf>> {
+    if !recursive {
+        let message = format!("{} is a directory, use --recursive", directory.display());
+        return Err(CommandError::new("invalid_input", message).into());
+    }
+    let mut files = vec![];
+    for entry in WalkDir::new(directory).sort_by_file_name() {
+        let entry = entry?;
+        let is_png = entry
+            .path()
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("png"));
+        if entry.file_type().is_file() && is_png {
+            files.push(entry.into_path());
+        }
+    }
+    Ok(files)
+}
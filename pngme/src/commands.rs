@@ -1,84 +1,1292 @@
+use std::convert::TryFrom;
+use std::fmt::Display;
 use std::fs;
 use std::io::{Read, Write};
+use std::ops::Range;
+use std::path::Path;
 use std::str::FromStr;
+use std::sync::Mutex;
+use std::time::{Duration, UNIX_EPOCH};
+
+use memmap2::Mmap;
+use serde_json::{json, Value};
 
 use crate::args;
-use pngme_lib::chunk::Chunk;
+use crate::batch::Inputs;
+use crate::output::{CommandError, DataEncoding, Output};
+use pngme_lib::chunk::{Chunk, ChunkLayout, ChunkRef};
 use pngme_lib::chunk_type::ChunkType;
+use pngme_lib::compression;
+use pngme_lib::crypto;
+use pngme_lib::detect::{self, FindingKind};
+use pngme_lib::diff::{self, ChunkChange, ImageDataChange};
+use pngme_lib::envelope::{self, Envelope};
+use pngme_lib::ihdr::Ihdr;
+use pngme_lib::lsb;
+use pngme_lib::message::{self, Message};
 use pngme_lib::png;
+use pngme_lib::repair::{self, RepairKind, RepairOptions};
+use pngme_lib::sequence;
+use pngme_lib::signature;
+use pngme_lib::strip::{self, StripPolicy};
+use pngme_lib::text::{CompressedTextChunk, InternationalTextChunk, TextChunk, TextualChunk};
+use pngme_lib::validate;
+
+/// Adds fields of object `fields` to object `record`
+fn with(mut record: Value, fields: Value) -> Value {
+    if let (Some(record), Value::Object(fields)) = (record.as_object_mut(), fields) {
+        record.extend(fields);
+    }
+    record
+}
 
-fn print_chunk_to_stdout(chunk: &Chunk, raw: bool) -> crate::Result<()> {
+fn print_chunk_to_stdout(
+    out: &mut Output,
+    chunk: impl Display,
+    record: Value,
+    data: &[u8],
+    raw: bool,
+) -> crate::Result<()> {
+    if raw && !out.is_text() {
+        return Err("raw data can be written only with text format".into());
+    }
     if raw {
-        let mut out = std::io::stdout();
-        out.write_all(chunk.data())?;
-        out.flush()?;
-    } else {
-        let data = match chunk.data_as_string() {
-            Ok(s) => format!("Data: {}", s),
-            Err(_) => "Could not parse data as UTF-8".to_string(),
+        out.write_raw(data)?;
+        return Ok(());
+    }
+    let text = std::str::from_utf8(data).ok();
+    let description = match text {
+        Some(s) => format!("{}\nData: {}", chunk, s),
+        None => format!("{}\nCould not parse data as UTF-8", chunk),
+    };
+    let fields = json!({
+        "length": data.len(),
+        "text": text,
+        "data": DataEncoding::Base64.encode(data),
+    });
+    out.record(description, with(record, fields));
+    Ok(())
+}
+
+/// Short description of a chunk used in records about other things
+fn chunk_summary(index: usize, chunk: &Chunk) -> Value {
+    json!({"index": index, "type": chunk.chunk_type().to_string(), "length": chunk.length()})
+}
+
+/// Takes message from argument or from stdin when argument is omitted
+fn read_message(message: Option<String>) -> crate::Result<Vec<u8>> {
+    let has_input_from_stdin = atty::isnt(atty::Stream::Stdin);
+    match (message, has_input_from_stdin) {
+        (None, _) => {
+            let mut buf = Vec::new();
+            std::io::stdin().lock().read_to_end(&mut buf)?;
+            Ok(buf)
+        }
+        (Some(string), false) => Ok(string.into_bytes()),
+        (Some(_), true) => {
+            Err("argument with data and stdin data are provided at same time".into())
+        }
+    }
+}
+
+/// Takes passphrase from file, PNGME_PASSPHRASE variable or interactive prompt in that order
+fn read_passphrase(file: Option<&Path>, confirm: bool) -> crate::Result<Vec<u8>> {
+    if let Some(path) = file {
+        let mut passphrase = fs::read(path)?;
+        while matches!(passphrase.last(), Some(b'\n') | Some(b'\r')) {
+            passphrase.pop();
+        }
+        return Ok(passphrase);
+    }
+    if let Some(passphrase) = std::env::var_os("PNGME_PASSPHRASE") {
+        let passphrase = passphrase
+            .into_string()
+            .or(Err("passphrase is not valid UTF-8"))?;
+        return Ok(passphrase.into_bytes());
+    }
+    let passphrase = rpassword::prompt_password("Passphrase: ")?;
+    if confirm && passphrase != rpassword::prompt_password("Repeat passphrase: ")? {
+        return Err("passphrases do not match".into());
+    }
+    Ok(passphrase.into_bytes())
+}
+
+fn map_file(path: &Path) -> crate::Result<Mmap> {
+    let file = fs::File::open(path)?;
+    // SAFETY: file is only read, concurrent modification by another process is not supported
+    Ok(unsafe { Mmap::map(&file)? })
+}
+
+/// Parses file contents, damaged files are parsed as far as possible when `lenient` is set
+fn parse_png<'a>(
+    bytes: &'a [u8],
+    lenient: bool,
+    out: &mut Output,
+) -> crate::Result<png::PngRef<'a>> {
+    let options = match lenient {
+        true => png::ParseOptions::lenient(),
+        false => png::ParseOptions::strict(),
+    };
+    let (image, warnings) = png::PngRef::parse(bytes, options)?;
+    for warning in warnings {
+        let kind = match warning {
+            png::ParseWarning::CrcMismatch { .. } => "crc_mismatch",
+            png::ParseWarning::SkippedBytes { .. } => "skipped_bytes",
+            png::ParseWarning::Truncated { .. } => "truncated",
         };
-        println!("{}\n{}", chunk, data);
+        let record = json!({
+            "code": kind,
+            "offset": warning.offset(),
+            "message": warning.to_string(),
+        });
+        out.warning(format_args!("Warning: {}", warning), record);
     }
+    Ok(image)
+}
+
+/// Replaces file at `path` atomically, keeping the old one with `backup` suffix when given
+fn write_png_file(image: &png::Png, path: &Path, backup: Option<&str>) -> crate::Result<()> {
+    let options = match backup {
+        Some(suffix) => png::WriteOptions::default().backup(suffix),
+        None => png::WriteOptions::default(),
+    };
+    image.write_to_path_with_options(path, &options)?;
     Ok(())
 }
 
-pub fn print(cmd: args::Print) -> crate::Result<()> {
-    let image = png::Png::from_file(&cmd.file_path)?;
+fn print_ihdr(out: &mut Output, data: &[u8], indent: &str) {
+    let ihdr = match Ihdr::from_data(data) {
+        Ok(ihdr) => ihdr,
+        Err(e) => return out.line(format_args!("{}could not decode header: {}", indent, e)),
+    };
+    let color_type = ihdr.color_type();
+    out.line(format_args!("{}width: {}", indent, ihdr.width()));
+    out.line(format_args!("{}height: {}", indent, ihdr.height()));
+    out.line(format_args!("{}bit depth: {}", indent, ihdr.bit_depth()));
+    out.line(format_args!(
+        "{}color type: {} ({})",
+        indent,
+        color_type.code(),
+        color_type
+    ));
+    out.line(format_args!(
+        "{}compression method: {}",
+        indent,
+        ihdr.compression()
+    ));
+    out.line(format_args!("{}filter method: {}", indent, ihdr.filter()));
+    let interlace = if ihdr.is_interlaced() {
+        "Adam7"
+    } else {
+        "none"
+    };
+    out.line(format_args!(
+        "{}interlace method: {} ({})",
+        indent,
+        ihdr.interlace(),
+        interlace
+    ));
+}
+
+fn ihdr_record(data: &[u8]) -> Value {
+    match Ihdr::from_data(data) {
+        Ok(ihdr) => json!({
+            "width": ihdr.width(),
+            "height": ihdr.height(),
+            "bit_depth": ihdr.bit_depth(),
+            "color_type": ihdr.color_type().code(),
+            "compression_method": ihdr.compression(),
+            "filter_method": ihdr.filter(),
+            "interlace_method": ihdr.interlace(),
+        }),
+        Err(e) => json!({ "error": e.to_string() }),
+    }
+}
+
+fn chunk_record(
+    index: usize,
+    chunk: &ChunkRef,
+    data: Option<DataEncoding>,
+    layout: bool,
+    verbose: bool,
+) -> Value {
+    let chunk_type = chunk.chunk_type();
+    let mut record = json!({
+        "index": index,
+        "offset": chunk.source_offset(),
+        "type": chunk_type.to_string(),
+        "critical": chunk_type.is_critical(),
+        "public": chunk_type.is_public(),
+        "reserved_bit_valid": chunk_type.is_reserved_bit_valid(),
+        "safe_to_copy": chunk_type.is_safe_to_copy(),
+        "length": chunk.length(),
+        "crc": chunk.crc(),
+    });
+    if let Some(encoding) = data {
+        record = with(record, json!({ "data": encoding.encode(chunk.data()) }));
+    }
+    if chunk.chunk_type_bytes() == *b"IHDR" {
+        record = with(record, json!({ "header": ihdr_record(chunk.data()) }));
+    }
+    if verbose {
+        let spec = chunk_type.spec().map(|spec| {
+            json!({
+                "name": spec.name(),
+                "description": spec.description(),
+                "placement": spec.placement().to_string(),
+                "multiplicity": spec.multiplicity().to_string(),
+            })
+        });
+        record = with(record, json!({ "spec": spec }));
+    }
+    if let Some(layout) = chunk.layout().filter(|_| layout) {
+        let range = |range: Range<u64>| json!([range.start, range.end]);
+        let layout = json!({
+            "header": range(layout.header()),
+            "data": range(layout.data()),
+            "crc": range(layout.crc()),
+        });
+        record = with(record, json!({ "layout": layout }));
+    }
+    record
+}
+
+/// Describes byte ranges of chunk header, data and crc in file
+fn describe_layout(layout: &ChunkLayout) -> String {
+    let range = |range: Range<u64>| format!("{:#x}..{:#x}", range.start, range.end);
+    format!(
+        "header {}, data {}, crc {}",
+        range(layout.header()),
+        range(layout.data()),
+        range(layout.crc())
+    )
+}
+
+pub fn print(cmd: args::Print, out: &mut Output) -> crate::Result<()> {
+    let inputs = Inputs::expand(&cmd.paths, cmd.recursive)?;
+    inputs.run(out, |path, out| print_file(&cmd, path, out))
+}
+
+fn print_file(cmd: &args::Print, file_path: &Path, out: &mut Output) -> crate::Result<()> {
+    let file = map_file(file_path)?;
+    let image = parse_png(&file, cmd.lenient, out)?;
+    if let Some(chunk_type) = cmd.chunk_type.as_deref() {
+        let chunk_type = ChunkType::from_str(chunk_type)?;
+        return print_messages(&image, &chunk_type, &cmd.selector, out);
+    }
+    if !cmd.selector.is_empty() {
+        return Err("chunk type is required to select messages".into());
+    }
     let indent = " ".repeat(4);
     for (i, chunk) in image.chunks().iter().enumerate() {
-        println!("[{}] {}", i + 1, chunk);
+        out.emit(chunk_record(i, chunk, cmd.data, cmd.layout, cmd.verbose));
+        if !out.is_text() {
+            continue;
+        }
+        match chunk.layout().filter(|_| cmd.layout) {
+            Some(layout) => out.line(format_args!(
+                "[{}] {}: {}",
+                i + 1,
+                chunk,
+                describe_layout(&layout)
+            )),
+            None => out.line(format_args!("[{}] {}", i + 1, chunk)),
+        }
         if cmd.verbose {
             let chunk_type = chunk.chunk_type();
             let is_reserved_bit_valid = chunk_type.is_reserved_bit_valid();
             let is_safe_to_copy = chunk_type.is_safe_to_copy();
-            println!("{}is critical: {}", indent, chunk_type.is_critical());
-            println!("{}is public: {}", indent, chunk_type.is_public());
-            println!("{}has valid reserve bit: {}", indent, is_reserved_bit_valid);
-            println!("{}is safe to copy: {}", indent, is_safe_to_copy);
-            println!("{}crc as dec: {}", indent, chunk.crc());
-            println!("{}crc as hex: {:x}", indent, chunk.crc());
+            match chunk_type.spec() {
+                Some(spec) => {
+                    out.line(format_args!(
+                        "{}{}: {}",
+                        indent,
+                        spec.name(),
+                        spec.description()
+                    ));
+                    out.line(format_args!("{}placement: {}", indent, spec.placement()));
+                    out.line(format_args!(
+                        "{}multiplicity: {}",
+                        indent,
+                        spec.multiplicity()
+                    ));
+                }
+                None => out.line(format_args!("{}not a registered chunk type", indent)),
+            }
+            out.line(format_args!(
+                "{}is critical: {}",
+                indent,
+                chunk_type.is_critical()
+            ));
+            out.line(format_args!(
+                "{}is public: {}",
+                indent,
+                chunk_type.is_public()
+            ));
+            out.line(format_args!(
+                "{}has valid reserve bit: {}",
+                indent, is_reserved_bit_valid
+            ));
+            out.line(format_args!(
+                "{}is safe to copy: {}",
+                indent, is_safe_to_copy
+            ));
+            out.line(format_args!("{}crc as dec: {}", indent, chunk.crc()));
+            out.line(format_args!("{}crc as hex: {:x}", indent, chunk.crc()));
+            if chunk.chunk_type_bytes() == *b"IHDR" {
+                print_ihdr(out, chunk.data(), &indent);
+            }
+        }
+        if let Some(encoding) = cmd.data {
+            let data = encoding.encode(chunk.data());
+            out.line(format_args!("{}data as {}: {}", indent, encoding, data));
         }
     }
+    let trailing = image.trailing_data();
+    if cmd.layout && !trailing.is_empty() {
+        let start = image.trailing_offset();
+        let end = start + trailing.len() as u64;
+        out.record(
+            format_args!(
+                "trailing data {:#x}..{:#x} len:{}",
+                start,
+                end,
+                trailing.len()
+            ),
+            json!({"trailing": {"offset": start, "length": trailing.len()}}),
+        );
+    }
     Ok(())
 }
 
-pub fn decode(cmd: args::Decode) -> crate::Result<()> {
-    let image = png::Png::from_file(&cmd.file_path)?;
-    let chunk_type = ChunkType::from_str(&cmd.chunk_type)?;
-    let chunk = image
-        .chunk_by_type(&chunk_type)
-        .ok_or(format!("Chunk with type {:?} not found", &cmd.chunk_type))?;
-    print_chunk_to_stdout(chunk, cmd.raw)?;
+/// Picks messages matching label and index, first one or all of them when nothing is given
+fn select_messages(
+    messages: Vec<Message>,
+    selector: &args::MessageSelector,
+    all_by_default: bool,
+    chunk_type: &ChunkType,
+) -> crate::Result<Vec<(usize, Message)>> {
+    if messages.is_empty() {
+        let message = format!("Chunk with type {:?} not found", chunk_type.to_string());
+        return Err(CommandError::new("not_found", message).into());
+    }
+    let matching = messages
+        .into_iter()
+        .enumerate()
+        .filter(|(index, _)| selector.index.is_none_or(|wanted| wanted == *index))
+        .filter(|(_, message)| {
+            selector
+                .label
+                .as_ref()
+                .is_none_or(|label| message.label() == Some(label))
+        });
+    let selected: Vec<_> = match selector.all || selector.index.is_some() || all_by_default {
+        true => matching.collect(),
+        false => matching.take(1).collect(),
+    };
+    if selected.is_empty() {
+        let message = "No message matches given index and label";
+        return Err(CommandError::new("not_found", message).into());
+    }
+    Ok(selected)
+}
+
+/// Describes message the same way as a chunk when it is a single chunk without label
+fn describe_message(chunk_type: &ChunkType, message: &Message, chunk_length: usize) -> String {
+    let description = match message.chunk_indexes().len() {
+        1 => format!("Chunk \"{}\" len:{}", chunk_type, chunk_length),
+        parts => format!("Chunk sequence \"{}\" parts:{}", chunk_type, parts),
+    };
+    match message.label() {
+        Some(label) => format!("{} label:{:?}", description, label),
+        None => description,
+    }
+}
+
+/// Identifies message among messages with the same chunk type
+fn message_record(chunk_type: &ChunkType, index: usize, message: &Message) -> Value {
+    json!({
+        "chunk_type": chunk_type.to_string(),
+        "index": index,
+        "label": message.label(),
+        "chunks": message.chunk_indexes(),
+    })
+}
+
+fn print_messages(
+    image: &png::PngRef,
+    chunk_type: &ChunkType,
+    selector: &args::MessageSelector,
+    out: &mut Output,
+) -> crate::Result<()> {
+    let messages = select_messages(image.messages(chunk_type), selector, true, chunk_type)?;
+    let indent = " ".repeat(4);
+    for (index, message) in messages.iter() {
+        let record = message_record(chunk_type, *index, message);
+        let fields = match message.data() {
+            Ok(data) => json!({ "length": data.len() }),
+            Err(e) => json!({ "error": e.to_string() }),
+        };
+        out.emit(with(record, fields));
+        if !out.is_text() {
+            continue;
+        }
+        let chunk_length = image.chunks()[message.chunk_indexes()[0]].length() as usize;
+        out.line(format_args!(
+            "[{}] {}",
+            index,
+            describe_message(chunk_type, message, chunk_length)
+        ));
+        let chunks: Vec<String> = message
+            .chunk_indexes()
+            .iter()
+            .map(|i| (i + 1).to_string())
+            .collect();
+        out.line(format_args!("{}chunks: {}", indent, chunks.join(", ")));
+        match message.data() {
+            Ok(data) => out.line(format_args!("{}data length: {}", indent, data.len())),
+            Err(e) => out.line(format_args!("{}could not read message: {}", indent, e)),
+        }
+    }
     Ok(())
 }
 
-pub fn remove(cmd: args::Remove) -> crate::Result<()> {
+pub fn decode(cmd: args::Decode, out: &mut Output) -> crate::Result<()> {
+    let inputs = Inputs::expand(&cmd.paths, cmd.recursive)?;
+    let passphrase = Passphrase::new(cmd.passphrase_file.as_deref());
+    inputs.run(out, |path, out| match cmd.method {
+        args::Method::Chunk => decode_file(&cmd, path, &passphrase, out),
+        args::Method::Lsb => decode_lsb(&cmd, path, &passphrase, out),
+    })
+}
+
+fn decode_file(
+    cmd: &args::Decode,
+    file_path: &Path,
+    passphrase: &Passphrase,
+    out: &mut Output,
+) -> crate::Result<()> {
+    let file = map_file(file_path)?;
+    let image = parse_png(&file, cmd.lenient, out)?;
+    let chunk_type = cmd
+        .chunk_type
+        .as_deref()
+        .ok_or("--chunk-type is required with chunk method")?;
+    let chunk_type = ChunkType::from_str(chunk_type)?;
+    let messages = image.messages(&chunk_type);
+    let messages = select_messages(messages, &cmd.selector, false, &chunk_type)?;
+    for (index, message) in messages.into_iter() {
+        let chunk_length = image.chunks()[message.chunk_indexes()[0]].length() as usize;
+        let mut description = describe_message(&chunk_type, &message, chunk_length);
+        if cmd.selector.all {
+            description = format!("[{}] {}", index, description);
+        }
+        let record = message_record(&chunk_type, index, &message);
+        let data = message.into_data()?;
+        output_payload(out, description, record, data, cmd, passphrase)?;
+    }
+    Ok(())
+}
+
+/// Passphrase for decoding, read on first use so it is asked at most once for all files
+struct Passphrase<'a> {
+    file: Option<&'a Path>,
+    passphrase: Mutex<Option<Vec<u8>>>,
+}
+
+impl<'a> Passphrase<'a> {
+    fn new(file: Option<&'a Path>) -> Self {
+        Passphrase {
+            file,
+            passphrase: Mutex::new(None),
+        }
+    }
+
+    fn get(&self) -> crate::Result<Vec<u8>> {
+        let mut passphrase = self
+            .passphrase
+            .lock()
+            .expect("passphrase lock is not poisoned");
+        if passphrase.is_none() {
+            *passphrase = Some(read_passphrase(self.file, false)?);
+        }
+        Ok(passphrase.clone().expect("passphrase is read above"))
+    }
+}
+
+/// Prints decoded payload or extracts file from its envelope
+fn output_payload(
+    out: &mut Output,
+    description: String,
+    record: Value,
+    data: Vec<u8>,
+    cmd: &args::Decode,
+    passphrase: &Passphrase,
+) -> crate::Result<()> {
+    if !envelope::is_envelope(&data) {
+        if cmd.extract.is_some() {
+            let message = format!("{} does not contain a file", description);
+            return Err(CommandError::new("not_found", message).into());
+        }
+        let mut data = match cmd.decrypt {
+            true => crypto::decrypt(&data, &passphrase.get()?)?,
+            false => data,
+        };
+        if compression::is_compressed(&data) {
+            data = compression::decompress(&data)?;
+        }
+        return print_chunk_to_stdout(out, description, record, &data, cmd.raw);
+    }
+    let envelope = Envelope::try_from(&data[..])?;
+    let content = match envelope.is_encrypted() {
+        true => envelope.open(Some(&passphrase.get()?))?,
+        false => envelope.open(None)?,
+    };
+    let record = with(
+        record,
+        json!({
+            "file": {
+                "filename": envelope.filename(),
+                "content_type": envelope.content_type(),
+                "timestamp": envelope.timestamp(),
+            }
+        }),
+    );
+    match cmd.extract.as_deref() {
+        Some(directory) => extract_file(out, directory, record, &envelope, &content),
+        None => {
+            let description = format!("{} file:{:?}", description, envelope.filename());
+            print_chunk_to_stdout(out, description, record, &content, cmd.raw)
+        }
+    }
+}
+
+fn extract_file(
+    out: &mut Output,
+    directory: &Path,
+    record: Value,
+    envelope: &Envelope,
+    content: &[u8],
+) -> crate::Result<()> {
+    let filename = envelope.safe_filename().ok_or(format!(
+        "unsafe filename {:?} in envelope",
+        envelope.filename()
+    ))?;
+    let path = directory.join(filename);
+    if path.exists() {
+        let message = format!("{} already exists", path.display());
+        return Err(CommandError::new("already_exists", message).into());
+    }
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(&path)?;
+    file.write_all(content)?;
+    // timestamps beyond what the platform can represent are left to the current time
+    if let Some(modified) = UNIX_EPOCH.checked_add(Duration::from_secs(envelope.timestamp())) {
+        file.set_modified(modified)?;
+    }
+    let description = format!(
+        "Extracted {} ({}, {} bytes)",
+        path.display(),
+        envelope.content_type(),
+        content.len()
+    );
+    let fields = json!({"extracted": path.display().to_string(), "length": content.len()});
+    out.record(description, with(record, fields));
+    Ok(())
+}
+
+fn decode_lsb(
+    cmd: &args::Decode,
+    file_path: &Path,
+    passphrase: &Passphrase,
+    out: &mut Output,
+) -> crate::Result<()> {
+    if cmd.chunk_type.is_some() {
+        return Err("chunk type can not be used with lsb method".into());
+    }
+    if !cmd.selector.is_empty() {
+        return Err("image pixels hold only one message".into());
+    }
+    let file = map_file(file_path)?;
+    let image = parse_png(&file, cmd.lenient, out)?.to_png();
+    let data = lsb::extract(&image)?;
+    let description = format!("Pixels len:{}", data.len());
+    let record = json!({ "method": "lsb" });
+    output_payload(out, description, record, data, cmd, passphrase)
+}
+
+pub fn remove(cmd: args::Remove, out: &mut Output) -> crate::Result<()> {
     let mut image = png::Png::from_file(&cmd.file_path)?;
     let chunk_type = ChunkType::from_str(&cmd.chunk_type)?;
-    let chunk = image.remove_chunk(&chunk_type)?;
-    print_chunk_to_stdout(&chunk, cmd.raw)?;
+    let messages = image.messages(&chunk_type);
+    let messages = select_messages(messages, &cmd.selector, false, &chunk_type)?;
+    for (index, message) in messages.iter() {
+        let chunk_length = image.chunks()[message.chunk_indexes()[0]].length() as usize;
+        let mut description = describe_message(&chunk_type, message, chunk_length);
+        if cmd.selector.all {
+            description = format!("[{}] {}", index, description);
+        }
+        let record = message_record(&chunk_type, *index, message);
+        match message.data() {
+            Ok(data) => print_chunk_to_stdout(out, description, record, data, cmd.raw)?,
+            Err(e) => {
+                let text = format!("{}, removed anyway: {}", description, e);
+                out.warning(text, with(record, json!({ "error": e.to_string() })));
+            }
+        }
+    }
+    let indexes: Vec<usize> = messages
+        .iter()
+        .flat_map(|(_, message)| message.chunk_indexes())
+        .copied()
+        .collect();
+    image.remove_chunks_at(&indexes);
     let output_path = cmd.output_file.unwrap_or(cmd.file_path);
-    fs::write(output_path, image.as_bytes())?;
+    write_png_file(&image, &output_path, cmd.backup.as_deref())?;
     Ok(())
 }
 
-pub fn encode(cmd: args::Encode) -> crate::Result<()> {
+fn read_envelope(path: &Path, content_type: Option<&str>) -> crate::Result<Envelope> {
+    let filename = path
+        .file_name()
+        .ok_or(format!("{:?} is not a file", path))?
+        .to_string_lossy();
+    let content_type = content_type.unwrap_or_else(|| envelope::guess_content_type(&filename));
+    let metadata = fs::metadata(path)?;
+    let timestamp = metadata
+        .modified()?
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |duration| duration.as_secs());
+    let content = fs::read(path)?;
+    Ok(Envelope::new(&filename, content_type, timestamp, content)?)
+}
+
+pub fn encode(cmd: args::Encode, out: &mut Output) -> crate::Result<()> {
     let mut image = png::Png::from_file(&cmd.file_path)?;
-    let chunk_type = ChunkType::from_str(&cmd.chunk_type)?;
-    let has_input_from_stdin = atty::isnt(atty::Stream::Stdin);
-    let buf: Vec<u8> = match (cmd.message, has_input_from_stdin) {
-        (None, _) => {
-            let mut buf = Vec::new();
-            std::io::stdin().lock().read_to_end(&mut buf)?;
-            buf
+    // With lsb method there is no chunk type so the only positional argument is the message
+    let (chunk_type, message) = match (cmd.method, cmd.chunk_type, cmd.message) {
+        (args::Method::Lsb, Some(_), Some(_)) => {
+            return Err("chunk type can not be used with lsb method".into())
         }
-        (Some(string), false) => string.as_bytes().iter().copied().collect(),
-        (Some(_), true) => {
-            return Err("argument with data and stdin data are provided at same time".into());
+        (args::Method::Lsb, message, None) => (None, message),
+        (args::Method::Chunk, Some(chunk_type), message) => {
+            (Some(ChunkType::from_str(&chunk_type)?), message)
         }
+        _ => return Err("chunk type is required".into()),
     };
-    image.append_chunk(Chunk::new(chunk_type, &buf));
+    let mut buf = match cmd.file.as_deref() {
+        Some(path) => {
+            let mut envelope = read_envelope(path, cmd.content_type.as_deref())?;
+            if let Some(algorithm) = cmd.compress {
+                envelope = envelope.compress(algorithm, cmd.level)?;
+            }
+            if cmd.encrypt {
+                let passphrase = read_passphrase(cmd.passphrase_file.as_deref(), true)?;
+                envelope = envelope.encrypt(&passphrase)?;
+            }
+            envelope.as_bytes()
+        }
+        None => {
+            let message = read_message(message)?;
+            match cmd.compress {
+                Some(algorithm) => compression::compress(&message, algorithm, cmd.level)?,
+                None => message,
+            }
+        }
+    };
+    if cmd.encrypt && cmd.file.is_none() {
+        let passphrase = read_passphrase(cmd.passphrase_file.as_deref(), true)?;
+        buf = crypto::encrypt(&buf, &passphrase)?;
+    }
+    if let Some(label) = cmd.label.as_deref() {
+        buf = message::add_label(label, &buf)?;
+    }
+    let mut record = json!({ "length": buf.len() });
+    match chunk_type {
+        Some(chunk_type) => {
+            record = with(record, json!({ "chunk_type": chunk_type.to_string() }));
+            let chunks = match cmd.max_chunk_size {
+                Some(max_chunk_size) => sequence::split(&chunk_type, &buf, max_chunk_size)?,
+                None if buf.len() > Chunk::MAX_LENGTH => {
+                    sequence::split(&chunk_type, &buf, Chunk::MAX_LENGTH)?
+                }
+                None => vec![Chunk::try_new(chunk_type, &buf)?],
+            };
+            image.insert_chunks(chunks, cmd.position)?
+        }
+        None if cmd.max_chunk_size.is_some() => {
+            return Err("max chunk size can not be used with lsb method".into())
+        }
+        None => lsb::embed(&mut image, &buf)?,
+    }
+    let output_path = cmd.output_file.unwrap_or(cmd.file_path);
+    write_png_file(&image, &output_path, cmd.backup.as_deref())?;
+    out.emit(with(
+        record,
+        json!({ "output": output_path.display().to_string() }),
+    ));
+    Ok(())
+}
+
+pub fn validate(cmd: args::Validate, out: &mut Output) -> crate::Result<()> {
+    let inputs = Inputs::expand(&cmd.paths, cmd.recursive)?;
+    inputs.run(out, validate_file)
+}
+
+fn validate_file(file_path: &Path, out: &mut Output) -> crate::Result<()> {
+    let image = png::Png::from_file(file_path)?;
+    let diagnostics = validate::validate(&image);
+    for diagnostic in diagnostics.iter() {
+        let record = json!({
+            "severity": diagnostic.severity().to_string(),
+            "chunk_index": diagnostic.chunk_index(),
+            "message": diagnostic.kind().to_string(),
+        });
+        out.record(diagnostic, record);
+    }
+    let errors = diagnostics.iter().filter(|d| d.is_error()).count();
+    let warnings = diagnostics.len() - errors;
+    if out.is_text() {
+        out.line(format_args!("{} errors, {} warnings", errors, warnings));
+    }
+    if errors > 0 {
+        let message = format!("{:?} is not a valid PNG file", file_path);
+        return Err(CommandError::new("invalid_png", message).into());
+    }
+    Ok(())
+}
+
+pub fn text(cmd: args::Text, out: &mut Output) -> crate::Result<()> {
+    match cmd.sub_cmd {
+        args::TextSubCommand::Get(cmd) => text_get(cmd, out),
+        args::TextSubCommand::Set(cmd) => text_set(cmd, out),
+        args::TextSubCommand::List(cmd) => text_list(cmd, out),
+        args::TextSubCommand::Remove(cmd) => text_remove(cmd, out),
+    }
+}
+
+fn text_entries(image: &png::Png, out: &mut Output) -> Vec<TextualChunk> {
+    let mut entries = vec![];
+    for chunk in image.chunks() {
+        if !TextualChunk::is_textual(chunk) {
+            continue;
+        }
+        match TextualChunk::try_from(chunk) {
+            Ok(entry) => entries.push(entry),
+            Err(e) => {
+                let record = json!({
+                    "code": "invalid_text",
+                    "type": chunk.chunk_type().to_string(),
+                    "message": e.to_string(),
+                });
+                let text = format!("Skipping invalid {} chunk: {}", chunk.chunk_type(), e);
+                out.warning(text, record);
+            }
+        }
+    }
+    entries
+}
+
+fn has_keyword(chunk: &Chunk, keyword: &str) -> bool {
+    TextualChunk::is_textual(chunk)
+        && TextualChunk::try_from(chunk).is_ok_and(|entry| entry.keyword() == keyword)
+}
+
+fn text_not_found(keyword: &str) -> crate::Error {
+    let message = format!("Text with keyword {:?} not found", keyword);
+    CommandError::new("not_found", message).into()
+}
+
+fn text_get(cmd: args::TextGet, out: &mut Output) -> crate::Result<()> {
+    let image = png::Png::from_file(&cmd.file_path)?;
+    let mut found = false;
+    let entries = text_entries(&image, out);
+    for entry in entries
+        .iter()
+        .filter(|entry| entry.keyword() == cmd.keyword)
+    {
+        let record = json!({"keyword": entry.keyword(), "text": entry.text()});
+        out.record(entry.text(), record);
+        found = true;
+    }
+    match found {
+        true => Ok(()),
+        false => Err(text_not_found(&cmd.keyword)),
+    }
+}
+
+fn text_set(cmd: args::TextSet, out: &mut Output) -> crate::Result<()> {
+    let mut image = png::Png::from_file(&cmd.file_path)?;
+    let text = String::from_utf8(read_message(cmd.text)?)?;
+    let is_latin1 = text.chars().all(|ch| u32::from(ch) <= 0xFF);
+    let is_international = cmd.international
+        || !is_latin1
+        || cmd.language.is_some()
+        || cmd.translated_keyword.is_some();
+    let entry = match (is_international, cmd.compressed) {
+        (true, compressed) => TextualChunk::International(InternationalTextChunk::new(
+            &cmd.keyword,
+            &text,
+            cmd.language.as_deref().unwrap_or(""),
+            cmd.translated_keyword.as_deref().unwrap_or(""),
+            compressed,
+        )?),
+        (false, true) => TextualChunk::Compressed(CompressedTextChunk::new(&cmd.keyword, &text)?),
+        (false, false) => TextualChunk::Text(TextChunk::new(&cmd.keyword, &text)?),
+    };
+    let keyword = &cmd.keyword;
+    image.retain_chunks(|chunk| !has_keyword(chunk, keyword));
+    let chunk = entry.to_chunk()?;
+    let record = json!({"type": chunk.chunk_type().to_string(), "keyword": keyword});
+    image.insert_chunk_before_iend(chunk)?;
     let output_path = cmd.output_file.unwrap_or(cmd.file_path);
-    fs::write(output_path, image.as_bytes())?;
+    write_png_file(&image, &output_path, cmd.backup.as_deref())?;
+    out.emit(with(
+        record,
+        json!({ "output": output_path.display().to_string() }),
+    ));
+    Ok(())
+}
+
+fn text_list(cmd: args::TextList, out: &mut Output) -> crate::Result<()> {
+    let image = png::Png::from_file(&cmd.file_path)?;
+    for entry in text_entries(&image, out) {
+        let record = json!({
+            "type": entry.chunk_type(),
+            "keyword": entry.keyword(),
+            "text": entry.text(),
+        });
+        match &entry {
+            TextualChunk::Text(_) => {
+                let line = format!("tEXt {}: {}", entry.keyword(), entry.text());
+                out.record(line, record)
+            }
+            TextualChunk::Compressed(_) => {
+                let line = format!("zTXt {}: {}", entry.keyword(), entry.text());
+                out.record(line, record)
+            }
+            TextualChunk::International(itxt) => {
+                let line = format!(
+                    "iTXt {} [{}] {}: {}",
+                    entry.keyword(),
+                    itxt.language_tag(),
+                    itxt.translated_keyword(),
+                    entry.text()
+                );
+                let fields = json!({
+                    "language": itxt.language_tag(),
+                    "translated_keyword": itxt.translated_keyword(),
+                });
+                out.record(line, with(record, fields))
+            }
+        }
+    }
+    Ok(())
+}
+
+fn text_remove(cmd: args::TextRemove, out: &mut Output) -> crate::Result<()> {
+    let mut image = png::Png::from_file(&cmd.file_path)?;
+    let removed = image.retain_chunks(|chunk| !has_keyword(chunk, &cmd.keyword));
+    if removed.is_empty() {
+        return Err(text_not_found(&cmd.keyword));
+    }
+    for chunk in removed.iter() {
+        let record = json!({"type": chunk.chunk_type().to_string(), "length": chunk.length()});
+        out.record(format!("Removed {}", chunk), record);
+    }
+    let output_path = cmd.output_file.unwrap_or(cmd.file_path);
+    write_png_file(&image, &output_path, cmd.backup.as_deref())
+}
+
+/// Creates file that must not exist yet, readable only by the owner on Unix
+fn create_private_file(path: &Path) -> std::io::Result<fs::File> {
+    let mut options = fs::OpenOptions::new();
+    options.write(true).create_new(true);
+    #[cfg(unix)]
+    std::os::unix::fs::OpenOptionsExt::mode(&mut options, 0o600);
+    options.open(path)
+}
+
+pub fn keygen(cmd: args::Keygen, out: &mut Output) -> crate::Result<()> {
+    let mut public_path = cmd.key_file.clone().into_os_string();
+    public_path.push(".pub");
+    let public_path = Path::new(&public_path);
+    if public_path.exists() {
+        let message = format!("{} already exists", public_path.display());
+        return Err(CommandError::new("already_exists", message).into());
+    }
+    let key = signature::generate_key()?;
+    let public = key.verifying_key();
+    let mut file = create_private_file(&cmd.key_file)?;
+    writeln!(file, "{}", signature::to_hex(key.as_bytes()))?;
+    let mut file = fs::OpenOptions::new()
+        .write(true)
+        .create_new(true)
+        .open(public_path)?;
+    writeln!(file, "{}", signature::to_hex(public.as_bytes()))?;
+    let fingerprint = signature::to_hex(&signature::fingerprint(&public));
+    let text = format!(
+        "Secret key: {}\nPublic key: {}\nFingerprint: {}",
+        cmd.key_file.display(),
+        public_path.display(),
+        fingerprint
+    );
+    let record = json!({
+        "secret_key": cmd.key_file.display().to_string(),
+        "public_key": public_path.display().to_string(),
+        "fingerprint": fingerprint,
+    });
+    out.record(text, record);
+    Ok(())
+}
+
+pub fn sign(cmd: args::Sign, out: &mut Output) -> crate::Result<()> {
+    let mut image = png::Png::from_file(&cmd.file_path)?;
+    let key = signature::signing_key_from_hex(&fs::read_to_string(&cmd.key)?)?;
+    let mut ancillary = Vec::new();
+    for chunk_type in cmd.include.iter() {
+        ancillary.push(ChunkType::from_str(chunk_type)?);
+    }
+    if cmd.include_all {
+        let present = image
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type())
+            .filter(|chunk_type| !chunk_type.is_critical())
+            .filter(|chunk_type| chunk_type.bytes() != signature::CHUNK_TYPE);
+        for chunk_type in present {
+            if !ancillary.contains(chunk_type) {
+                ancillary.push(chunk_type.clone());
+            }
+        }
+    }
+    let signed = signature::sign(&mut image, &key, &ancillary)?;
+    let covered = signed.covered_chunks(&image);
+    let output_path = cmd.output_file.unwrap_or(cmd.file_path);
+    write_png_file(&image, &output_path, cmd.backup.as_deref())?;
+    let covered_types: Vec<String> = signed
+        .covered_types()
+        .iter()
+        .map(|t| t.to_string())
+        .collect();
+    let record = json!({
+        "output": output_path.display().to_string(),
+        "fingerprint": signature::to_hex(signed.fingerprint()),
+        "covered_types": covered_types,
+        "covered": covered,
+    });
+    out.record(format!("Signed {} chunks", covered.len()), record);
+    Ok(())
+}
+
+pub fn verify(cmd: args::Verify, out: &mut Output) -> crate::Result<()> {
+    let image = png::Png::from_file(&cmd.file_path)?;
+    let key = signature::verifying_key_from_hex(&fs::read_to_string(&cmd.key)?)?;
+    let signed = signature::verify(&image, &key)?;
+    let fingerprint = signature::to_hex(signed.fingerprint());
+    let covered = signed.covered_chunks(&image);
+    let not_covered: Vec<usize> = (0..image.chunks().len())
+        .filter(|index| !covered.contains(index))
+        .collect();
+    let summaries = |indexes: &[usize]| -> Vec<Value> {
+        indexes
+            .iter()
+            .map(|&index| chunk_summary(index, &image.chunks()[index]))
+            .collect()
+    };
+    let list = |indexes: &[usize]| -> String {
+        indexes
+            .iter()
+            .map(|&index| format!("\n[{}] {}", index + 1, image.chunks()[index]))
+            .collect()
+    };
+    let mut text = format!(
+        "Signature is valid, key fingerprint: {}\nCovered chunks:{}",
+        fingerprint,
+        list(&covered)
+    );
+    if !not_covered.is_empty() {
+        text.push_str(&format!("\nNot covered:{}", list(&not_covered)));
+    }
+    let record = json!({
+        "valid": true,
+        "fingerprint": fingerprint,
+        "covered": summaries(&covered),
+        "not_covered": summaries(&not_covered),
+    });
+    out.record(text, record);
+    Ok(())
+}
+
+fn describe_change(change: &ChunkChange) -> String {
+    let chunk_type = change.chunk_type();
+    let delta = match change.size_delta() {
+        0 => String::new(),
+        delta => format!(" ({:+})", delta),
+    };
+    match (change.before(), change.after()) {
+        (Some(old), None) => format!(
+            "removed [{}] \"{}\" len:{}{}",
+            old.index() + 1,
+            chunk_type,
+            old.length(),
+            delta
+        ),
+        (None, Some(new)) => format!(
+            "added [{}] \"{}\" len:{}{}",
+            new.index() + 1,
+            chunk_type,
+            new.length(),
+            delta
+        ),
+        (Some(old), Some(new)) => {
+            let kind = match (change.is_modified(), change.is_reordered()) {
+                (true, true) => "modified and reordered",
+                (true, false) => "modified",
+                _ => "reordered",
+            };
+            let length = match old.length() == new.length() {
+                true => format!("len:{}", new.length()),
+                false => format!("len:{} -> {}", old.length(), new.length()),
+            };
+            format!(
+                "{} [{}] -> [{}] \"{}\" {}{}",
+                kind,
+                old.index() + 1,
+                new.index() + 1,
+                chunk_type,
+                length,
+                delta
+            )
+        }
+        (None, None) => unreachable!("change has at least one side"),
+    }
+}
+
+fn image_data_code(change: ImageDataChange) -> &'static str {
+    match change {
+        ImageDataChange::Unchanged => "unchanged",
+        ImageDataChange::Resplit => "resplit",
+        ImageDataChange::Recompressed => "recompressed",
+        ImageDataChange::Modified => "modified",
+        ImageDataChange::Undecodable => "undecodable",
+    }
+}
+
+fn change_record(change: &ChunkChange) -> Value {
+    let place = |place: Option<diff::ChunkPlace>| {
+        place.map(|place| json!({"index": place.index(), "length": place.length()}))
+    };
+    json!({
+        "type": change.chunk_type().to_string(),
+        "before": place(change.before()),
+        "after": place(change.after()),
+        "added": change.is_added(),
+        "removed": change.is_removed(),
+        "modified": change.is_modified(),
+        "reordered": change.is_reordered(),
+        "size_delta": change.size_delta(),
+    })
+}
+
+pub fn diff(cmd: args::Diff, out: &mut Output) -> crate::Result<()> {
+    let old = png::Png::from_file(&cmd.old_file)?;
+    let new = png::Png::from_file(&cmd.new_file)?;
+    let result = diff::diff(&old, &new);
+    let file_size = |path: &Path| fs::metadata(path).map(|metadata| metadata.len() as i64);
+    let delta = file_size(&cmd.new_file)? - file_size(&cmd.old_file)?;
+    let changes: Vec<Value> = result.changes().iter().map(change_record).collect();
+    let count = |predicate: fn(&ChunkChange) -> bool| {
+        result.changes().iter().filter(|c| predicate(c)).count()
+    };
+    let mut text: String = result
+        .changes()
+        .iter()
+        .map(|change| format!("{}\n", describe_change(change)))
+        .collect();
+    text.push_str(&format!(
+        "{} added, {} removed, {} modified, {} reordered, {} unchanged\n\
+         file size: {:+} bytes\nimage data: {}",
+        count(ChunkChange::is_added),
+        count(ChunkChange::is_removed),
+        count(ChunkChange::is_modified),
+        count(ChunkChange::is_reordered),
+        result.unchanged(),
+        delta,
+        result.image_data()
+    ));
+    let record = json!({
+        "changes": changes,
+        "unchanged": result.unchanged(),
+        "size_delta": delta,
+        "image_data_modified": result.image_data().is_modified(),
+        "image_data": image_data_code(result.image_data()),
+    });
+    out.record(text, record);
+    Ok(())
+}
+
+fn repair_code(kind: &RepairKind) -> &'static str {
+    match kind {
+        RepairKind::CrcFixed { .. } => "crc_fixed",
+        RepairKind::CrcKept => "crc_kept",
+        RepairKind::DuplicateIhdrRemoved => "duplicate_ihdr_removed",
+        RepairKind::IhdrMovedFirst => "ihdr_moved",
+        RepairKind::ChunkAfterIendMoved => "chunk_after_iend_moved",
+        RepairKind::ChunkAfterIendDropped => "chunk_after_iend_dropped",
+        RepairKind::DuplicateIendRemoved => "duplicate_iend_removed",
+        RepairKind::IendAdded => "iend_added",
+        RepairKind::IdatMoved => "idat_moved",
+        RepairKind::PlteMoved => "plte_moved",
+    }
+}
+
+pub fn repair(cmd: args::Repair, out: &mut Output) -> crate::Result<()> {
+    let file = map_file(&cmd.file_path)?;
+    let mut image = parse_png(&file, true, out)?.to_png();
+    let options = RepairOptions::default()
+        .crc(cmd.crc)
+        .after_iend(cmd.after_iend);
+    let repairs = repair::repair(&mut image, options);
+    for repair in repairs.iter() {
+        let record = json!({
+            "code": repair_code(repair.kind()),
+            "chunk_type": repair.chunk_type(),
+            "offset": repair.offset(),
+            "message": repair.kind().to_string(),
+        });
+        out.record(repair, record);
+    }
+    // lenient parsing alone may have dropped damaged bytes
+    let changed = image.as_bytes() != file[..];
+    drop(file);
+    if !changed && cmd.output_file.is_none() {
+        out.record("Nothing to repair", json!({ "output": null }));
+        return Ok(());
+    }
+    if !changed {
+        out.line("Nothing to repair");
+    }
+    let output_path = cmd.output_file.unwrap_or(cmd.file_path);
+    write_png_file(&image, &output_path, cmd.backup.as_deref())?;
+    let record = json!({ "output": output_path.display().to_string() });
+    out.record(format!("Saved to {}", output_path.display()), record);
+    Ok(())
+}
+
+pub fn strip(cmd: args::Strip, out: &mut Output) -> crate::Result<()> {
+    let inputs = Inputs::expand(&cmd.paths, cmd.recursive)?;
+    if cmd.output_file.is_some() && inputs.is_batch() {
+        return Err("output file can be given only for a single file".into());
+    }
+    let policy = match (cmd.policy.as_ref(), cmd.keep.is_empty()) {
+        (Some(policy), _) => policy.clone(),
+        (None, true) => StripPolicy::Ancillary,
+        (None, false) => {
+            let types = cmd.keep.iter().map(|t| ChunkType::from_str(t));
+            StripPolicy::Allowlist(types.collect::<Result<_, _>>()?)
+        }
+    };
+    inputs.run(out, |path, out| strip_file(&cmd, &policy, path, out))
+}
+
+fn strip_file(
+    cmd: &args::Strip,
+    policy: &StripPolicy,
+    file_path: &Path,
+    out: &mut Output,
+) -> crate::Result<()> {
+    let mut image = png::Png::from_file(file_path)?;
+    let stripped = strip::strip(&mut image, policy);
+    for chunk in stripped.chunks() {
+        let description = match chunk.source_offset() {
+            Some(offset) => format!("Removed {} at {:#x}", chunk, offset),
+            None => format!("Removed {}", chunk),
+        };
+        let record = json!({
+            "type": chunk.chunk_type().to_string(),
+            "offset": chunk.source_offset(),
+            "length": chunk.length(),
+        });
+        out.record(description, record);
+    }
+    let trailing = stripped.trailing_data().len();
+    if trailing > 0 {
+        let description = format!("Removed {} bytes after IEND", trailing);
+        out.record(description, json!({ "trailing": { "length": trailing } }));
+    }
+    let output_path = cmd.output_file.as_deref().unwrap_or(file_path);
+    let saved = !cmd.dry_run && (!stripped.is_empty() || cmd.output_file.is_some());
+    if saved {
+        write_png_file(&image, output_path, cmd.backup.as_deref())?;
+    }
+    let summary = match (stripped.is_empty(), cmd.dry_run) {
+        (true, _) => "Nothing to remove".to_string(),
+        (false, true) => format!("Would remove {} bytes, dry run", stripped.len()),
+        (false, false) => format!("Removed {} bytes", stripped.len()),
+    };
+    let record = json!({
+        "removed_chunks": stripped.chunks().len(),
+        "removed_bytes": stripped.len(),
+        "dry_run": cmd.dry_run,
+        "output": saved.then(|| output_path.display().to_string()),
+    });
+    out.record(summary, record);
+    Ok(())
+}
+
+fn finding_code(kind: &FindingKind) -> &'static str {
+    match kind {
+        FindingKind::PrivateChunk(_) => "private_chunk",
+        FindingKind::UnknownChunk(_) => "unknown_chunk",
+        FindingKind::HighEntropy { .. } => "high_entropy",
+        FindingKind::DataAfterIend { .. } => "data_after_iend",
+        FindingKind::OversizedText { .. } => "oversized_text",
+        FindingKind::ImageDataTrailer { .. } => "image_data_trailer",
+        FindingKind::LsbChiSquare { .. } => "lsb_chi_square",
+        FindingKind::LsbRs { .. } => "lsb_rs",
+    }
+}
+
+pub fn scan(cmd: args::Scan, out: &mut Output) -> crate::Result<()> {
+    if !(0.0..=1.0).contains(&cmd.min_score) {
+        return Err("minimum score must be between 0 and 1".into());
+    }
+    let inputs = Inputs::expand(&cmd.paths, cmd.recursive)?;
+    inputs.run(out, |path, out| scan_file(&cmd, path, out))
+}
+
+fn scan_file(cmd: &args::Scan, file_path: &Path, out: &mut Output) -> crate::Result<()> {
+    let file = map_file(file_path)?;
+    let image = parse_png(&file, cmd.lenient, out)?.to_png();
+    let findings: Vec<_> = detect::scan(&image)
+        .into_iter()
+        .filter(|finding| finding.score() >= cmd.min_score)
+        .collect();
+    for finding in findings.iter() {
+        let record = json!({
+            "code": finding_code(finding.kind()),
+            "score": finding.score(),
+            "chunk_index": finding.chunk_index(),
+            "message": finding.kind().to_string(),
+        });
+        out.record(finding, record);
+    }
+    if out.is_text() {
+        match findings.first() {
+            None => out.line("Nothing suspicious found"),
+            Some(finding) => out.line(format_args!(
+                "{} findings, highest score {:.2}",
+                findings.len(),
+                finding.score()
+            )),
+        }
+    }
+    if !findings.is_empty() {
+        let message = format!("{:?} may hide data", file_path);
+        return Err(CommandError::new("suspicious", message).into());
+    }
     Ok(())
 }
@@ -1,17 +1,36 @@
 use crate::args::SubCommand;
 
 mod args;
+mod batch;
 mod commands;
+mod output;
 
 type Error = Box<dyn std::error::Error + Sync + Send>;
 type Result<T> = std::result::Result<T, Error>;
 
 fn main() -> crate::Result<()> {
-    match crate::args::parse_cli().sub_cmd {
-        SubCommand::Encode(cmd) => crate::commands::encode(cmd)?,
-        SubCommand::Decode(cmd) => crate::commands::decode(cmd)?,
-        SubCommand::Remove(cmd) => crate::commands::remove(cmd)?,
-        SubCommand::Print(cmd) => crate::commands::print(cmd)?,
+    let opts = crate::args::parse_cli();
+    let mut out = crate::output::Output::new(opts.format);
+    let result = match opts.sub_cmd {
+        SubCommand::Encode(cmd) => crate::commands::encode(cmd, &mut out),
+        SubCommand::Decode(cmd) => crate::commands::decode(cmd, &mut out),
+        SubCommand::Remove(cmd) => crate::commands::remove(cmd, &mut out),
+        SubCommand::Print(cmd) => crate::commands::print(cmd, &mut out),
+        SubCommand::Validate(cmd) => crate::commands::validate(cmd, &mut out),
+        SubCommand::Text(cmd) => crate::commands::text(cmd, &mut out),
+        SubCommand::Keygen(cmd) => crate::commands::keygen(cmd, &mut out),
+        SubCommand::Sign(cmd) => crate::commands::sign(cmd, &mut out),
+        SubCommand::Verify(cmd) => crate::commands::verify(cmd, &mut out),
+        SubCommand::Diff(cmd) => crate::commands::diff(cmd, &mut out),
+        SubCommand::Repair(cmd) => crate::commands::repair(cmd, &mut out),
+        SubCommand::Strip(cmd) => crate::commands::strip(cmd, &mut out),
+        SubCommand::Scan(cmd) => crate::commands::scan(cmd, &mut out),
     };
-    Ok(())
+    // text format keeps the usual error reporting, other formats print error as a record
+    let is_text = out.is_text();
+    out.finish(result.as_ref().err());
+    match result {
+        Err(_) if !is_text => std::process::exit(1),
+        result => result,
+    }
 }
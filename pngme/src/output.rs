@@ -0,0 +1,354 @@
+//! Output of subcommands as human readable text, JSON document or newline delimited JSON
+//!
+//! Machine readable output is a list of records, the shape of a record depends on subcommand.
+//! JSON format prints `{"records": [...], "error": null}` once the subcommand finishes, NDJSON
+//! prints every record on its own line as soon as it is ready and `{"error": {...}}` as the last
+//! line when the subcommand fails. Errors carry a stable `code` and a human readable `message`.
+//!
+//! When a subcommand runs on many files, output of every file is buffered and added under the
+//! file path: a `==> path <==` header in text, a `{"path", "records", "error"}` record in JSON
+//! and a `path` field on each record in NDJSON.
+use std::fmt::{self, Display};
+use std::io::{self, Write};
+use std::path::Path;
+use std::str::FromStr;
+
+use base64::Engine;
+use serde_json::{json, Value};
+
+use pngme_lib::error::{
+    ChunkParseError, ChunkTypeParseError, CompressionError, CryptoError, EncodeError,
+    EnvelopeError, IhdrParseError, ImageDataError, InsertChunkError, LsbError, MessageError,
+    PngFromBytesError, PngFromFileError, RemoveChunkError, SequenceError, SignatureError,
+    TextChunkError,
+};
+
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum Format {
+    Text,
+    Json,
+    Ndjson,
+}
+
+impl FromStr for Format {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "text" => Ok(Self::Text),
+            "json" => Ok(Self::Json),
+            "ndjson" => Ok(Self::Ndjson),
+            _ => Err(format!(
+                "unknown format {:?}, expected text, json or ndjson",
+                s
+            )),
+        }
+    }
+}
+
+/// Encoding of binary data in output
+#[derive(Clone, Copy, PartialEq, Eq)]
+pub enum DataEncoding {
+    Base64,
+    Hex,
+}
+
+impl DataEncoding {
+    pub(crate) fn encode(&self, data: &[u8]) -> String {
+        match self {
+            Self::Base64 => base64::engine::general_purpose::STANDARD.encode(data),
+            Self::Hex => pngme_lib::signature::to_hex(data),
+        }
+    }
+}
+
+impl FromStr for DataEncoding {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "base64" => Ok(Self::Base64),
+            "hex" => Ok(Self::Hex),
+            _ => Err(format!(
+                "unknown data encoding {:?}, expected base64 or hex",
+                s
+            )),
+        }
+    }
+}
+
+impl Display for DataEncoding {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Base64 => write!(f, "base64"),
+            Self::Hex => write!(f, "hex"),
+        }
+    }
+}
+
+/// Error raised by a subcommand itself rather than by the library
+pub(crate) struct CommandError {
+    code: &'static str,
+    message: String,
+}
+
+impl CommandError {
+    pub(crate) fn new(code: &'static str, message: impl Into<String>) -> Self {
+        Self {
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl Display for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+// main reports returned errors with Debug, which should read like the other errors
+impl fmt::Debug for CommandError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.message)
+    }
+}
+
+impl std::error::Error for CommandError {}
+
+/// Collects records of a subcommand and prints them in the chosen format
+pub(crate) struct Output {
+    format: Format,
+    records: Vec<Value>,
+    /// Output of a single file in a batch is kept until the file is done, text goes to `text`,
+    /// warnings to `warnings` and NDJSON records to `records`
+    buffered: bool,
+    text: Vec<u8>,
+    warnings: Vec<u8>,
+}
+
+impl Output {
+    pub(crate) fn new(format: Format) -> Self {
+        Output {
+            format,
+            records: Vec::new(),
+            buffered: false,
+            text: Vec::new(),
+            warnings: Vec::new(),
+        }
+    }
+
+    /// Output that prints nothing until it is added to another one with `add_file`
+    pub(crate) fn buffered(format: Format) -> Self {
+        Output {
+            buffered: true,
+            ..Self::new(format)
+        }
+    }
+
+    pub(crate) fn format(&self) -> Format {
+        self.format
+    }
+
+    pub(crate) fn is_text(&self) -> bool {
+        self.format == Format::Text
+    }
+
+    /// Prints a line of text, callers check the format
+    pub(crate) fn line(&mut self, text: impl Display) {
+        match self.buffered {
+            true => writeln!(self.text, "{}", text).expect("writing to memory does not fail"),
+            false => println!("{}", text),
+        }
+    }
+
+    /// Writes bytes as they are, e.g. raw message data
+    pub(crate) fn write_raw(&mut self, data: &[u8]) -> io::Result<()> {
+        if self.buffered {
+            self.text.extend_from_slice(data);
+            return Ok(());
+        }
+        let mut stdout = io::stdout();
+        stdout.write_all(data)?;
+        stdout.flush()
+    }
+
+    /// Prints `text` in text format, adds `record` in other formats
+    pub(crate) fn record(&mut self, text: impl Display, record: Value) {
+        match self.format {
+            Format::Text => self.line(text),
+            _ => self.emit(record),
+        }
+    }
+
+    /// Reports a problem that does not stop the command, text goes to stderr
+    pub(crate) fn warning(&mut self, text: impl Display, record: Value) {
+        match (self.format, self.buffered) {
+            (Format::Text, true) => {
+                writeln!(self.warnings, "{}", text).expect("writing to memory does not fail")
+            }
+            (Format::Text, false) => eprintln!("{}", text),
+            _ => self.emit(json!({ "warning": record })),
+        }
+    }
+
+    /// Adds record that has no text representation, ignored in text format
+    pub(crate) fn emit(&mut self, record: Value) {
+        match self.format {
+            Format::Text => {}
+            Format::Ndjson if !self.buffered => println!("{}", record),
+            Format::Json | Format::Ndjson => self.records.push(record),
+        }
+    }
+
+    /// Adds output of a single file processed in a batch together with its error
+    pub(crate) fn add_file(
+        &mut self,
+        path: &Path,
+        file: Output,
+        error: Option<&crate::Error>,
+    ) -> io::Result<()> {
+        let path = path.display().to_string();
+        match self.format {
+            Format::Text => {
+                self.line(format_args!("==> {} <==", path));
+                self.write_raw(&file.text)?;
+                for warning in String::from_utf8_lossy(&file.warnings).lines() {
+                    eprintln!("{}: {}", path, warning);
+                }
+                if let Some(error) = error {
+                    eprintln!("{}: {}", path, error);
+                }
+            }
+            Format::Json => self.emit(json!({
+                "path": path,
+                "records": file.records,
+                "error": error.map(error_record),
+            })),
+            Format::Ndjson => {
+                for mut record in file.records {
+                    if let Some(record) = record.as_object_mut() {
+                        record.insert("path".into(), Value::from(path.as_str()));
+                    }
+                    self.emit(record);
+                }
+                if let Some(error) = error {
+                    self.emit(json!({ "path": path, "error": error_record(error) }));
+                }
+            }
+        }
+        Ok(())
+    }
+
+    /// Prints everything collected so far, `error` is printed as well in machine formats
+    pub(crate) fn finish(self, error: Option<&crate::Error>) {
+        let error = error.map(error_record);
+        match (self.format, error) {
+            (Format::Text, _) => {}
+            (Format::Json, error) => {
+                let document = json!({"records": self.records, "error": error});
+                println!("{:#}", document);
+            }
+            (Format::Ndjson, Some(error)) => println!("{}", json!({ "error": error })),
+            (Format::Ndjson, None) => {}
+        }
+    }
+}
+
+fn error_record(error: &crate::Error) -> Value {
+    json!({"code": error_code(error), "message": error.to_string()})
+}
+
+/// Stable machine readable code of an error
+fn error_code(error: &crate::Error) -> &'static str {
+    if let Some(e) = error.downcast_ref::<CommandError>() {
+        return e.code;
+    }
+    if error.is::<std::io::Error>() {
+        return "io";
+    }
+    if let Some(e) = error.downcast_ref::<PngFromFileError>() {
+        return match e {
+            PngFromFileError::FileReadError(_) => "io",
+            PngFromFileError::PngFromBytesError(e) => png_code(e),
+        };
+    }
+    if let Some(e) = error.downcast_ref::<PngFromBytesError>() {
+        return png_code(e);
+    }
+    if let Some(e) = error.downcast_ref::<ChunkParseError>() {
+        return chunk_code(e);
+    }
+    if error.is::<ChunkTypeParseError>() {
+        return "invalid_chunk_type";
+    }
+    if let Some(e) = error.downcast_ref::<CryptoError>() {
+        return crypto_code(e);
+    }
+    if let Some(e) = error.downcast_ref::<EnvelopeError>() {
+        return match e {
+            EnvelopeError::Crypto(e) => crypto_code(e),
+            EnvelopeError::Compression(_) => "compression",
+            EnvelopeError::ChecksumMismatch => "checksum_mismatch",
+            _ => "envelope",
+        };
+    }
+    if let Some(e) = error.downcast_ref::<SignatureError>() {
+        return match e {
+            SignatureError::NotSigned => "not_signed",
+            SignatureError::KeyMismatch => "key_mismatch",
+            SignatureError::BadSignature => "bad_signature",
+            SignatureError::InvalidKey => "invalid_key",
+            _ => "signature",
+        };
+    }
+    if let Some(e) = error.downcast_ref::<MessageError>() {
+        return match e {
+            MessageError::Sequence(_) => "sequence",
+            _ => "message",
+        };
+    }
+    if error.is::<SequenceError>() {
+        return "sequence";
+    }
+    if error.is::<CompressionError>() {
+        return "compression";
+    }
+    if error.is::<LsbError>() {
+        return "lsb";
+    }
+    if error.is::<IhdrParseError>() || error.is::<ImageDataError>() {
+        return "image_data";
+    }
+    if error.is::<EncodeError>() {
+        return "encode";
+    }
+    if error.is::<TextChunkError>() {
+        return "text_chunk";
+    }
+    if error.is::<InsertChunkError>() || error.is::<RemoveChunkError>() {
+        return "chunk_position";
+    }
+    "invalid_input"
+}
+
+fn png_code(error: &PngFromBytesError) -> &'static str {
+    match error {
+        PngFromBytesError::ChunkParseError(e) => chunk_code(e),
+        _ => "invalid_png",
+    }
+}
+
+fn chunk_code(error: &ChunkParseError) -> &'static str {
+    match error {
+        ChunkParseError::CrcMismatch => "crc_mismatch",
+        _ => "invalid_chunk",
+    }
+}
+
+fn crypto_code(error: &CryptoError) -> &'static str {
+    match error {
+        CryptoError::DecryptionFailed => "decryption_failed",
+        _ => "crypto",
+    }
+}
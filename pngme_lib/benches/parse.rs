@@ -0,0 +1,43 @@
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use criterion::{black_box, criterion_group, criterion_main, Criterion, Throughput};
+use pngme_lib::chunk::Chunk;
+use pngme_lib::chunk_type::ChunkType;
+use pngme_lib::png::{Png, PngReader, PngRef};
+
+/// Builds a png with many large IDAT chunks, similar to big scans
+fn idat_heavy_png() -> Vec<u8> {
+    let data: Vec<u8> = (0..1 << 16).map(|i: u32| (i * 31 % 251) as u8).collect();
+    let mut chunks = vec![Chunk::new(ChunkType::from_str("IHDR").unwrap(), &[0; 13])];
+    chunks.extend((0..256).map(|_| Chunk::new(ChunkType::from_str("IDAT").unwrap(), &data)));
+    chunks.push(Chunk::new(ChunkType::from_str("IEND").unwrap(), &[]));
+    Png::from_chunks(chunks).as_bytes()
+}
+
+fn parse_benchmark(c: &mut Criterion) {
+    let inputs = [
+        ("dice", std::fs::read("../images/dice.png").unwrap()),
+        ("idat_heavy", idat_heavy_png()),
+    ];
+    for (name, bytes) in inputs.iter() {
+        let mut group = c.benchmark_group(*name);
+        group.throughput(Throughput::Bytes(bytes.len() as u64));
+        group.bench_function("owned Png::try_from", |b| {
+            b.iter(|| Png::try_from(black_box(&bytes[..])).unwrap())
+        });
+        group.bench_function("streaming PngReader", |b| {
+            b.iter(|| {
+                let reader = PngReader::new(black_box(&bytes[..])).unwrap();
+                reader.map(Result::unwrap).count()
+            })
+        });
+        group.bench_function("borrowed PngRef::try_from", |b| {
+            b.iter(|| PngRef::try_from(black_box(&bytes[..])).unwrap())
+        });
+        group.finish();
+    }
+}
+
+criterion_group!(benches, parse_benchmark);
+criterion_main!(benches);
@@ -1,35 +1,108 @@
-use std::convert::TryInto;
+use std::convert::{TryFrom, TryInto};
+use std::ops::Range;
+use std::str::Utf8Error;
 use std::string::FromUtf8Error;
 
 use byteorder::ByteOrder;
+use crc::crc32::Hasher32;
 
 use crate::chunk_type::ChunkType;
 use crate::error::ChunkParseError;
 
-#[derive(::derive_more::Display)]
+#[derive(Clone, ::derive_more::Display)]
 #[display(fmt = "Chunk \"{}\" len:{}", chunk_type, length)]
 pub struct Chunk {
     length: u32,
     chunk_type: ChunkType,
     data: Vec<u8>,
     crc: u32,
+    source_offset: Option<u64>,
+}
+
+/// Byte ranges taken by parts of a chunk in the file it was read from
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkLayout {
+    offset: u64,
+    length: u32,
+}
+
+impl ChunkLayout {
+    pub fn new(offset: u64, length: u32) -> Self {
+        Self { offset, length }
+    }
+    /// Offset of the first byte of the chunk length
+    pub fn offset(&self) -> u64 {
+        self.offset
+    }
+    /// Length and type
+    pub fn header(&self) -> Range<u64> {
+        self.offset..self.offset + 8
+    }
+    pub fn data(&self) -> Range<u64> {
+        self.offset + 8..self.offset + 8 + u64::from(self.length)
+    }
+    pub fn crc(&self) -> Range<u64> {
+        let end = self.end();
+        end - 4..end
+    }
+    /// Offset right after the chunk
+    pub fn end(&self) -> u64 {
+        self.offset + 12 + u64::from(self.length)
+    }
 }
 
 impl Chunk {
+    /// Largest data length allowed by the PNG specification
+    pub const MAX_LENGTH: usize = (1 << 31) - 1;
+
+    /// Panics when data is longer than `Chunk::MAX_LENGTH`, use `try_new` for untrusted sizes
     pub fn new(chunk_type: ChunkType, data: &[u8]) -> Self {
-        let data_for_crc: Vec<u8> = chunk_type
-            .bytes()
-            .iter()
-            .chain(data.iter())
-            .copied()
-            .collect();
-        let crc = ::crc::crc32::checksum_ieee(&data_for_crc[..]);
-        Self {
+        Self::try_new(chunk_type, data).expect("chunk data is too long")
+    }
+    pub fn try_new(chunk_type: ChunkType, data: &[u8]) -> Result<Self, ChunkParseError> {
+        if data.len() > Self::MAX_LENGTH {
+            return Err(ChunkParseError::DataTooLong(data.len()));
+        }
+        let crc = Self::calculate_crc(&chunk_type, data);
+        Ok(Self {
             length: data.len() as u32,
             chunk_type,
-            data: data.iter().copied().collect(),
+            data: data.to_vec(),
             crc,
+            source_offset: None,
+        })
+    }
+    /// Builds chunk from already separated parts, checking that the crc matches the data
+    pub(crate) fn from_parts(
+        chunk_type: ChunkType,
+        data: Vec<u8>,
+        crc: u32,
+    ) -> Result<Self, ChunkParseError> {
+        let length: u32 = data
+            .len()
+            .try_into()
+            .map_err(|_| ChunkParseError::LengthDoesNotFitToU32)?;
+        if crc != Self::calculate_crc(&chunk_type, &data) {
+            return Err(ChunkParseError::CrcMismatch);
         }
+        Ok(Self {
+            length,
+            chunk_type,
+            data,
+            crc,
+            source_offset: None,
+        })
+    }
+    /// Remembers where the chunk was read from
+    pub(crate) fn with_source_offset(mut self, offset: u64) -> Self {
+        self.source_offset = Some(offset);
+        self
+    }
+    pub(crate) fn calculate_crc(chunk_type: &ChunkType, data: &[u8]) -> u32 {
+        let mut digest = ::crc::crc32::Digest::new(::crc::crc32::IEEE);
+        digest.write(&chunk_type.bytes());
+        digest.write(data);
+        digest.sum32()
     }
     pub fn length(&self) -> u32 {
         self.length
@@ -43,6 +116,20 @@ impl Chunk {
     pub fn crc(&self) -> u32 {
         self.crc
     }
+    /// Whether stored crc matches chunk type and data, false only for chunks parsed with the
+    /// crc check disabled
+    pub fn has_valid_crc(&self) -> bool {
+        self.crc == Self::calculate_crc(&self.chunk_type, &self.data)
+    }
+    /// Offset of the chunk in the file it was read from, `None` for chunks created in memory
+    pub fn source_offset(&self) -> Option<u64> {
+        self.source_offset
+    }
+    /// Where the chunk parts were in the file it was read from
+    pub fn layout(&self) -> Option<ChunkLayout> {
+        self.source_offset
+            .map(|offset| ChunkLayout::new(offset, self.length))
+    }
     pub fn data_as_string(&self) -> Result<String, FromUtf8Error> {
         String::from_utf8(self.data.clone())
     }
@@ -62,43 +149,128 @@ impl std::convert::TryFrom<&[u8]> for Chunk {
     type Error = ChunkParseError;
 
     fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
-        let mut bytes_iter = bytes.iter();
-        let length: Vec<u8> = bytes_iter.by_ref().take(4).copied().collect();
-        if length.len() != 4 {
+        Ok(ChunkRef::try_from(bytes)?.to_chunk())
+    }
+}
+
+/// Read-only view of a chunk that borrows its bytes from the input instead of copying them
+#[derive(Clone, Copy)]
+pub struct ChunkRef<'a> {
+    bytes: &'a [u8],
+    source_offset: Option<u64>,
+}
+
+impl<'a> ChunkRef<'a> {
+    /// Parses chunk at the start of `bytes`, returns it together with the remaining bytes
+    pub fn parse_prefix(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), ChunkParseError> {
+        Self::split_prefix(bytes, true)
+    }
+    /// Same as `parse_prefix` but accepts chunk whose crc does not match its data
+    pub fn parse_prefix_ignoring_crc(bytes: &'a [u8]) -> Result<(Self, &'a [u8]), ChunkParseError> {
+        Self::split_prefix(bytes, false)
+    }
+    fn split_prefix(bytes: &'a [u8], check_crc: bool) -> Result<(Self, &'a [u8]), ChunkParseError> {
+        if bytes.len() < 4 {
             return Err(ChunkParseError::NotEnoughBytesToParseLength);
         }
-        let length = byteorder::BigEndian::read_u32(&length[..]);
-        let type_vector: Vec<u8> = bytes_iter.by_ref().take(4).copied().collect();
-        let type_error = Err(ChunkParseError::NotEnoughBytesToParseType);
-        let type_bytes: [u8; 4] = <[u8; 4]>::try_from(type_vector).or(type_error)?;
+        let length = byteorder::BigEndian::read_u32(&bytes[..4]) as usize;
+        let end = bytes.len().min(length.saturating_add(12));
+        let (chunk, rest) = bytes.split_at(end);
+        Ok((Self::parse(chunk, check_crc)?, rest))
+    }
+    pub(crate) fn with_source_offset(mut self, offset: u64) -> Self {
+        self.source_offset = Some(offset);
+        self
+    }
+    pub fn length(&self) -> u32 {
+        byteorder::BigEndian::read_u32(&self.bytes[..4])
+    }
+    pub fn chunk_type_bytes(&self) -> [u8; 4] {
+        [self.bytes[4], self.bytes[5], self.bytes[6], self.bytes[7]]
+    }
+    pub fn chunk_type(&self) -> ChunkType {
+        ChunkType::try_from(self.chunk_type_bytes()).expect("chunk type is validated on parse")
+    }
+    pub fn data(&self) -> &'a [u8] {
+        &self.bytes[8..self.bytes.len() - 4]
+    }
+    pub fn crc(&self) -> u32 {
+        byteorder::BigEndian::read_u32(&self.bytes[self.bytes.len() - 4..])
+    }
+    pub fn data_as_str(&self) -> Result<&'a str, Utf8Error> {
+        std::str::from_utf8(self.data())
+    }
+    pub fn as_bytes(&self) -> &'a [u8] {
+        self.bytes
+    }
+    /// Offset of the chunk in the bytes it was parsed from as part of a PNG file
+    pub fn source_offset(&self) -> Option<u64> {
+        self.source_offset
+    }
+    pub fn layout(&self) -> Option<ChunkLayout> {
+        self.source_offset
+            .map(|offset| ChunkLayout::new(offset, self.length()))
+    }
+    /// Whether stored crc matches chunk type and data, always true unless the crc was ignored
+    /// on parse
+    pub fn has_valid_crc(&self) -> bool {
+        self.crc() == Chunk::calculate_crc(&self.chunk_type(), self.data())
+    }
+    pub fn to_chunk(&self) -> Chunk {
+        Chunk {
+            length: self.length(),
+            chunk_type: self.chunk_type(),
+            data: self.data().to_vec(),
+            crc: self.crc(),
+            source_offset: self.source_offset,
+        }
+    }
+}
+
+impl<'a> std::convert::TryFrom<&'a [u8]> for ChunkRef<'a> {
+    type Error = ChunkParseError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<Self, Self::Error> {
+        Self::parse(bytes, true)
+    }
+}
+
+impl<'a> ChunkRef<'a> {
+    fn parse(bytes: &'a [u8], check_crc: bool) -> Result<Self, ChunkParseError> {
+        if bytes.len() < 4 {
+            return Err(ChunkParseError::NotEnoughBytesToParseLength);
+        }
+        if bytes.len() < 8 {
+            return Err(ChunkParseError::NotEnoughBytesToParseType);
+        }
+        let length = byteorder::BigEndian::read_u32(&bytes[..4]);
+        let type_bytes = [bytes[4], bytes[5], bytes[6], bytes[7]];
         let chunk_type = ChunkType::try_from(type_bytes)?;
-        let data_take_count = bytes.len().saturating_sub(4 * 3);
-        let data: Vec<u8> = bytes_iter.by_ref().take(data_take_count).copied().collect();
-        let data_parse_error = Err(ChunkParseError::LengthDoesNotFitToU32);
-        let data_length: u32 = data.len().try_into().or(data_parse_error)?;
-        if length != data_length {
+        let data_length = bytes.len().saturating_sub(4 * 3);
+        if u32::try_from(data_length).is_err() {
+            return Err(ChunkParseError::LengthDoesNotFitToU32);
+        }
+        if length as usize != data_length {
             return Err(ChunkParseError::EncodedLengthNotEqualToActual);
         }
-        let crc: Vec<u8> = bytes_iter.by_ref().copied().collect();
-        if crc.len() != 4 {
+        if bytes.len() < 12 {
             return Err(ChunkParseError::CouldNotParseCrc);
         }
-        let crc = byteorder::BigEndian::read_u32(&crc[..]);
-        let data_for_crc: Vec<u8> = chunk_type
-            .bytes()
-            .iter()
-            .chain(data.iter())
-            .copied()
-            .collect();
-        if crc != ::crc::crc32::checksum_ieee(&data_for_crc[..]) {
+        let chunk = Self {
+            bytes,
+            source_offset: None,
+        };
+        if check_crc && chunk.crc() != Chunk::calculate_crc(&chunk_type, chunk.data()) {
             return Err(ChunkParseError::CrcMismatch);
         }
-        Ok(Chunk {
-            length,
-            chunk_type,
-            data,
-            crc,
-        })
+        Ok(chunk)
+    }
+}
+
+impl std::fmt::Display for ChunkRef<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let chunk_type = String::from_utf8_lossy(&self.bytes[4..8]);
+        write!(f, "Chunk \"{}\" len:{}", chunk_type, self.length())
     }
 }
 
@@ -247,6 +419,68 @@ mod tests {
         assert!(chunk.is_err());
     }
 
+    #[test]
+    fn test_chunk_ref_borrows_input() {
+        let bytes = testing_bytes();
+        let chunk = ChunkRef::try_from(bytes.as_ref()).unwrap();
+        assert_eq!(chunk.length(), 42);
+        assert_eq!(chunk.chunk_type().to_string(), "RuSt");
+        assert_eq!(chunk.crc(), 2882656334);
+        assert_eq!(
+            chunk.data_as_str().unwrap(),
+            "This is where your secret message will be!"
+        );
+        assert_eq!(chunk.data().as_ptr(), bytes[8..].as_ptr());
+        assert_eq!(chunk.as_bytes(), &bytes[..]);
+        assert_eq!(chunk.to_chunk().as_bytes(), testing_chunk().as_bytes());
+        assert_eq!(chunk.to_string(), testing_chunk().to_string());
+    }
+
+    #[test]
+    fn test_chunk_ref_invalid_crc() {
+        let mut bytes = testing_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        assert!(ChunkRef::try_from(bytes.as_ref()).is_err());
+    }
+
+    #[test]
+    fn test_chunk_ref_parse_prefix() {
+        let mut bytes = testing_bytes();
+        bytes.extend_from_slice(&[1, 2, 3]);
+        let (chunk, rest) = ChunkRef::parse_prefix(&bytes).unwrap();
+        assert_eq!(chunk.length(), 42);
+        assert_eq!(rest, &[1, 2, 3]);
+        assert!(ChunkRef::parse_prefix(&bytes[..20]).is_err());
+        assert!(ChunkRef::parse_prefix(&bytes[..3]).is_err());
+    }
+
+    #[test]
+    fn test_chunk_ref_ignoring_crc() {
+        let mut bytes = testing_bytes();
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        let (chunk, rest) = ChunkRef::parse_prefix_ignoring_crc(&bytes).unwrap();
+        assert!(rest.is_empty());
+        assert!(!chunk.has_valid_crc());
+        assert_eq!(chunk.to_chunk().crc(), chunk.crc());
+        bytes[last] ^= 1;
+        let (chunk, _) = ChunkRef::parse_prefix_ignoring_crc(&bytes).unwrap();
+        assert!(chunk.has_valid_crc());
+        assert!(ChunkRef::parse_prefix_ignoring_crc(&bytes[..20]).is_err());
+    }
+
+    #[test]
+    fn test_chunk_layout() {
+        let layout = ChunkLayout::new(33, 4);
+        assert_eq!(layout.offset(), 33);
+        assert_eq!(layout.header(), 33..41);
+        assert_eq!(layout.data(), 41..45);
+        assert_eq!(layout.crc(), 45..49);
+        assert_eq!(layout.end(), 49);
+        assert!(testing_chunk().layout().is_none());
+    }
+
     #[test]
     pub fn test_chunk_trait_impls() {
         let chunk = testing_chunk();
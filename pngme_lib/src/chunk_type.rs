@@ -1,13 +1,13 @@
 use crate::error::ChunkTypeParseError;
+use crate::registry::{self, ChunkSpec};
 
-#[derive(std::fmt::Debug)]
+#[derive(std::fmt::Debug, Clone)]
 pub struct ChunkType {
     ancillary_bit_char: char,
     private_bit_char: char,
     reserved_bit_char: char,
     safe_to_copy_bit_char: char,
     bytes: [u8; 4],
-    chars: [char; 4],
     string: String,
 }
 
@@ -30,6 +30,13 @@ impl ChunkType {
     pub fn is_safe_to_copy(&self) -> bool {
         self.safe_to_copy_bit_char.is_ascii_lowercase()
     }
+    /// Whether the type is defined by the specification or a registered extension
+    pub fn is_known(&self) -> bool {
+        self.spec().is_some()
+    }
+    pub fn spec(&self) -> Option<&'static ChunkSpec> {
+        registry::lookup(self.bytes)
+    }
 
     fn validate_char(ch: char) -> Result<char, ChunkTypeParseError> {
         match ch.is_ascii_lowercase() || ch.is_ascii_uppercase() {
@@ -59,7 +66,6 @@ impl std::convert::TryFrom<[u8; 4]> for ChunkType {
             reserved_bit_char,
             safe_to_copy_bit_char,
             bytes,
-            chars,
             string: chars.iter().collect(),
         })
     }
@@ -93,7 +99,6 @@ impl std::str::FromStr for ChunkType {
                 u32::from(chars[2]) as u8,
                 u32::from(chars[3]) as u8,
             ],
-            chars,
             string: value.into(),
         })
     }
@@ -256,4 +261,14 @@ mod tests {
         assert!(ChunkType::from_str("ABCd").unwrap().is_safe_to_copy());
         assert!(!ChunkType::from_str("abCD").unwrap().is_safe_to_copy());
     }
+
+    #[test]
+    pub fn test_known_chunk_type() {
+        let chunk_type = ChunkType::from_str("gAMA").unwrap();
+        assert!(chunk_type.is_known());
+        assert_eq!(chunk_type.spec().unwrap().name(), "Image gamma");
+        assert!(ChunkType::try_from(*b"acTL").unwrap().is_known());
+        assert!(!ChunkType::from_str("RuSt").unwrap().is_known());
+        assert!(ChunkType::from_str("GAMA").unwrap().spec().is_none());
+    }
 }
@@ -0,0 +1,301 @@
+//! Compression of message payloads
+//!
+//! Compressed payload starts with magic `pmCZ`, format version, algorithm code and original
+//! length as big endian u64, compressed data follows. Deflate is always available, zstd and
+//! brotli need cargo features with the same names.
+use std::convert::TryInto;
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::error::CompressionError;
+
+const MAGIC: &[u8; 4] = b"pmCZ";
+const VERSION: u8 = 1;
+const HEADER_LENGTH: usize = MAGIC.len() + 2 + 8;
+/// Largest original length accepted by `compress` and from a header
+const MAX_LENGTH: u64 = 1 << 30;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Algorithm {
+    Deflate,
+    Zstd,
+    Brotli,
+}
+
+impl Algorithm {
+    fn code(&self) -> u8 {
+        match self {
+            Self::Deflate => 1,
+            Self::Zstd => 2,
+            Self::Brotli => 3,
+        }
+    }
+
+    /// Lowest, highest and default compression level
+    pub fn levels(&self) -> (u32, u32, u32) {
+        match self {
+            Self::Deflate => (0, 9, 6),
+            Self::Zstd => (1, 22, 3),
+            Self::Brotli => (0, 11, 9),
+        }
+    }
+
+    /// Returns false when crate is built without the feature for this algorithm
+    pub fn is_available(&self) -> bool {
+        match self {
+            Self::Deflate => true,
+            Self::Zstd => cfg!(feature = "zstd"),
+            Self::Brotli => cfg!(feature = "brotli"),
+        }
+    }
+}
+
+impl std::convert::TryFrom<u8> for Algorithm {
+    type Error = CompressionError;
+
+    fn try_from(code: u8) -> Result<Self, Self::Error> {
+        match code {
+            1 => Ok(Self::Deflate),
+            2 => Ok(Self::Zstd),
+            3 => Ok(Self::Brotli),
+            _ => Err(CompressionError::UnknownAlgorithm(code.to_string())),
+        }
+    }
+}
+
+impl std::str::FromStr for Algorithm {
+    type Err = CompressionError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "deflate" => Ok(Self::Deflate),
+            "zstd" => Ok(Self::Zstd),
+            "brotli" => Ok(Self::Brotli),
+            _ => Err(CompressionError::UnknownAlgorithm(s.to_string())),
+        }
+    }
+}
+
+impl std::fmt::Display for Algorithm {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Deflate => "deflate",
+            Self::Zstd => "zstd",
+            Self::Brotli => "brotli",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Returns true when data starts with the compressed payload magic
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.len() >= HEADER_LENGTH && data.starts_with(MAGIC)
+}
+
+/// Compresses data with default level of the algorithm when `level` is `None`
+pub fn compress(
+    data: &[u8],
+    algorithm: Algorithm,
+    level: Option<u32>,
+) -> Result<Vec<u8>, CompressionError> {
+    let (lowest, highest, default) = algorithm.levels();
+    let level = level.unwrap_or(default);
+    if level < lowest || level > highest {
+        return Err(CompressionError::InvalidLevel { algorithm, level });
+    }
+    if data.len() as u64 > MAX_LENGTH {
+        return Err(CompressionError::TooLarge(data.len() as u64));
+    }
+    let mut compressed = Vec::with_capacity(HEADER_LENGTH + data.len() / 2);
+    compressed.extend_from_slice(MAGIC);
+    compressed.extend_from_slice(&[VERSION, algorithm.code()]);
+    compressed.extend_from_slice(&(data.len() as u64).to_be_bytes());
+    match algorithm {
+        Algorithm::Deflate => {
+            let mut encoder = ZlibEncoder::new(compressed, Compression::new(level));
+            encoder.write_all(data)?;
+            Ok(encoder.finish()?)
+        }
+        Algorithm::Zstd => compress_zstd(data, level, compressed),
+        Algorithm::Brotli => compress_brotli(data, level, compressed),
+    }
+}
+
+/// Restores data compressed by `compress`, algorithm is taken from the header
+pub fn decompress(data: &[u8]) -> Result<Vec<u8>, CompressionError> {
+    if !is_compressed(data) {
+        return Err(CompressionError::InvalidHeader);
+    }
+    let (header, compressed) = data.split_at(HEADER_LENGTH);
+    if header[MAGIC.len()] != VERSION {
+        return Err(CompressionError::UnsupportedVersion(header[MAGIC.len()]));
+    }
+    let algorithm = std::convert::TryFrom::try_from(header[MAGIC.len() + 1])?;
+    let length = u64::from_be_bytes(header[MAGIC.len() + 2..].try_into().expect("8 bytes"));
+    if length > MAX_LENGTH {
+        return Err(CompressionError::TooLarge(length));
+    }
+    let reader: Box<dyn Read> = match algorithm {
+        Algorithm::Deflate => Box::new(ZlibDecoder::new(compressed)),
+        Algorithm::Zstd => zstd_reader(compressed)?,
+        Algorithm::Brotli => brotli_reader(compressed)?,
+    };
+    // reading one byte more than expected catches payloads that decompress to a larger size
+    let mut decompressed = Vec::new();
+    reader.take(length + 1).read_to_end(&mut decompressed)?;
+    if decompressed.len() as u64 != length {
+        return Err(CompressionError::LengthMismatch);
+    }
+    Ok(decompressed)
+}
+
+#[cfg(feature = "zstd")]
+fn compress_zstd(data: &[u8], level: u32, out: Vec<u8>) -> Result<Vec<u8>, CompressionError> {
+    let mut encoder = zstd::Encoder::new(out, level as i32)?;
+    encoder.write_all(data)?;
+    Ok(encoder.finish()?)
+}
+
+#[cfg(not(feature = "zstd"))]
+fn compress_zstd(_: &[u8], _: u32, _: Vec<u8>) -> Result<Vec<u8>, CompressionError> {
+    Err(CompressionError::UnavailableAlgorithm(Algorithm::Zstd))
+}
+
+#[cfg(feature = "zstd")]
+fn zstd_reader(data: &[u8]) -> Result<Box<dyn Read + '_>, CompressionError> {
+    Ok(Box::new(zstd::Decoder::new(data)?))
+}
+
+#[cfg(not(feature = "zstd"))]
+fn zstd_reader(_: &[u8]) -> Result<Box<dyn Read + '_>, CompressionError> {
+    Err(CompressionError::UnavailableAlgorithm(Algorithm::Zstd))
+}
+
+#[cfg(feature = "brotli")]
+fn compress_brotli(data: &[u8], level: u32, out: Vec<u8>) -> Result<Vec<u8>, CompressionError> {
+    let mut encoder = brotli::CompressorWriter::new(out, 4096, level, 22);
+    encoder.write_all(data)?;
+    encoder.flush()?;
+    Ok(encoder.into_inner())
+}
+
+#[cfg(not(feature = "brotli"))]
+fn compress_brotli(_: &[u8], _: u32, _: Vec<u8>) -> Result<Vec<u8>, CompressionError> {
+    Err(CompressionError::UnavailableAlgorithm(Algorithm::Brotli))
+}
+
+#[cfg(feature = "brotli")]
+fn brotli_reader(data: &[u8]) -> Result<Box<dyn Read + '_>, CompressionError> {
+    Ok(Box::new(brotli::Decompressor::new(data, 4096)))
+}
+
+#[cfg(not(feature = "brotli"))]
+fn brotli_reader(_: &[u8]) -> Result<Box<dyn Read + '_>, CompressionError> {
+    Err(CompressionError::UnavailableAlgorithm(Algorithm::Brotli))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use crate::png::Png;
+    use std::convert::TryFrom;
+    use std::str::FromStr;
+
+    const TEXT: &[u8] =
+        br#"{"message": "repeated", "items": ["repeated", "repeated", "repeated"]}"#;
+
+    fn round_trip_through_png(algorithm: Algorithm) {
+        let payload = TEXT.repeat(20);
+        let compressed = compress(&payload, algorithm, None).unwrap();
+        assert!(is_compressed(&compressed));
+        assert!(compressed.len() < payload.len() / 4);
+        let mut png = Png::from_file("../images/dice.png").unwrap();
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        png.insert_chunk_before_iend(Chunk::new(chunk_type, &compressed))
+            .unwrap();
+        let png = Png::try_from(&png.as_bytes()[..]).unwrap();
+        let chunk = png.chunk_by_type(&ChunkType::from_str("ruSt").unwrap());
+        assert_eq!(decompress(chunk.unwrap().data()).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_deflate_round_trip() {
+        round_trip_through_png(Algorithm::Deflate);
+    }
+
+    #[cfg(feature = "zstd")]
+    #[test]
+    fn test_zstd_round_trip() {
+        round_trip_through_png(Algorithm::Zstd);
+    }
+
+    #[cfg(feature = "brotli")]
+    #[test]
+    fn test_brotli_round_trip() {
+        round_trip_through_png(Algorithm::Brotli);
+    }
+
+    #[test]
+    fn test_levels() {
+        for level in 0..=9 {
+            let compressed = compress(TEXT, Algorithm::Deflate, Some(level)).unwrap();
+            assert_eq!(decompress(&compressed).unwrap(), TEXT);
+        }
+        let result = compress(TEXT, Algorithm::Deflate, Some(10));
+        assert!(matches!(result, Err(CompressionError::InvalidLevel { .. })));
+    }
+
+    #[test]
+    fn test_length_mismatch() {
+        let mut compressed = compress(TEXT, Algorithm::Deflate, None).unwrap();
+        compressed[HEADER_LENGTH - 1] -= 1;
+        let result = decompress(&compressed);
+        assert!(matches!(result, Err(CompressionError::LengthMismatch)));
+    }
+
+    #[test]
+    fn test_length_too_large() {
+        let mut compressed = compress(TEXT, Algorithm::Deflate, None).unwrap();
+        compressed[MAGIC.len() + 2..HEADER_LENGTH].copy_from_slice(&u64::MAX.to_be_bytes());
+        let result = decompress(&compressed);
+        assert!(matches!(result, Err(CompressionError::TooLarge(u64::MAX))));
+    }
+
+    #[test]
+    fn test_compress_too_large() {
+        let data = vec![0; MAX_LENGTH as usize + 1];
+        let result = compress(&data, Algorithm::Deflate, None);
+        assert!(
+            matches!(result, Err(CompressionError::TooLarge(length)) if length == MAX_LENGTH + 1)
+        );
+    }
+
+    #[test]
+    fn test_invalid_data() {
+        assert!(!is_compressed(TEXT));
+        assert!(matches!(
+            decompress(TEXT),
+            Err(CompressionError::InvalidHeader)
+        ));
+        let mut compressed = compress(TEXT, Algorithm::Deflate, None).unwrap();
+        compressed[MAGIC.len() + 1] = 9;
+        assert!(decompress(&compressed).is_err());
+        assert!("lzma".parse::<Algorithm>().is_err());
+    }
+
+    #[cfg(not(feature = "zstd"))]
+    #[test]
+    fn test_unavailable_algorithm() {
+        assert!(!Algorithm::Zstd.is_available());
+        let result = compress(TEXT, Algorithm::Zstd, None);
+        assert!(matches!(
+            result,
+            Err(CompressionError::UnavailableAlgorithm(Algorithm::Zstd))
+        ));
+    }
+}
@@ -0,0 +1,298 @@
+//! Passphrase based authenticated encryption of message payloads
+//!
+//! Encrypted payload is self-describing, all numbers are big endian:
+//!
+//! | bytes | field                                        |
+//! |-------|----------------------------------------------|
+//! | 8     | magic `pngmeENC`                             |
+//! | 1     | format version, currently 1                  |
+//! | 1     | key derivation function, 1 is Argon2id       |
+//! | 12    | Argon2 memory cost (KiB), iterations, lanes  |
+//! | 16    | salt                                         |
+//! | 1     | cipher, 1 is XChaCha20-Poly1305              |
+//! | 24    | nonce                                        |
+//! | rest  | ciphertext followed by 16 bytes tag          |
+//!
+//! The whole header is authenticated as associated data, so changing any of its bytes makes
+//! decryption fail the same way a wrong passphrase does. Callers can authenticate context stored
+//! outside of the payload the same way.
+use argon2::{Algorithm, Argon2, Params, Version};
+use byteorder::ByteOrder;
+use chacha20poly1305::aead::{Aead, AeadCore, KeyInit, OsRng, Payload};
+use chacha20poly1305::{XChaCha20Poly1305, XNonce};
+
+use crate::error::CryptoError;
+
+const MAGIC: &[u8; 8] = b"pngmeENC";
+const VERSION: u8 = 1;
+const KDF_ARGON2ID: u8 = 1;
+const CIPHER_XCHACHA20POLY1305: u8 = 1;
+const SALT_LENGTH: usize = 16;
+const NONCE_LENGTH: usize = 24;
+const KEY_LENGTH: usize = 32;
+const HEADER_LENGTH: usize = MAGIC.len() + 2 + 12 + SALT_LENGTH + 1 + NONCE_LENGTH;
+/// Cost parameters are read from untrusted headers, larger ones could exhaust memory or time
+const MAX_MEMORY_KIB: u32 = 1 << 20;
+const MAX_ITERATIONS: u32 = 64;
+const MAX_LANES: u32 = 64;
+
+/// Argon2id cost parameters stored in the header of every encrypted payload
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct KdfParams {
+    memory_kib: u32,
+    iterations: u32,
+    lanes: u32,
+}
+
+impl KdfParams {
+    /// Fails for parameters Argon2 rejects and above 1 GiB of memory, 64 iterations or 64 lanes
+    pub fn new(memory_kib: u32, iterations: u32, lanes: u32) -> Result<Self, CryptoError> {
+        if memory_kib > MAX_MEMORY_KIB || iterations > MAX_ITERATIONS || lanes > MAX_LANES {
+            return Err(CryptoError::KdfParamsTooLarge);
+        }
+        let params = Self {
+            memory_kib,
+            iterations,
+            lanes,
+        };
+        params.to_argon2()?;
+        Ok(params)
+    }
+    pub fn memory_kib(&self) -> u32 {
+        self.memory_kib
+    }
+    pub fn iterations(&self) -> u32 {
+        self.iterations
+    }
+    pub fn lanes(&self) -> u32 {
+        self.lanes
+    }
+
+    fn to_argon2(self) -> Result<Params, CryptoError> {
+        Params::new(
+            self.memory_kib,
+            self.iterations,
+            self.lanes,
+            Some(KEY_LENGTH),
+        )
+        .map_err(|_| CryptoError::InvalidKdfParams)
+    }
+}
+
+impl Default for KdfParams {
+    fn default() -> Self {
+        Self {
+            memory_kib: Params::DEFAULT_M_COST,
+            iterations: Params::DEFAULT_T_COST,
+            lanes: Params::DEFAULT_P_COST,
+        }
+    }
+}
+
+/// Returns true when data starts with the encrypted payload magic
+pub fn is_encrypted(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+pub fn encrypt(plaintext: &[u8], passphrase: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    encrypt_with_params(plaintext, passphrase, KdfParams::default())
+}
+
+pub fn encrypt_with_params(
+    plaintext: &[u8],
+    passphrase: &[u8],
+    params: KdfParams,
+) -> Result<Vec<u8>, CryptoError> {
+    encrypt_with_context(plaintext, passphrase, params, &[])
+}
+
+/// Encrypts `plaintext` and authenticates `context` with it, decryption needs the same context
+pub fn encrypt_with_context(
+    plaintext: &[u8],
+    passphrase: &[u8],
+    params: KdfParams,
+    context: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    let mut salt = [0; SALT_LENGTH];
+    getrandom::getrandom(&mut salt).map_err(|_| CryptoError::RandomSource)?;
+    let nonce = XChaCha20Poly1305::generate_nonce(&mut OsRng);
+    let mut header = Vec::with_capacity(HEADER_LENGTH);
+    header.extend_from_slice(MAGIC);
+    header.extend_from_slice(&[VERSION, KDF_ARGON2ID]);
+    header.extend_from_slice(&params.memory_kib.to_be_bytes());
+    header.extend_from_slice(&params.iterations.to_be_bytes());
+    header.extend_from_slice(&params.lanes.to_be_bytes());
+    header.extend_from_slice(&salt);
+    header.push(CIPHER_XCHACHA20POLY1305);
+    header.extend_from_slice(&nonce);
+    let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, &salt, params)?.into());
+    let aad = [&header[..], context].concat();
+    let payload = Payload {
+        msg: plaintext,
+        aad: &aad,
+    };
+    let ciphertext = cipher
+        .encrypt(&nonce, payload)
+        .map_err(|_| CryptoError::EncryptionFailed)?;
+    header.extend(ciphertext);
+    Ok(header)
+}
+
+pub fn decrypt(data: &[u8], passphrase: &[u8]) -> Result<Vec<u8>, CryptoError> {
+    decrypt_with_context(data, passphrase, &[])
+}
+
+/// Decrypts data encrypted with `encrypt_with_context`, fails when `context` differs
+pub fn decrypt_with_context(
+    data: &[u8],
+    passphrase: &[u8],
+    context: &[u8],
+) -> Result<Vec<u8>, CryptoError> {
+    if !is_encrypted(data) || data.len() < HEADER_LENGTH {
+        return Err(CryptoError::InvalidHeader);
+    }
+    let (header, ciphertext) = data.split_at(HEADER_LENGTH);
+    let mut rest = &header[MAGIC.len()..];
+    let mut take = |count: usize| {
+        let (taken, remaining) = rest.split_at(count);
+        rest = remaining;
+        taken
+    };
+    let version = take(1)[0];
+    if version != VERSION {
+        return Err(CryptoError::UnsupportedVersion(version));
+    }
+    let kdf = take(1)[0];
+    if kdf != KDF_ARGON2ID {
+        return Err(CryptoError::UnsupportedKdf(kdf));
+    }
+    let params = KdfParams::new(
+        byteorder::BigEndian::read_u32(take(4)),
+        byteorder::BigEndian::read_u32(take(4)),
+        byteorder::BigEndian::read_u32(take(4)),
+    )?;
+    let salt = take(SALT_LENGTH);
+    let cipher_id = take(1)[0];
+    if cipher_id != CIPHER_XCHACHA20POLY1305 {
+        return Err(CryptoError::UnsupportedCipher(cipher_id));
+    }
+    let nonce = XNonce::from_slice(take(NONCE_LENGTH));
+    let cipher = XChaCha20Poly1305::new(&derive_key(passphrase, salt, params)?.into());
+    let aad = [header, context].concat();
+    let payload = Payload {
+        msg: ciphertext,
+        aad: &aad,
+    };
+    cipher
+        .decrypt(nonce, payload)
+        .map_err(|_| CryptoError::DecryptionFailed)
+}
+
+fn derive_key(
+    passphrase: &[u8],
+    salt: &[u8],
+    params: KdfParams,
+) -> Result<[u8; KEY_LENGTH], CryptoError> {
+    let argon2 = Argon2::new(Algorithm::Argon2id, Version::V0x13, params.to_argon2()?);
+    let mut key = [0; KEY_LENGTH];
+    argon2
+        .hash_password_into(passphrase, salt, &mut key)
+        .map_err(|e| CryptoError::KeyDerivation(e.to_string()))?;
+    Ok(key)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fast_params() -> KdfParams {
+        KdfParams::new(64, 1, 1).unwrap()
+    }
+
+    fn encrypted() -> Vec<u8> {
+        encrypt_with_params(b"secret message", b"passphrase", fast_params()).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let data = encrypted();
+        assert!(is_encrypted(&data));
+        assert_eq!(data.len(), HEADER_LENGTH + 14 + 16);
+        assert_eq!(decrypt(&data, b"passphrase").unwrap(), b"secret message");
+    }
+
+    #[test]
+    fn test_ciphertext_does_not_contain_plaintext() {
+        let data = encrypted();
+        assert!(!data.windows(6).any(|window| window == b"secret"));
+        assert_ne!(encrypted(), data);
+    }
+
+    #[test]
+    fn test_wrong_passphrase() {
+        let result = decrypt(&encrypted(), b"wrong");
+        assert!(matches!(result, Err(CryptoError::DecryptionFailed)));
+    }
+
+    #[test]
+    fn test_tampered_ciphertext_and_header() {
+        let mut data = encrypted();
+        let last = data.len() - 1;
+        data[last] ^= 1;
+        let result = decrypt(&data, b"passphrase");
+        assert!(matches!(result, Err(CryptoError::DecryptionFailed)));
+
+        let mut data = encrypted();
+        data[HEADER_LENGTH - 1] ^= 1; // last byte of nonce
+        let result = decrypt(&data, b"passphrase");
+        assert!(matches!(result, Err(CryptoError::DecryptionFailed)));
+    }
+
+    #[test]
+    fn test_context() {
+        let data = encrypt_with_context(b"secret", b"passphrase", fast_params(), b"ctx").unwrap();
+        assert_eq!(
+            decrypt_with_context(&data, b"passphrase", b"ctx").unwrap(),
+            b"secret"
+        );
+        let result = decrypt_with_context(&data, b"passphrase", b"other");
+        assert!(matches!(result, Err(CryptoError::DecryptionFailed)));
+        assert!(decrypt(&data, b"passphrase").is_err());
+    }
+
+    #[test]
+    fn test_invalid_header() {
+        let result = decrypt(b"plain message", b"passphrase");
+        assert!(matches!(result, Err(CryptoError::InvalidHeader)));
+        let mut data = encrypted();
+        data[8] = 2;
+        let result = decrypt(&data, b"passphrase");
+        assert!(matches!(result, Err(CryptoError::UnsupportedVersion(2))));
+    }
+
+    #[test]
+    fn test_invalid_params() {
+        assert!(KdfParams::new(0, 1, 1).is_err());
+        assert!(KdfParams::new(64, 0, 1).is_err());
+        assert!(KdfParams::new(MAX_MEMORY_KIB, MAX_ITERATIONS, MAX_LANES).is_ok());
+        assert!(matches!(
+            KdfParams::new(MAX_MEMORY_KIB + 1, 1, 1),
+            Err(CryptoError::KdfParamsTooLarge)
+        ));
+        assert!(KdfParams::new(64, MAX_ITERATIONS + 1, 1).is_err());
+        assert!(KdfParams::new(64, 1, MAX_LANES + 1).is_err());
+    }
+
+    #[test]
+    fn test_oversized_params_in_header() {
+        let mut data = encrypted();
+        // memory cost follows magic, version and key derivation function
+        data[10..14].copy_from_slice(&u32::MAX.to_be_bytes());
+        let result = decrypt(&data, b"passphrase");
+        assert!(matches!(result, Err(CryptoError::KdfParamsTooLarge)));
+        let mut data = encrypted();
+        data[14..18].copy_from_slice(&u32::MAX.to_be_bytes());
+        let result = decrypt(&data, b"passphrase");
+        assert!(matches!(result, Err(CryptoError::KdfParamsTooLarge)));
+    }
+}
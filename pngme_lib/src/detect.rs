@@ -0,0 +1,634 @@
+//! Heuristics that look for data hidden in a `Png`
+//!
+//! Chunk layout is checked for private, unknown and high entropy chunks, data after IEND, large
+//! text chunks and bytes after the end of the zlib stream in IDAT. Pixels of 8 and 16 bit
+//! non-indexed images go through the chi-square attack and RS analysis that detect changes of
+//! least significant bits. Every finding has a score from 0 to 1, how likely it hides something.
+use std::convert::TryFrom;
+use std::io::Read;
+
+use flate2::read::ZlibDecoder;
+
+use crate::ihdr::ColorType;
+use crate::pixels::{self, PixelBuffer};
+use crate::png::Png;
+use crate::text::TextualChunk;
+
+/// Text longer than this many bytes is unusual for metadata
+const TEXT_LIMIT: usize = 4096;
+/// Chunks shorter than this are too small for a meaningful entropy
+const ENTROPY_MIN_LENGTH: usize = 32;
+/// Entropy relative to its maximum for data length above which data looks encrypted or packed
+const ENTROPY_THRESHOLD: f64 = 0.9;
+/// Chi-square attack needs this many samples to tell noise from embedding
+const CHI_SQUARE_MIN_SAMPLES: usize = 2048;
+/// Probability of chi-square attack reported as finding
+const CHI_SQUARE_THRESHOLD: f64 = 0.5;
+/// Share of changed samples estimated by RS analysis reported as finding
+const RS_THRESHOLD: f64 = 0.05;
+
+#[derive(Debug, Clone, PartialEq)]
+pub enum FindingKind {
+    PrivateChunk(String),
+    UnknownChunk(String),
+    /// Non-standard chunk data with entropy in bits per byte
+    HighEntropy {
+        chunk_type: String,
+        entropy: f64,
+    },
+    /// Chunks and bytes after the first IEND
+    DataAfterIend {
+        length: usize,
+    },
+    OversizedText {
+        chunk_type: String,
+        length: usize,
+    },
+    /// Bytes in IDAT after the end of the zlib stream
+    ImageDataTrailer {
+        length: usize,
+    },
+    /// Chance that least significant bits of the first `samples` samples were replaced
+    LsbChiSquare {
+        probability: f64,
+        samples: usize,
+    },
+    /// Estimated share of samples carrying embedded bits
+    LsbRs {
+        rate: f64,
+    },
+}
+
+impl std::fmt::Display for FindingKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::PrivateChunk(chunk_type) => write!(f, "{} is a private chunk", chunk_type),
+            Self::UnknownChunk(chunk_type) => write!(f, "{} is not a standard chunk", chunk_type),
+            Self::HighEntropy {
+                chunk_type,
+                entropy,
+            } => write!(
+                f,
+                "{} data looks random, entropy {:.2} bits per byte",
+                chunk_type, entropy
+            ),
+            Self::DataAfterIend { length } => write!(f, "{} bytes after IEND", length),
+            Self::OversizedText { chunk_type, length } => {
+                write!(f, "{} holds {} bytes of text", chunk_type, length)
+            }
+            Self::ImageDataTrailer { length } => {
+                write!(f, "{} bytes in IDAT after the end of image data", length)
+            }
+            Self::LsbChiSquare {
+                probability,
+                samples,
+            } => write!(
+                f,
+                "chi-square attack: least significant bits of first {} samples replaced with \
+                 probability {:.2}",
+                samples, probability
+            ),
+            Self::LsbRs { rate } => write!(
+                f,
+                "RS analysis: about {:.1}% of samples carry embedded bits",
+                rate * 100.0
+            ),
+        }
+    }
+}
+
+/// Something in a `Png` that may hide data, optionally pointing at a chunk
+#[derive(Debug, Clone, PartialEq)]
+pub struct Finding {
+    kind: FindingKind,
+    chunk_index: Option<usize>,
+    score: f64,
+}
+
+impl Finding {
+    fn new(kind: FindingKind, chunk_index: Option<usize>, score: f64) -> Self {
+        Self {
+            kind,
+            chunk_index,
+            score: score.clamp(0.0, 1.0),
+        }
+    }
+    pub fn kind(&self) -> &FindingKind {
+        &self.kind
+    }
+    pub fn chunk_index(&self) -> Option<usize> {
+        self.chunk_index
+    }
+    /// How likely the finding hides data, from 0 to 1
+    pub fn score(&self) -> f64 {
+        self.score
+    }
+}
+
+impl std::fmt::Display for Finding {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        // Chunks are numbered from 1 like in the output of print
+        match self.chunk_index {
+            Some(index) => write!(
+                f,
+                "score {:.2} [chunk {}]: {}",
+                self.score,
+                index + 1,
+                self.kind
+            ),
+            None => write!(f, "score {:.2}: {}", self.score, self.kind),
+        }
+    }
+}
+
+/// Runs every heuristic on `png`, findings are sorted by score from the highest
+pub fn scan(png: &Png) -> Vec<Finding> {
+    let mut findings = scan_chunks(png);
+    findings.extend(scan_image_data(png));
+    if let Some(image) = read_pixels(png) {
+        findings.extend(scan_pixels(&image));
+    }
+    findings.sort_by(|a, b| b.score.total_cmp(&a.score));
+    findings
+}
+
+fn scan_chunks(png: &Png) -> Vec<Finding> {
+    let mut findings = vec![];
+    for (index, chunk) in png.chunks().iter().enumerate() {
+        let chunk_type = chunk.chunk_type().to_string();
+        if !chunk.chunk_type().is_known() {
+            let (kind, score) = match chunk.chunk_type().is_public() {
+                true => (FindingKind::UnknownChunk(chunk_type.clone()), 0.4),
+                false => (FindingKind::PrivateChunk(chunk_type.clone()), 0.6),
+            };
+            findings.push(Finding::new(kind, Some(index), score));
+            if chunk.data().len() >= ENTROPY_MIN_LENGTH {
+                let (entropy, relative) = entropy(chunk.data());
+                if relative >= ENTROPY_THRESHOLD {
+                    let kind = FindingKind::HighEntropy {
+                        chunk_type,
+                        entropy,
+                    };
+                    findings.push(Finding::new(kind, Some(index), relative));
+                }
+            }
+        } else if TextualChunk::is_textual(chunk) {
+            let length =
+                TextualChunk::try_from(chunk).map_or(chunk.data().len(), |text| text.text().len());
+            if length > TEXT_LIMIT {
+                let score = 1.0 - 0.5 * TEXT_LIMIT as f64 / length as f64;
+                let kind = FindingKind::OversizedText { chunk_type, length };
+                findings.push(Finding::new(kind, Some(index), score));
+            }
+        }
+    }
+    let chunks = png.chunks();
+    let iend = chunks
+        .iter()
+        .position(|chunk| chunk.chunk_type().bytes() == *b"IEND");
+    if let Some(iend) = iend {
+        let after: usize = chunks[iend + 1..]
+            .iter()
+            .map(|chunk| chunk.as_bytes().len())
+            .sum();
+        let length = after + png.trailing_data().len();
+        if length > 0 {
+            let index = Some(iend + 1).filter(|&index| index < chunks.len());
+            findings.push(Finding::new(
+                FindingKind::DataAfterIend { length },
+                index,
+                0.9,
+            ));
+        }
+    }
+    findings
+}
+
+/// Looks for bytes that follow the zlib stream of image data
+fn scan_image_data(png: &Png) -> Option<Finding> {
+    let compressed = pixels::compressed_data(png).ok()?;
+    let mut decoder = ZlibDecoder::new(&compressed[..]);
+    std::io::copy(&mut decoder.by_ref(), &mut std::io::sink()).ok()?;
+    let length = compressed.len() - decoder.total_in() as usize;
+    if length == 0 {
+        return None;
+    }
+    let index = png
+        .chunks()
+        .iter()
+        .rposition(|chunk| chunk.chunk_type().bytes() == *b"IDAT");
+    // Some encoders pad the stream with zeros
+    let padding = compressed[compressed.len() - length..]
+        .iter()
+        .all(|&b| b == 0);
+    let score = if padding { 0.3 } else { 0.8 };
+    Some(Finding::new(
+        FindingKind::ImageDataTrailer { length },
+        index,
+        score,
+    ))
+}
+
+/// Shannon entropy in bits per byte and its share of the maximum possible for data length
+fn entropy(data: &[u8]) -> (f64, f64) {
+    let mut counts = [0usize; 256];
+    for &byte in data {
+        counts[usize::from(byte)] += 1;
+    }
+    let length = data.len() as f64;
+    let entropy: f64 = counts
+        .iter()
+        .filter(|&&count| count > 0)
+        .map(|&count| {
+            let p = count as f64 / length;
+            -p * p.log2()
+        })
+        .sum();
+    (entropy, entropy / length.log2().min(8.0))
+}
+
+/// Pixels of images whose samples have a least significant bit of their own
+fn read_pixels(png: &Png) -> Option<PixelBuffer> {
+    let ihdr = png.header().ok()?;
+    if ihdr.color_type() == ColorType::Indexed || ihdr.bit_depth() < 8 {
+        return None;
+    }
+    pixels::decode(png).ok()
+}
+
+fn scan_pixels(image: &PixelBuffer) -> Vec<Finding> {
+    let mut findings = vec![];
+    if let Some((probability, samples)) = chi_square_attack(image) {
+        if probability >= CHI_SQUARE_THRESHOLD {
+            let kind = FindingKind::LsbChiSquare {
+                probability,
+                samples,
+            };
+            findings.push(Finding::new(kind, None, probability));
+        }
+    }
+    if let Some(rate) = rs_analysis(image) {
+        if rate >= RS_THRESHOLD {
+            // Even a few percent of samples is a strong sign, half of them is certain
+            findings.push(Finding::new(FindingKind::LsbRs { rate }, None, rate * 2.0));
+        }
+    }
+    findings
+}
+
+/// Every sample of the image in row order
+fn samples(image: &PixelBuffer) -> Vec<u16> {
+    let channels = image.format().channels();
+    (0..image.height())
+        .flat_map(|y| (0..image.width()).map(move |x| (x, y)))
+        .flat_map(|(x, y)| (0..channels).map(move |channel| (x, y, channel)))
+        .map(|(x, y, channel)| image.sample(x, y, channel).expect("sample is inside image"))
+        .collect()
+}
+
+/// Chi-square attack of Westfeld and Pfitzmann on growing prefixes of samples
+///
+/// Replacing least significant bits equalizes counts of values that differ only in that bit.
+/// Returns the highest probability of embedding and the number of samples it was found in.
+fn chi_square_attack(image: &PixelBuffer) -> Option<(f64, usize)> {
+    let samples: Vec<u8> = samples(image).iter().map(|&s| s as u8).collect();
+    let mut best: Option<(f64, usize)> = None;
+    let mut length = samples.len();
+    while length >= CHI_SQUARE_MIN_SAMPLES {
+        if let Some(probability) = chi_square_probability(&samples[..length]) {
+            if best.is_none_or(|(best, _)| probability > best) {
+                best = Some((probability, length));
+            }
+        }
+        length /= 2;
+    }
+    best
+}
+
+fn chi_square_probability(samples: &[u8]) -> Option<f64> {
+    let mut histogram = [0usize; 256];
+    for &sample in samples {
+        histogram[usize::from(sample)] += 1;
+    }
+    let mut chi_square = 0.0;
+    let mut categories = 0;
+    for pair in histogram.chunks_exact(2) {
+        let expected = (pair[0] + pair[1]) as f64 / 2.0;
+        if expected < 5.0 {
+            continue;
+        }
+        chi_square += (pair[0] as f64 - expected).powi(2) / expected;
+        categories += 1;
+    }
+    if categories < 2 {
+        return None;
+    }
+    let freedom = f64::from(categories - 1);
+    Some(1.0 - lower_gamma(freedom / 2.0, chi_square / 2.0))
+}
+
+/// Regularized lower incomplete gamma function P(a, x)
+fn lower_gamma(a: f64, x: f64) -> f64 {
+    if x <= 0.0 {
+        return 0.0;
+    }
+    let prefix = (-x + a * x.ln() - ln_gamma(a)).exp();
+    if x < a + 1.0 {
+        let mut term = 1.0 / a;
+        let mut sum = term;
+        let mut n = a;
+        for _ in 0..1000 {
+            n += 1.0;
+            term *= x / n;
+            sum += term;
+            if term.abs() < sum.abs() * 1e-12 {
+                break;
+            }
+        }
+        return (sum * prefix).min(1.0);
+    }
+    // Continued fraction of the upper function evaluated with modified Lentz's method
+    let tiny = 1e-300;
+    let mut b = x + 1.0 - a;
+    let mut c = 1.0 / tiny;
+    let mut d = 1.0 / b;
+    let mut h = d;
+    for i in 1..1000 {
+        let i = f64::from(i);
+        let an = -i * (i - a);
+        b += 2.0;
+        d = an * d + b;
+        if d.abs() < tiny {
+            d = tiny;
+        }
+        c = b + an / c;
+        if c.abs() < tiny {
+            c = tiny;
+        }
+        d = 1.0 / d;
+        let delta = d * c;
+        h *= delta;
+        if (delta - 1.0).abs() < 1e-12 {
+            break;
+        }
+    }
+    (1.0 - prefix * h).max(0.0)
+}
+
+/// Logarithm of the gamma function for positive `x`, Lanczos approximation
+fn ln_gamma(x: f64) -> f64 {
+    const COEFFICIENTS: [f64; 9] = [
+        0.999_999_999_999_809_9,
+        676.520_368_121_885_1,
+        -1_259.139_216_722_402_8,
+        771.323_428_777_653_1,
+        -176.615_029_162_140_6,
+        12.507_343_278_686_905,
+        -0.138_571_095_265_720_12,
+        9.984_369_578_019_572e-6,
+        1.505_632_735_149_311_6e-7,
+    ];
+    let x = x - 1.0;
+    let t = x + 7.5;
+    let series = COEFFICIENTS[1..]
+        .iter()
+        .enumerate()
+        .fold(COEFFICIENTS[0], |sum, (i, c)| {
+            sum + c / (x + i as f64 + 1.0)
+        });
+    0.5 * (2.0 * std::f64::consts::PI).ln() + (x + 0.5) * t.ln() - t + series.ln()
+}
+
+/// RS analysis of Fridrich, Goljan and Du on groups of four horizontally adjacent samples
+///
+/// Flipping least significant bits makes smooth groups noisier in a natural image, the
+/// difference shrinks as more bits are replaced. Returns the estimated share of samples used.
+fn rs_analysis(image: &PixelBuffer) -> Option<f64> {
+    const MASK: [i32; 4] = [0, 1, 1, 0];
+    let channels = image.format().channels();
+    let max = (1i32 << image.format().bit_depth()) - 1;
+    let mut groups = vec![];
+    for y in 0..image.height() {
+        for channel in 0..channels {
+            for x in (0..image.width().saturating_sub(3)).step_by(4) {
+                let sample = |dx| i32::from(image.sample(x + dx, y, channel).unwrap_or(0));
+                let group = [sample(0), sample(1), sample(2), sample(3)];
+                // Saturated areas like backgrounds and opaque alpha distort the statistics
+                if group.iter().all(|&value| value > 0 && value < max) {
+                    groups.push(group);
+                }
+            }
+        }
+    }
+    if groups.is_empty() {
+        return None;
+    }
+    let flipped: Vec<[i32; 4]> = groups
+        .iter()
+        .map(|group| {
+            let mut group = *group;
+            group.iter_mut().for_each(|value| *value ^= 1);
+            group
+        })
+        .collect();
+    let negative = MASK.map(|m| -m);
+    let d0 = regular_minus_singular(&groups, &MASK);
+    let d1 = regular_minus_singular(&flipped, &MASK);
+    let negative_d0 = regular_minus_singular(&groups, &negative);
+    let negative_d1 = regular_minus_singular(&flipped, &negative);
+    let a = 2.0 * (d1 + d0);
+    let b = negative_d0 - negative_d1 - d1 - 3.0 * d0;
+    let c = d0 - negative_d0;
+    let z = if a.abs() < f64::EPSILON {
+        if b.abs() < f64::EPSILON {
+            return None;
+        }
+        -c / b
+    } else {
+        let discriminant = b * b - 4.0 * a * c;
+        if discriminant < 0.0 {
+            return None;
+        }
+        let roots = [
+            (-b + discriminant.sqrt()) / (2.0 * a),
+            (-b - discriminant.sqrt()) / (2.0 * a),
+        ];
+        match roots[0].abs() < roots[1].abs() {
+            true => roots[0],
+            false => roots[1],
+        }
+    };
+    Some((z / (z - 0.5)).clamp(0.0, 1.0))
+}
+
+/// Share of regular groups minus share of singular groups after applying `mask`
+fn regular_minus_singular(groups: &[[i32; 4]], mask: &[i32; 4]) -> f64 {
+    let smoothness =
+        |group: &[i32; 4]| -> i32 { group.windows(2).map(|pair| (pair[1] - pair[0]).abs()).sum() };
+    let mut difference = 0i64;
+    for group in groups {
+        let mut flipped = *group;
+        for (value, &m) in flipped.iter_mut().zip(mask.iter()) {
+            *value = match m {
+                1 => *value ^ 1,
+                -1 => ((*value + 1) ^ 1) - 1,
+                _ => *value,
+            };
+        }
+        match smoothness(&flipped).cmp(&smoothness(group)) {
+            std::cmp::Ordering::Greater => difference += 1,
+            std::cmp::Ordering::Less => difference -= 1,
+            std::cmp::Ordering::Equal => {}
+        }
+    }
+    difference as f64 / groups.len() as f64
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::Ihdr;
+    use crate::lsb;
+    use crate::test_utils::{chunk, dice};
+
+    fn random_bytes(length: usize) -> Vec<u8> {
+        let mut seed = 7u32;
+        (0..length)
+            .map(|_| {
+                seed ^= seed << 13;
+                seed ^= seed >> 17;
+                seed ^= seed << 5;
+                seed as u8
+            })
+            .collect()
+    }
+
+    fn kinds(png: &Png) -> Vec<FindingKind> {
+        scan(png).into_iter().map(|finding| finding.kind).collect()
+    }
+
+    #[test]
+    fn test_clean_image() {
+        assert!(scan(&dice()).is_empty());
+    }
+
+    #[test]
+    fn test_chunks() {
+        let mut png = dice();
+        let text = [b"Comment\0".to_vec(), vec![b'a'; 5000]].concat();
+        png.insert_chunk_after_ihdr(chunk("tEXt", &text)).unwrap();
+        png.insert_chunk_after_ihdr(chunk("rAND", &random_bytes(1024)))
+            .unwrap();
+        png.insert_chunk_after_ihdr(chunk("prVt", b"short"))
+            .unwrap();
+        let findings = scan(&png);
+        let kinds: Vec<_> = findings.iter().map(|finding| finding.kind()).collect();
+        assert_eq!(kinds.len(), 4);
+        assert!(matches!(
+            kinds[0],
+            FindingKind::HighEntropy { chunk_type, entropy } if chunk_type == "rAND" && *entropy > 7.5
+        ));
+        assert_eq!(kinds[1], &FindingKind::PrivateChunk("prVt".to_string()));
+        assert_eq!(
+            kinds[2],
+            &FindingKind::OversizedText {
+                chunk_type: "tEXt".to_string(),
+                length: 5000
+            }
+        );
+        assert_eq!(kinds[3], &FindingKind::UnknownChunk("rAND".to_string()));
+        let indexes: Vec<_> = findings.iter().map(|f| f.chunk_index()).collect();
+        assert_eq!(indexes, [Some(2), Some(1), Some(3), Some(2)]);
+    }
+
+    #[test]
+    fn test_data_after_iend() {
+        let bytes = std::fs::read("../images/secret.png").unwrap();
+        let bytes = [bytes, b"trailing".to_vec()].concat();
+        let png = Png::try_from(&bytes[..]).unwrap();
+        let findings = scan(&png);
+        assert_eq!(
+            findings[0].kind(),
+            &FindingKind::DataAfterIend {
+                length: 89 + 12 + 8
+            }
+        );
+        assert_eq!(findings[0].chunk_index(), Some(7));
+        assert!((findings[0].score() - 0.9).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_oversized_header() {
+        let ihdr = Ihdr::new(0x7fff_ffff, 0x7fff_ffff, 8, ColorType::Rgba, false).unwrap();
+        let png = Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            chunk("IDAT", &pixels::deflate(&[0; 16], 6)),
+            chunk("IEND", &[]),
+        ]);
+        assert!(read_pixels(&png).is_none());
+        assert!(scan(&png).is_empty());
+    }
+
+    #[test]
+    fn test_display() {
+        let finding = Finding::new(FindingKind::DataAfterIend { length: 5 }, Some(7), 0.9);
+        assert_eq!(
+            finding.to_string(),
+            "score 0.90 [chunk 8]: 5 bytes after IEND"
+        );
+    }
+
+    #[test]
+    fn test_image_data_trailer() {
+        let mut png = dice();
+        png.insert_chunk_before_iend(chunk("IDAT", b"hidden"))
+            .unwrap();
+        let findings = scan(&png);
+        assert_eq!(findings.len(), 1);
+        assert_eq!(
+            findings[0].kind(),
+            &FindingKind::ImageDataTrailer { length: 6 }
+        );
+        assert_eq!(findings[0].chunk_index(), Some(6));
+    }
+
+    #[test]
+    fn test_lsb_embedding() {
+        let mut png = dice();
+        let capacity = lsb::capacity(&png).unwrap();
+        lsb::embed(&mut png, &random_bytes(capacity / 4)).unwrap();
+        let findings = scan(&png);
+        assert!(findings.iter().any(|finding| matches!(
+            finding.kind(),
+            FindingKind::LsbChiSquare { probability, samples }
+                if *probability > 0.99 && *samples < capacity * 8 / 2
+        )));
+        assert!(findings.iter().any(|finding| matches!(
+            finding.kind(),
+            FindingKind::LsbRs { rate } if *rate > 0.1
+        )));
+
+        let mut png = dice();
+        lsb::embed(&mut png, &random_bytes(capacity)).unwrap();
+        assert!(kinds(&png).iter().any(|kind| matches!(
+            kind,
+            FindingKind::LsbChiSquare { probability, .. } if *probability > 0.99
+        )));
+    }
+
+    #[test]
+    fn test_entropy() {
+        assert_eq!(entropy(&[7; 64]), (0.0, 0.0));
+        let (bits, relative) = entropy(&(0..=255).collect::<Vec<u8>>());
+        assert!((bits - 8.0).abs() < 1e-9 && (relative - 1.0).abs() < 1e-9);
+    }
+
+    #[test]
+    fn test_chi_square_distribution() {
+        // Median of chi-square distribution with 2 degrees of freedom is 2 ln 2
+        let p = lower_gamma(1.0, 2f64.ln());
+        assert!((p - 0.5).abs() < 1e-9);
+        assert!((lower_gamma(5.0, 100.0) - 1.0).abs() < 1e-9);
+        assert!((ln_gamma(5.0) - 24f64.ln()).abs() < 1e-9);
+    }
+}
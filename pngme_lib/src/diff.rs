@@ -0,0 +1,393 @@
+//! Chunk level comparison of two `Png`s
+//!
+//! Chunks are aligned by type and occurrence: the k-th chunk of a type in the old file is paired
+//! with the k-th chunk of the same type in the new one. Paired chunks keep their order unless
+//! they fall outside the longest sequence of pairs that appears in the same order in both files.
+use std::collections::HashSet;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::pixels;
+use crate::png::Png;
+
+/// Position and data length of a chunk in one of the compared files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ChunkPlace {
+    index: usize,
+    length: u32,
+}
+
+impl ChunkPlace {
+    fn new(index: usize, chunk: &Chunk) -> Self {
+        Self {
+            index,
+            length: chunk.length(),
+        }
+    }
+    pub fn index(&self) -> usize {
+        self.index
+    }
+    pub fn length(&self) -> u32 {
+        self.length
+    }
+}
+
+/// Difference of a single chunk, at least one of places before and after the change is present
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ChunkChange {
+    chunk_type: ChunkType,
+    before: Option<ChunkPlace>,
+    after: Option<ChunkPlace>,
+    modified: bool,
+    reordered: bool,
+}
+
+impl ChunkChange {
+    pub fn chunk_type(&self) -> &ChunkType {
+        &self.chunk_type
+    }
+    pub fn before(&self) -> Option<ChunkPlace> {
+        self.before
+    }
+    pub fn after(&self) -> Option<ChunkPlace> {
+        self.after
+    }
+    pub fn is_added(&self) -> bool {
+        self.before.is_none()
+    }
+    pub fn is_removed(&self) -> bool {
+        self.after.is_none()
+    }
+    /// Chunk is present in both files with different data
+    pub fn is_modified(&self) -> bool {
+        self.modified
+    }
+    /// Chunk is present in both files but moved relative to other chunks
+    pub fn is_reordered(&self) -> bool {
+        self.reordered
+    }
+    /// Change of data length in bytes, missing chunk counts as zero bytes
+    pub fn size_delta(&self) -> i64 {
+        let length = |place: Option<ChunkPlace>| place.map_or(0, |place| i64::from(place.length));
+        length(self.after) - length(self.before)
+    }
+}
+
+/// How image data changed, compared both as chunks and as decompressed stream
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ImageDataChange {
+    /// IDAT chunks are the same
+    Unchanged,
+    /// Compressed stream is the same but split into IDAT chunks differently
+    Resplit,
+    /// Compressed stream differs but decompresses to the same data
+    Recompressed,
+    /// Decompressed data differs
+    Modified,
+    /// Image data of at least one file could not be decompressed
+    Undecodable,
+}
+
+impl ImageDataChange {
+    /// True when decompressed image data may differ
+    pub fn is_modified(&self) -> bool {
+        matches!(self, Self::Modified | Self::Undecodable)
+    }
+}
+
+impl std::fmt::Display for ImageDataChange {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Unchanged => write!(f, "unchanged"),
+            Self::Resplit => write!(f, "same stream split into different IDAT chunks"),
+            Self::Recompressed => write!(f, "recompressed, decoded stream is the same"),
+            Self::Modified => write!(f, "decoded stream differs"),
+            Self::Undecodable => write!(f, "could not be decoded for comparison"),
+        }
+    }
+}
+
+/// Result of comparing two `Png`s
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diff {
+    changes: Vec<ChunkChange>,
+    unchanged: usize,
+    image_data: ImageDataChange,
+}
+
+impl Diff {
+    /// Changed chunks, removed ones first in old order, then the rest in new order
+    pub fn changes(&self) -> &[ChunkChange] {
+        &self.changes
+    }
+    /// Number of chunks that are the same and in the same order in both files
+    pub fn unchanged(&self) -> usize {
+        self.unchanged
+    }
+    pub fn image_data(&self) -> ImageDataChange {
+        self.image_data
+    }
+    pub fn is_empty(&self) -> bool {
+        self.changes.is_empty()
+    }
+}
+
+/// Compares `old` and `new` chunk by chunk
+pub fn diff(old: &Png, new: &Png) -> Diff {
+    let pairs = pair_chunks(old.chunks(), new.chunks());
+    let in_order: HashSet<usize> =
+        longest_increasing(&pairs.iter().map(|&(_, j)| j).collect::<Vec<_>>())
+            .into_iter()
+            .collect();
+    let mut changes: Vec<ChunkChange> = Vec::new();
+    let mut unchanged = 0;
+    for (k, &(i, j)) in pairs.iter().enumerate() {
+        let (old_chunk, new_chunk) = (&old.chunks()[i], &new.chunks()[j]);
+        let modified = old_chunk.data() != new_chunk.data();
+        let reordered = !in_order.contains(&k);
+        if !modified && !reordered {
+            unchanged += 1;
+            continue;
+        }
+        changes.push(ChunkChange {
+            chunk_type: old_chunk.chunk_type().clone(),
+            before: Some(ChunkPlace::new(i, old_chunk)),
+            after: Some(ChunkPlace::new(j, new_chunk)),
+            modified,
+            reordered,
+        });
+    }
+    let unpaired = |chunks: &[Chunk], paired: HashSet<usize>| -> Vec<(ChunkType, ChunkPlace)> {
+        (0..chunks.len())
+            .filter(|index| !paired.contains(index))
+            .map(|index| {
+                let chunk = &chunks[index];
+                (chunk.chunk_type().clone(), ChunkPlace::new(index, chunk))
+            })
+            .collect()
+    };
+    for (chunk_type, place) in unpaired(new.chunks(), pairs.iter().map(|p| p.1).collect()) {
+        changes.push(ChunkChange {
+            chunk_type,
+            before: None,
+            after: Some(place),
+            modified: false,
+            reordered: false,
+        });
+    }
+    changes.sort_by_key(|change| change.after.map(|place| place.index));
+    let removed = unpaired(old.chunks(), pairs.iter().map(|p| p.0).collect())
+        .into_iter()
+        .map(|(chunk_type, place)| ChunkChange {
+            chunk_type,
+            before: Some(place),
+            after: None,
+            modified: false,
+            reordered: false,
+        });
+    let changes = removed.chain(changes).collect();
+    Diff {
+        changes,
+        unchanged,
+        image_data: compare_image_data(old, new),
+    }
+}
+
+/// Pairs k-th chunk of each type in `old` with k-th chunk of the same type in `new`
+///
+/// Pairs are sorted by index in `old`.
+fn pair_chunks(old: &[Chunk], new: &[Chunk]) -> Vec<(usize, usize)> {
+    let occurrence = |chunks: &[Chunk], index: usize| {
+        let chunk_type = chunks[index].chunk_type();
+        chunks[..index]
+            .iter()
+            .filter(|chunk| chunk.chunk_type() == chunk_type)
+            .count()
+    };
+    let mut pairs = Vec::new();
+    for i in 0..old.len() {
+        let k = occurrence(old, i);
+        let j = new
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| chunk.chunk_type() == old[i].chunk_type())
+            .map(|(j, _)| j)
+            .nth(k);
+        if let Some(j) = j {
+            pairs.push((i, j));
+        }
+    }
+    pairs
+}
+
+/// Positions of one longest strictly increasing subsequence of `values`
+fn longest_increasing(values: &[usize]) -> Vec<usize> {
+    // tails[l] is position of the smallest tail of an increasing subsequence of length l + 1
+    let mut tails: Vec<usize> = Vec::new();
+    let mut previous: Vec<Option<usize>> = vec![None; values.len()];
+    for (position, &value) in values.iter().enumerate() {
+        let length = tails.partition_point(|&tail| values[tail] < value);
+        previous[position] = length.checked_sub(1).map(|l| tails[l]);
+        match tails.get_mut(length) {
+            Some(tail) => *tail = position,
+            None => tails.push(position),
+        }
+    }
+    let mut sequence = Vec::with_capacity(tails.len());
+    let mut current = tails.last().copied();
+    while let Some(position) = current {
+        sequence.push(position);
+        current = previous[position];
+    }
+    sequence.reverse();
+    sequence
+}
+
+fn compare_image_data(old: &Png, new: &Png) -> ImageDataChange {
+    let idat_data = |png: &Png| -> Vec<Vec<u8>> {
+        png.chunks()
+            .iter()
+            .filter(|chunk| chunk.chunk_type().bytes() == *b"IDAT")
+            .map(|chunk| chunk.data().to_vec())
+            .collect()
+    };
+    let (old_chunks, new_chunks) = (idat_data(old), idat_data(new));
+    if old_chunks == new_chunks {
+        return ImageDataChange::Unchanged;
+    }
+    if old_chunks.concat() == new_chunks.concat() {
+        return ImageDataChange::Resplit;
+    }
+    match (pixels::inflate(old), pixels::inflate(new)) {
+        (Ok(old), Ok(new)) if old == new => ImageDataChange::Recompressed,
+        (Ok(_), Ok(_)) => ImageDataChange::Modified,
+        _ => ImageDataChange::Undecodable,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::encoder::{Encoder, FilterStrategy, FilterType};
+    use crate::test_utils::{chunk, dice};
+    use std::str::FromStr;
+
+    fn png(chunks: &[(&str, &[u8])]) -> Png {
+        Png::from_chunks(chunks.iter().map(|(t, data)| chunk(t, data)).collect())
+    }
+
+    #[test]
+    fn test_identical() {
+        let result = diff(&dice(), &dice());
+        assert!(result.is_empty());
+        assert_eq!(result.unchanged(), dice().chunks().len());
+        assert_eq!(result.image_data(), ImageDataChange::Unchanged);
+    }
+
+    #[test]
+    fn test_added_removed_modified() {
+        let old = png(&[
+            ("IHDR", b"a"),
+            ("tEXt", b"one"),
+            ("tIME", b"t"),
+            ("IEND", b""),
+        ]);
+        let new = png(&[
+            ("IHDR", b"a"),
+            ("tEXt", b"three"),
+            ("ruSt", b"x"),
+            ("IEND", b""),
+        ]);
+        let result = diff(&old, &new);
+        assert_eq!(result.unchanged(), 2);
+        let changes = result.changes();
+        assert_eq!(changes.len(), 3);
+
+        assert!(changes[0].is_removed());
+        assert_eq!(changes[0].chunk_type().to_string(), "tIME");
+        assert_eq!(changes[0].before().unwrap().index(), 2);
+        assert_eq!(changes[0].size_delta(), -1);
+
+        assert!(changes[1].is_modified() && !changes[1].is_reordered());
+        assert_eq!(changes[1].chunk_type().to_string(), "tEXt");
+        assert_eq!(changes[1].size_delta(), 2);
+
+        assert!(changes[2].is_added());
+        assert_eq!(changes[2].after().unwrap().index(), 2);
+        assert_eq!(changes[2].size_delta(), 1);
+    }
+
+    #[test]
+    fn test_reordered() {
+        let old = png(&[("IHDR", b""), ("gAMA", b""), ("pHYs", b""), ("IEND", b"")]);
+        let new = png(&[("IHDR", b""), ("pHYs", b""), ("gAMA", b""), ("IEND", b"")]);
+        let result = diff(&old, &new);
+        assert_eq!(result.unchanged(), 3);
+        assert_eq!(result.changes().len(), 1);
+        let change = &result.changes()[0];
+        assert!(change.is_reordered() && !change.is_modified());
+        assert_eq!(change.size_delta(), 0);
+    }
+
+    #[test]
+    fn test_same_type_is_paired_by_occurrence() {
+        let old = png(&[("tEXt", b"a"), ("tEXt", b"b")]);
+        let new = png(&[("tEXt", b"a"), ("tEXt", b"c"), ("tEXt", b"d")]);
+        let result = diff(&old, &new);
+        let changes = result.changes();
+        assert_eq!(changes.len(), 2);
+        assert!(changes[0].is_modified());
+        assert_eq!(changes[0].after().unwrap().index(), 1);
+        assert!(changes[1].is_added());
+        assert_eq!(changes[1].after().unwrap().index(), 2);
+    }
+
+    #[test]
+    fn test_resplit_image_data() {
+        let old = dice();
+        let data: Vec<u8> = old
+            .chunks_by_type(&ChunkType::from_str("IDAT").unwrap())
+            .flat_map(|chunk| chunk.data().iter().copied())
+            .collect();
+        let mut new = dice();
+        let idat: Vec<Chunk> = data
+            .chunks(10_000)
+            .map(|part| chunk("IDAT", part))
+            .collect();
+        new.replace_image_data(idat).unwrap();
+        let result = diff(&old, &new);
+        assert_eq!(result.image_data(), ImageDataChange::Resplit);
+        assert!(!result.image_data().is_modified());
+        assert!(result.changes().iter().any(|change| change.is_added()));
+    }
+
+    #[test]
+    fn test_recompressed_and_modified_image_data() {
+        let old = dice();
+        let mut new = dice();
+        let data = pixels::inflate(&old).unwrap();
+        let idat = vec![chunk("IDAT", &pixels::deflate(&data, 1))];
+        new.replace_image_data(idat).unwrap();
+        assert_eq!(diff(&old, &new).image_data(), ImageDataChange::Recompressed);
+
+        let mut pixels = pixels::decode(&old).unwrap();
+        pixels.data_mut()[0] ^= 1;
+        let encoder = Encoder::new(FilterStrategy::Fixed(FilterType::None), 1, 1 << 16).unwrap();
+        encoder.encode_into(&mut new, &pixels).unwrap();
+        assert_eq!(diff(&old, &new).image_data(), ImageDataChange::Modified);
+    }
+
+    #[test]
+    fn test_undecodable_image_data() {
+        let old = png(&[("IHDR", b""), ("IDAT", b"not zlib"), ("IEND", b"")]);
+        let new = png(&[("IHDR", b""), ("IDAT", b"other"), ("IEND", b"")]);
+        assert_eq!(diff(&old, &new).image_data(), ImageDataChange::Undecodable);
+    }
+
+    #[test]
+    fn test_longest_increasing() {
+        assert_eq!(longest_increasing(&[]), Vec::<usize>::new());
+        assert_eq!(longest_increasing(&[0, 2, 1, 3]), [0, 2, 3]);
+        assert_eq!(longest_increasing(&[3, 0, 1, 2]), [1, 2, 3]);
+    }
+}
@@ -0,0 +1,259 @@
+//! Encoding of raw pixels back into IDAT chunks
+use std::str::FromStr;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::EncodeError;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::pixels::{self, PixelBuffer, PixelFormat};
+use crate::png::Png;
+
+/// Largest chunk data length allowed by the PNG specification
+const MAX_CHUNK_SIZE: usize = (1 << 31) - 1;
+
+/// Scanline filter type as stored in the first byte of every scanline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterType {
+    None = 0,
+    Sub = 1,
+    Up = 2,
+    Average = 3,
+    Paeth = 4,
+}
+
+impl FilterType {
+    const ALL: [FilterType; 5] = [
+        FilterType::None,
+        FilterType::Sub,
+        FilterType::Up,
+        FilterType::Average,
+        FilterType::Paeth,
+    ];
+}
+
+/// How a filter is chosen for each scanline
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum FilterStrategy {
+    /// Same filter for every scanline
+    Fixed(FilterType),
+    /// Filter giving the minimum sum of absolute differences, heuristic recommended by the
+    /// PNG specification. Images with palette or less than 8 bits per sample use None.
+    MinimumSum,
+}
+
+impl FromStr for FilterStrategy {
+    type Err = EncodeError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "none" => Ok(Self::Fixed(FilterType::None)),
+            "sub" => Ok(Self::Fixed(FilterType::Sub)),
+            "up" => Ok(Self::Fixed(FilterType::Up)),
+            "average" => Ok(Self::Fixed(FilterType::Average)),
+            "paeth" => Ok(Self::Fixed(FilterType::Paeth)),
+            "adaptive" => Ok(Self::MinimumSum),
+            _ => Err(EncodeError::UnknownFilterStrategy(s.to_string())),
+        }
+    }
+}
+
+/// Settings for turning pixels into IDAT chunks
+#[derive(Debug, Clone, Copy)]
+pub struct Encoder {
+    filter: FilterStrategy,
+    compression_level: u32,
+    max_idat_size: usize,
+}
+
+impl Encoder {
+    /// Compression level goes from 0 to 9, `max_idat_size` is the largest IDAT data length
+    pub fn new(
+        filter: FilterStrategy,
+        compression_level: u32,
+        max_idat_size: usize,
+    ) -> Result<Self, EncodeError> {
+        if compression_level > 9 {
+            return Err(EncodeError::InvalidCompressionLevel(compression_level));
+        }
+        if max_idat_size == 0 || max_idat_size > MAX_CHUNK_SIZE {
+            return Err(EncodeError::InvalidMaxIdatSize(max_idat_size));
+        }
+        Ok(Encoder {
+            filter,
+            compression_level,
+            max_idat_size,
+        })
+    }
+
+    pub fn filter(&self) -> FilterStrategy {
+        self.filter
+    }
+
+    pub fn compression_level(&self) -> u32 {
+        self.compression_level
+    }
+
+    pub fn max_idat_size(&self) -> usize {
+        self.max_idat_size
+    }
+
+    /// Filters and compresses pixels into IDAT chunks, interlaced when `ihdr` says so
+    pub fn encode(&self, image: &PixelBuffer, ihdr: &Ihdr) -> Result<Vec<Chunk>, EncodeError> {
+        let format = PixelFormat::from(ihdr);
+        if (image.width(), image.height()) != (ihdr.width(), ihdr.height())
+            || image.format() != format
+        {
+            return Err(EncodeError::FormatMismatch);
+        }
+        let mut filtered = Vec::with_capacity(image.data().len() + image.height() as usize);
+        if ihdr.is_interlaced() {
+            for pass in pixels::ADAM7.iter() {
+                let (width, height) = pixels::pass_size(image.width(), image.height(), pass);
+                if width == 0 || height == 0 {
+                    continue;
+                }
+                let data = pixels::pass_data(image, pass);
+                self.filter_rows(&data, format.row_bytes(width), format, &mut filtered);
+            }
+        } else {
+            self.filter_rows(image.data(), image.row_bytes(), format, &mut filtered);
+        }
+        let compressed = pixels::deflate(&filtered, self.compression_level);
+        let chunks = compressed
+            .chunks(self.max_idat_size)
+            .map(|data| {
+                let idat = ChunkType::from_str("IDAT").expect("IDAT is a valid chunk type");
+                Chunk::new(idat, data)
+            })
+            .collect();
+        Ok(chunks)
+    }
+
+    /// Replaces IDAT chunks of `png` with encoded pixels, other chunks are kept as they are
+    pub fn encode_into(&self, png: &mut Png, image: &PixelBuffer) -> Result<(), EncodeError> {
+        let ihdr = png.header()?;
+        let chunks = self.encode(image, &ihdr)?;
+        png.replace_image_data(chunks)?;
+        Ok(())
+    }
+
+    fn filter_rows(&self, data: &[u8], row_bytes: usize, format: PixelFormat, out: &mut Vec<u8>) {
+        let unit = format.bytes_per_pixel();
+        let strategy = match self.filter {
+            FilterStrategy::MinimumSum
+                if format.bit_depth() < 8 || format.color_type() == ColorType::Indexed =>
+            {
+                FilterStrategy::Fixed(FilterType::None)
+            }
+            strategy => strategy,
+        };
+        for (row, current) in data.chunks(row_bytes).enumerate() {
+            let previous = (row > 0).then(|| &data[(row - 1) * row_bytes..row * row_bytes]);
+            let filter_type = match strategy {
+                FilterStrategy::Fixed(filter_type) => filter_type,
+                FilterStrategy::MinimumSum => *FilterType::ALL
+                    .iter()
+                    .min_by_key(|&&filter_type| {
+                        pixels::filter_row(filter_type as u8, current, previous, unit)
+                            .map(|byte| u64::from((byte as i8).unsigned_abs()))
+                            .sum::<u64>()
+                    })
+                    .expect("there are filter types"),
+            };
+            out.push(filter_type as u8);
+            out.extend(pixels::filter_row(
+                filter_type as u8,
+                current,
+                previous,
+                unit,
+            ));
+        }
+    }
+}
+
+impl Default for Encoder {
+    fn default() -> Self {
+        Encoder {
+            filter: FilterStrategy::MinimumSum,
+            compression_level: 6,
+            max_idat_size: 1 << 16,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_utils::{chunk_types, dice};
+
+    #[test]
+    fn test_round_trip_with_every_strategy() {
+        let png = dice();
+        let image = pixels::decode(&png).unwrap();
+        for strategy in ["none", "sub", "up", "average", "paeth", "adaptive"].iter() {
+            let encoder = Encoder::new(strategy.parse().unwrap(), 6, 1 << 16).unwrap();
+            let mut encoded = dice();
+            encoder.encode_into(&mut encoded, &image).unwrap();
+            assert_eq!(pixels::decode(&encoded).unwrap(), image, "{}", strategy);
+        }
+    }
+
+    #[test]
+    fn test_adaptive_filter_is_smaller_than_none() {
+        let png = dice();
+        let image = pixels::decode(&png).unwrap();
+        let ihdr = png.header().unwrap();
+        let size = |filter| {
+            let encoder = Encoder::new(filter, 6, MAX_CHUNK_SIZE).unwrap();
+            encoder.encode(&image, &ihdr).unwrap()[0].length()
+        };
+        let none = size(FilterStrategy::Fixed(FilterType::None));
+        assert!(size(FilterStrategy::MinimumSum) < none);
+    }
+
+    #[test]
+    fn test_keeps_ancillary_chunks_and_splits_idat() {
+        let mut png = Png::from_file("../images/secret.png").unwrap();
+        let image = pixels::decode(&png).unwrap();
+        let encoder = Encoder::new(FilterStrategy::MinimumSum, 9, 10_000).unwrap();
+        encoder.encode_into(&mut png, &image).unwrap();
+        let types = chunk_types(&png);
+        let idat_count = types.iter().filter(|t| *t == "IDAT").count();
+        assert!(idat_count > 2);
+        let others: Vec<_> = types.iter().filter(|t| *t != "IDAT").collect();
+        assert_eq!(others, ["IHDR", "sRGB", "gAMA", "pHYs", "IEND", "RuSt"]);
+        assert_eq!(types[4..4 + idat_count], vec!["IDAT"; idat_count][..]);
+        assert!(png.chunks().iter().all(|chunk| chunk.length() <= 10_000));
+        assert_eq!(pixels::decode(&png).unwrap(), image);
+    }
+
+    #[test]
+    fn test_interlaced_round_trip() {
+        let png = dice();
+        let image = pixels::decode(&png).unwrap();
+        let ihdr = Ihdr::new(361, 288, 8, ColorType::Rgba, true).unwrap();
+        let mut chunks = vec![ihdr.to_chunk()];
+        chunks.extend(Encoder::default().encode(&image, &ihdr).unwrap());
+        chunks.push(Chunk::new(ChunkType::from_str("IEND").unwrap(), &[]));
+        let interlaced = Png::from_chunks(chunks);
+        assert_eq!(pixels::decode(&interlaced).unwrap(), image);
+    }
+
+    #[test]
+    fn test_invalid_settings() {
+        let strategy = FilterStrategy::MinimumSum;
+        assert!(Encoder::new(strategy, 10, 1).is_err());
+        assert!(Encoder::new(strategy, 9, 0).is_err());
+        assert!(Encoder::new(strategy, 0, MAX_CHUNK_SIZE + 1).is_err());
+        assert!("best".parse::<FilterStrategy>().is_err());
+    }
+
+    #[test]
+    fn test_format_mismatch() {
+        let png = dice();
+        let image = pixels::decode(&png).unwrap();
+        let ihdr = Ihdr::new(361, 288, 16, ColorType::Rgba, false).unwrap();
+        let result = Encoder::default().encode(&image, &ihdr);
+        assert!(matches!(result, Err(EncodeError::FormatMismatch)));
+    }
+}
@@ -0,0 +1,408 @@
+//! Envelope carrying a hidden file together with its metadata
+//!
+//! Envelope layout, all numbers are big endian:
+//!
+//! | bytes | field                                                 |
+//! |-------|-------------------------------------------------------|
+//! | 4     | magic `pmEV`                                          |
+//! | 1     | format version, currently 1                           |
+//! | 1     | flags: bit 0 compressed payload, bit 1 encrypted one  |
+//! | 8     | file timestamp, seconds since Unix epoch              |
+//! | 2     | filename length `n`                                   |
+//! | n     | UTF-8 filename                                        |
+//! | 2     | content type length `m`                               |
+//! | m     | UTF-8 content type                                    |
+//! | 32    | SHA-256 of the original content, of the payload when  |
+//! |       | it is encrypted                                       |
+//! | rest  | payload                                               |
+//!
+//! The envelope itself is not encrypted, so filename, content type and timestamp stay readable
+//! even when the payload is encrypted. They are authenticated together with the payload, and the
+//! checksum of an encrypted envelope reveals nothing about the content.
+use std::convert::{TryFrom, TryInto};
+use std::path::Path;
+
+use sha2::{Digest, Sha256};
+
+use crate::compression::{self, Algorithm};
+use crate::crypto::{self, KdfParams};
+use crate::error::EnvelopeError;
+
+const MAGIC: &[u8; 4] = b"pmEV";
+const VERSION: u8 = 1;
+const FLAG_COMPRESSED: u8 = 1;
+const FLAG_ENCRYPTED: u8 = 1 << 1;
+const CHECKSUM_LENGTH: usize = 32;
+
+/// File with metadata, its payload can be compressed and encrypted
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Envelope {
+    filename: String,
+    content_type: String,
+    timestamp: u64,
+    checksum: [u8; CHECKSUM_LENGTH],
+    flags: u8,
+    payload: Vec<u8>,
+}
+
+impl Envelope {
+    pub fn new(
+        filename: &str,
+        content_type: &str,
+        timestamp: u64,
+        content: Vec<u8>,
+    ) -> Result<Self, EnvelopeError> {
+        for field in [filename, content_type].iter() {
+            if field.len() > usize::from(u16::MAX) {
+                return Err(EnvelopeError::FieldTooLong(field.len()));
+            }
+        }
+        Ok(Envelope {
+            filename: filename.to_string(),
+            content_type: content_type.to_string(),
+            timestamp,
+            checksum: Sha256::digest(&content).into(),
+            flags: 0,
+            payload: content,
+        })
+    }
+
+    pub fn filename(&self) -> &str {
+        &self.filename
+    }
+
+    /// Filename when it is a plain name that can not escape the directory it is written to
+    pub fn safe_filename(&self) -> Option<&str> {
+        let path = Path::new(&self.filename);
+        let is_plain = path
+            .file_name()
+            .is_some_and(|name| name == path.as_os_str());
+        (is_plain && !self.filename.contains(['/', '\\'])).then_some(&self.filename)
+    }
+
+    pub fn content_type(&self) -> &str {
+        &self.content_type
+    }
+
+    pub fn timestamp(&self) -> u64 {
+        self.timestamp
+    }
+
+    /// SHA-256 of the original content, of the encrypted payload when encrypted
+    pub fn checksum(&self) -> &[u8; CHECKSUM_LENGTH] {
+        &self.checksum
+    }
+
+    pub fn is_compressed(&self) -> bool {
+        self.flags & FLAG_COMPRESSED != 0
+    }
+
+    pub fn is_encrypted(&self) -> bool {
+        self.flags & FLAG_ENCRYPTED != 0
+    }
+
+    /// Stored payload, compressed and encrypted when flags say so
+    pub fn payload(&self) -> &[u8] {
+        &self.payload
+    }
+
+    /// Compresses payload, level is the default one of the algorithm when `None`
+    pub fn compress(
+        mut self,
+        algorithm: Algorithm,
+        level: Option<u32>,
+    ) -> Result<Self, EnvelopeError> {
+        if self.is_encrypted() {
+            return Err(EnvelopeError::AlreadyEncrypted);
+        }
+        if self.is_compressed() {
+            return Err(EnvelopeError::AlreadyCompressed);
+        }
+        self.payload = compression::compress(&self.payload, algorithm, level)?;
+        self.flags |= FLAG_COMPRESSED;
+        Ok(self)
+    }
+
+    pub fn encrypt(self, passphrase: &[u8]) -> Result<Self, EnvelopeError> {
+        self.encrypt_with_params(passphrase, KdfParams::default())
+    }
+
+    pub fn encrypt_with_params(
+        mut self,
+        passphrase: &[u8],
+        params: KdfParams,
+    ) -> Result<Self, EnvelopeError> {
+        if self.is_encrypted() {
+            return Err(EnvelopeError::AlreadyEncrypted);
+        }
+        self.flags |= FLAG_ENCRYPTED;
+        let header = self.header();
+        self.payload = crypto::encrypt_with_context(&self.payload, passphrase, params, &header)?;
+        // Encryption authenticates the content, a checksum of it would only leak information
+        self.checksum = Sha256::digest(&self.payload).into();
+        Ok(self)
+    }
+
+    /// Restores original content and checks it against the checksum
+    pub fn open(&self, passphrase: Option<&[u8]>) -> Result<Vec<u8>, EnvelopeError> {
+        if self.is_encrypted() && Sha256::digest(&self.payload)[..] != self.checksum[..] {
+            return Err(EnvelopeError::ChecksumMismatch);
+        }
+        let mut content = match (self.is_encrypted(), passphrase) {
+            (true, Some(passphrase)) => {
+                crypto::decrypt_with_context(&self.payload, passphrase, &self.header())?
+            }
+            (true, None) => return Err(EnvelopeError::PassphraseRequired),
+            (false, _) => self.payload.clone(),
+        };
+        if self.is_compressed() {
+            content = compression::decompress(&content)?;
+        }
+        if !self.is_encrypted() && Sha256::digest(&content)[..] != self.checksum[..] {
+            return Err(EnvelopeError::ChecksumMismatch);
+        }
+        Ok(content)
+    }
+
+    /// Fields before the checksum, authenticated as context of the encrypted payload
+    fn header(&self) -> Vec<u8> {
+        let mut bytes =
+            Vec::with_capacity(MAGIC.len() + 14 + self.filename.len() + self.content_type.len());
+        bytes.extend_from_slice(MAGIC);
+        bytes.extend_from_slice(&[VERSION, self.flags]);
+        bytes.extend_from_slice(&self.timestamp.to_be_bytes());
+        for field in [&self.filename, &self.content_type].iter() {
+            bytes.extend_from_slice(&(field.len() as u16).to_be_bytes());
+            bytes.extend_from_slice(field.as_bytes());
+        }
+        bytes
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header();
+        bytes.extend_from_slice(&self.checksum);
+        bytes.extend_from_slice(&self.payload);
+        bytes
+    }
+}
+
+impl TryFrom<&[u8]> for Envelope {
+    type Error = EnvelopeError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        if !is_envelope(bytes) {
+            return Err(EnvelopeError::InvalidHeader);
+        }
+        let mut rest = &bytes[MAGIC.len()..];
+        let mut take = |count: usize| {
+            if rest.len() < count {
+                return Err(EnvelopeError::InvalidHeader);
+            }
+            let (taken, remaining) = rest.split_at(count);
+            rest = remaining;
+            Ok(taken)
+        };
+        let version = take(1)?[0];
+        if version != VERSION {
+            return Err(EnvelopeError::UnsupportedVersion(version));
+        }
+        let flags = take(1)?[0];
+        if flags & !(FLAG_COMPRESSED | FLAG_ENCRYPTED) != 0 {
+            return Err(EnvelopeError::UnsupportedFlags(flags));
+        }
+        let timestamp = u64::from_be_bytes(take(8)?.try_into().expect("8 bytes are taken"));
+        let mut text = || {
+            let length = u16::from_be_bytes(take(2)?.try_into().expect("2 bytes are taken"));
+            let field = take(usize::from(length))?;
+            String::from_utf8(field.to_vec()).map_err(|_| EnvelopeError::InvalidHeader)
+        };
+        let filename = text()?;
+        let content_type = text()?;
+        let checksum = take(CHECKSUM_LENGTH)?
+            .try_into()
+            .expect("checksum bytes are taken");
+        Ok(Envelope {
+            filename,
+            content_type,
+            timestamp,
+            checksum,
+            flags,
+            payload: rest.to_vec(),
+        })
+    }
+}
+
+/// Returns true when data starts with the envelope magic
+pub fn is_envelope(data: &[u8]) -> bool {
+    data.starts_with(MAGIC)
+}
+
+/// Content type guessed from filename extension
+pub fn guess_content_type(filename: &str) -> &'static str {
+    let extension = Path::new(filename)
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("png") => "image/png",
+        Some("jpg") | Some("jpeg") => "image/jpeg",
+        Some("gif") => "image/gif",
+        Some("txt") => "text/plain",
+        Some("md") => "text/markdown",
+        Some("html") | Some("htm") => "text/html",
+        Some("json") => "application/json",
+        Some("pdf") => "application/pdf",
+        Some("zip") => "application/zip",
+        Some("gz") => "application/gzip",
+        _ => "application/octet-stream",
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::error::CryptoError;
+
+    fn testing_envelope() -> Envelope {
+        Envelope::new(
+            "notes.txt",
+            "text/plain",
+            1_600_000_000,
+            b"content".to_vec(),
+        )
+        .unwrap()
+    }
+
+    fn fast_params() -> KdfParams {
+        KdfParams::new(64, 1, 1).unwrap()
+    }
+
+    #[test]
+    fn test_round_trip() {
+        let envelope = testing_envelope();
+        let bytes = envelope.as_bytes();
+        assert!(is_envelope(&bytes));
+        let parsed = Envelope::try_from(&bytes[..]).unwrap();
+        assert_eq!(parsed, envelope);
+        assert_eq!(parsed.filename(), "notes.txt");
+        assert_eq!(parsed.content_type(), "text/plain");
+        assert_eq!(parsed.timestamp(), 1_600_000_000);
+        assert!(!parsed.is_encrypted() && !parsed.is_compressed());
+        assert_eq!(parsed.open(None).unwrap(), b"content");
+    }
+
+    #[test]
+    fn test_encrypted_round_trip() {
+        let envelope = testing_envelope()
+            .encrypt_with_params(b"passphrase", fast_params())
+            .unwrap();
+        assert!(envelope.is_encrypted());
+        assert_ne!(envelope.payload(), b"content");
+        let parsed = Envelope::try_from(&envelope.as_bytes()[..]).unwrap();
+        assert!(matches!(
+            parsed.open(None),
+            Err(EnvelopeError::PassphraseRequired)
+        ));
+        assert!(parsed.open(Some(b"wrong")).is_err());
+        assert_eq!(parsed.open(Some(b"passphrase")).unwrap(), b"content");
+        let twice = parsed.encrypt_with_params(b"passphrase", fast_params());
+        assert!(matches!(twice, Err(EnvelopeError::AlreadyEncrypted)));
+    }
+
+    #[test]
+    fn test_encrypted_envelope_hides_checksum_and_authenticates_metadata() {
+        let envelope = testing_envelope();
+        let content_checksum = *envelope.checksum();
+        let encrypted = envelope
+            .encrypt_with_params(b"passphrase", fast_params())
+            .unwrap();
+        assert_ne!(encrypted.checksum(), &content_checksum);
+        let bytes = encrypted.as_bytes();
+        assert!(!bytes
+            .windows(4)
+            .any(|window| window == &content_checksum[..4]));
+
+        let mut renamed = bytes.clone();
+        let start = bytes.windows(9).position(|w| w == b"notes.txt").unwrap();
+        renamed[start..start + 5].copy_from_slice(b"other");
+        let mut retimed = bytes;
+        retimed[13] ^= 1;
+        for tampered in [renamed, retimed] {
+            let parsed = Envelope::try_from(&tampered[..]).unwrap();
+            assert!(matches!(
+                parsed.open(Some(b"passphrase")),
+                Err(EnvelopeError::Crypto(CryptoError::DecryptionFailed))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_compressed_and_encrypted_round_trip() {
+        let content = b"content ".repeat(100);
+        let envelope = Envelope::new("notes.txt", "text/plain", 0, content.clone())
+            .unwrap()
+            .compress(Algorithm::Deflate, Some(9))
+            .unwrap();
+        assert!(envelope.is_compressed());
+        assert!(envelope.payload().len() < content.len());
+        let envelope = envelope
+            .encrypt_with_params(b"passphrase", fast_params())
+            .unwrap();
+        let parsed = Envelope::try_from(&envelope.as_bytes()[..]).unwrap();
+        assert!(parsed.is_compressed() && parsed.is_encrypted());
+        assert_eq!(parsed.open(Some(b"passphrase")).unwrap(), content);
+        let compressed_after_encryption = parsed.compress(Algorithm::Deflate, None);
+        assert!(matches!(
+            compressed_after_encryption,
+            Err(EnvelopeError::AlreadyEncrypted)
+        ));
+    }
+
+    #[test]
+    fn test_checksum_mismatch() {
+        let mut bytes = testing_envelope().as_bytes();
+        *bytes.last_mut().unwrap() ^= 1;
+        let parsed = Envelope::try_from(&bytes[..]).unwrap();
+        assert!(matches!(
+            parsed.open(None),
+            Err(EnvelopeError::ChecksumMismatch)
+        ));
+    }
+
+    #[test]
+    fn test_invalid_envelopes() {
+        let bytes = testing_envelope().as_bytes();
+        assert!(Envelope::try_from(&bytes[..20]).is_err());
+        assert!(Envelope::try_from(&b"not an envelope"[..]).is_err());
+        let mut wrong_version = bytes.clone();
+        wrong_version[4] = 2;
+        assert!(matches!(
+            Envelope::try_from(&wrong_version[..]),
+            Err(EnvelopeError::UnsupportedVersion(2))
+        ));
+        let mut unknown_flags = bytes;
+        unknown_flags[5] = 0x80;
+        assert!(matches!(
+            Envelope::try_from(&unknown_flags[..]),
+            Err(EnvelopeError::UnsupportedFlags(0x80))
+        ));
+    }
+
+    #[test]
+    fn test_safe_filename() {
+        let envelope = |name: &str| Envelope::new(name, "", 0, Vec::new()).unwrap();
+        assert_eq!(envelope("icon.png").safe_filename(), Some("icon.png"));
+        assert_eq!(envelope("../icon.png").safe_filename(), None);
+        assert_eq!(envelope("/etc/passwd").safe_filename(), None);
+        assert_eq!(envelope("dir\\icon.png").safe_filename(), None);
+        assert_eq!(envelope("..").safe_filename(), None);
+        assert_eq!(envelope("").safe_filename(), None);
+    }
+
+    #[test]
+    fn test_guess_content_type() {
+        assert_eq!(guess_content_type("icon.PNG"), "image/png");
+        assert_eq!(guess_content_type("notes.txt"), "text/plain");
+        assert_eq!(guess_content_type("data"), "application/octet-stream");
+    }
+}
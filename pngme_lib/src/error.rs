@@ -31,6 +31,7 @@ pub enum ChunkParseError {
     EncodedLengthNotEqualToActual,
     CouldNotParseCrc,
     CrcMismatch,
+    DataTooLong(usize),
 }
 
 impl From<ChunkTypeParseError> for ChunkParseError {
@@ -55,12 +56,476 @@ impl fmt::Display for ChunkParseError {
             }
             ChunkParseError::CouldNotParseCrc => write!(f, "Could not parse crc"),
             ChunkParseError::CrcMismatch => write!(f, "Decoded crc not equal to calculated crc"),
+            ChunkParseError::DataTooLong(length) => {
+                write!(f, "Chunk data of {} bytes exceeds maximum length", length)
+            }
         }
     }
 }
 
 impl std::error::Error for ChunkParseError {}
 
+#[derive(Debug)]
+pub enum IhdrParseError {
+    NotFound,
+    WrongChunkType(String),
+    InvalidLength(usize),
+    InvalidDimensions { width: u32, height: u32 },
+    InvalidColorType(u8),
+    InvalidBitDepth { bit_depth: u8, color_type: u8 },
+    UnknownCompressionMethod(u8),
+    UnknownFilterMethod(u8),
+    UnknownInterlaceMethod(u8),
+}
+
+impl fmt::Display for IhdrParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotFound => write!(f, "IHDR chunk not found"),
+            Self::WrongChunkType(chunk_type) => {
+                write!(f, "Expected IHDR chunk, got {}", chunk_type)
+            }
+            Self::InvalidLength(length) => {
+                write!(f, "IHDR data should be 13 bytes long, got {}", length)
+            }
+            Self::InvalidDimensions { width, height } => {
+                write!(f, "Invalid image dimensions {}x{}", width, height)
+            }
+            Self::InvalidColorType(color_type) => write!(f, "Invalid color type {}", color_type),
+            Self::InvalidBitDepth {
+                bit_depth,
+                color_type,
+            } => write!(
+                f,
+                "Bit depth {} is not allowed for color type {}",
+                bit_depth, color_type
+            ),
+            Self::UnknownCompressionMethod(method) => {
+                write!(f, "Unknown compression method {}", method)
+            }
+            Self::UnknownFilterMethod(method) => write!(f, "Unknown filter method {}", method),
+            Self::UnknownInterlaceMethod(method) => {
+                write!(f, "Unknown interlace method {}", method)
+            }
+        }
+    }
+}
+
+impl std::error::Error for IhdrParseError {}
+
+#[derive(Debug)]
+pub enum TextChunkError {
+    WrongChunkType(String),
+    InvalidKeyword(String),
+    InvalidLanguageTag(String),
+    InvalidTranslatedKeyword,
+    NotLatin1(char),
+    MissingNullSeparator,
+    Truncated,
+    InvalidCompressionFlag(u8),
+    UnknownCompressionMethod(u8),
+    Decompression(std::io::Error),
+    TooLong(usize),
+    InvalidUtf8,
+    Chunk(ChunkParseError),
+}
+
+impl fmt::Display for TextChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::WrongChunkType(chunk_type) => {
+                write!(f, "Chunk {} is not a textual chunk", chunk_type)
+            }
+            Self::InvalidKeyword(keyword) => write!(f, "Invalid keyword {:?}", keyword),
+            Self::InvalidLanguageTag(tag) => write!(f, "Invalid language tag {:?}", tag),
+            Self::InvalidTranslatedKeyword => {
+                write!(f, "Translated keyword should not contain null characters")
+            }
+            Self::NotLatin1(ch) => write!(f, "Char {:?} can not be encoded as Latin-1", ch),
+            Self::MissingNullSeparator => write!(f, "Null separator not found"),
+            Self::Truncated => write!(f, "Not enough bytes to parse textual chunk"),
+            Self::InvalidCompressionFlag(flag) => write!(f, "Invalid compression flag {}", flag),
+            Self::UnknownCompressionMethod(method) => {
+                write!(f, "Unknown compression method {}", method)
+            }
+            Self::Decompression(e) => write!(f, "Could not decompress text: {}", e),
+            Self::TooLong(limit) => write!(f, "Decompressed text is longer than {} bytes", limit),
+            Self::InvalidUtf8 => write!(f, "Text is not valid UTF-8"),
+            Self::Chunk(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for TextChunkError {}
+
+impl From<std::io::Error> for TextChunkError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Decompression(err)
+    }
+}
+
+impl From<ChunkParseError> for TextChunkError {
+    fn from(err: ChunkParseError) -> Self {
+        Self::Chunk(err)
+    }
+}
+
+#[derive(Debug)]
+pub enum CryptoError {
+    InvalidHeader,
+    UnsupportedVersion(u8),
+    UnsupportedKdf(u8),
+    UnsupportedCipher(u8),
+    InvalidKdfParams,
+    KdfParamsTooLarge,
+    KeyDerivation(String),
+    RandomSource,
+    EncryptionFailed,
+    DecryptionFailed,
+}
+
+impl fmt::Display for CryptoError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidHeader => write!(f, "Data is not an encrypted payload"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Unsupported encrypted payload version {}", version)
+            }
+            Self::UnsupportedKdf(kdf) => write!(f, "Unsupported key derivation function {}", kdf),
+            Self::UnsupportedCipher(cipher) => write!(f, "Unsupported cipher {}", cipher),
+            Self::InvalidKdfParams => write!(f, "Invalid key derivation parameters"),
+            Self::KdfParamsTooLarge => write!(f, "Key derivation parameters are too large"),
+            Self::KeyDerivation(e) => write!(f, "Could not derive key: {}", e),
+            Self::RandomSource => write!(f, "Could not get random bytes from operating system"),
+            Self::EncryptionFailed => write!(f, "Encryption failed"),
+            Self::DecryptionFailed => {
+                write!(f, "Decryption failed: wrong passphrase or tampered data")
+            }
+        }
+    }
+}
+
+impl std::error::Error for CryptoError {}
+
+#[derive(Debug)]
+pub enum ImageDataError {
+    Header(IhdrParseError),
+    MissingImageData,
+    NonConsecutiveImageData,
+    Decompression(std::io::Error),
+    InvalidFilterType(u8),
+    UnexpectedLength { expected: usize, actual: usize },
+    TooLarge { limit: usize },
+}
+
+impl fmt::Display for ImageDataError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Header(e) => e.fmt(f),
+            Self::MissingImageData => write!(f, "No IDAT chunks found"),
+            Self::NonConsecutiveImageData => write!(f, "IDAT chunks are not consecutive"),
+            Self::Decompression(e) => write!(f, "Could not decompress image data: {}", e),
+            Self::InvalidFilterType(filter_type) => {
+                write!(f, "Invalid scanline filter type {}", filter_type)
+            }
+            Self::UnexpectedLength { expected, actual } => write!(
+                f,
+                "Image data should contain {} bytes, got {} bytes",
+                expected, actual
+            ),
+            Self::TooLarge { limit } => {
+                write!(f, "Image data is larger than the limit of {} bytes", limit)
+            }
+        }
+    }
+}
+
+impl std::error::Error for ImageDataError {}
+
+impl From<IhdrParseError> for ImageDataError {
+    fn from(err: IhdrParseError) -> Self {
+        Self::Header(err)
+    }
+}
+
+#[derive(Debug)]
+pub enum EncodeError {
+    Header(IhdrParseError),
+    FormatMismatch,
+    InvalidCompressionLevel(u32),
+    InvalidMaxIdatSize(usize),
+    UnknownFilterStrategy(String),
+    InsertChunk(InsertChunkError),
+}
+
+impl fmt::Display for EncodeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Header(e) => e.fmt(f),
+            Self::FormatMismatch => write!(f, "Pixels do not match dimensions or format of IHDR"),
+            Self::InvalidCompressionLevel(level) => {
+                write!(f, "Compression level should be from 0 to 9, got {}", level)
+            }
+            Self::InvalidMaxIdatSize(size) => write!(f, "Invalid maximum IDAT size {}", size),
+            Self::UnknownFilterStrategy(name) => write!(
+                f,
+                "Unknown filter {:?}, expected none, sub, up, average, paeth or adaptive",
+                name
+            ),
+            Self::InsertChunk(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for EncodeError {}
+
+impl From<IhdrParseError> for EncodeError {
+    fn from(err: IhdrParseError) -> Self {
+        Self::Header(err)
+    }
+}
+
+impl From<InsertChunkError> for EncodeError {
+    fn from(err: InsertChunkError) -> Self {
+        Self::InsertChunk(err)
+    }
+}
+
+#[derive(Debug)]
+pub enum LsbError {
+    Header(IhdrParseError),
+    ImageData(ImageDataError),
+    Encode(EncodeError),
+    UnsupportedFormat(String),
+    PayloadTooLarge { payload: usize, capacity: usize },
+    NoPayload,
+}
+
+impl fmt::Display for LsbError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Header(e) => e.fmt(f),
+            Self::ImageData(e) => e.fmt(f),
+            Self::Encode(e) => e.fmt(f),
+            Self::UnsupportedFormat(reason) => write!(f, "Unsupported image: {}", reason),
+            Self::PayloadTooLarge { payload, capacity } => write!(
+                f,
+                "Payload of {} bytes does not fit into image, capacity is {} bytes",
+                payload, capacity
+            ),
+            Self::NoPayload => write!(f, "Image pixels do not contain a hidden payload"),
+        }
+    }
+}
+
+impl std::error::Error for LsbError {}
+
+impl From<IhdrParseError> for LsbError {
+    fn from(err: IhdrParseError) -> Self {
+        Self::Header(err)
+    }
+}
+
+impl From<EncodeError> for LsbError {
+    fn from(err: EncodeError) -> Self {
+        Self::Encode(err)
+    }
+}
+
+impl From<ImageDataError> for LsbError {
+    fn from(err: ImageDataError) -> Self {
+        Self::ImageData(err)
+    }
+}
+
+#[derive(Debug)]
+pub enum SequenceError {
+    InvalidHeader,
+    UnsupportedVersion(u8),
+    InvalidMaxChunkSize(usize),
+    TooManyParts,
+    RandomSource,
+    NoParts,
+    MixedMessages,
+    InconsistentTotal,
+    DuplicatePart(u32),
+    MissingParts(Vec<u32>),
+    OutOfOrder { position: u32, index: u32 },
+    Chunk(ChunkParseError),
+}
+
+impl fmt::Display for SequenceError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidHeader => write!(f, "Data is not a part of a chunk sequence"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Unsupported chunk sequence version {}", version)
+            }
+            Self::InvalidMaxChunkSize(size) => {
+                write!(f, "Maximum chunk size {} can not fit sequence header", size)
+            }
+            Self::TooManyParts => write!(f, "Payload needs too many chunks"),
+            Self::RandomSource => write!(f, "Could not get random bytes from operating system"),
+            Self::NoParts => write!(f, "Chunk sequence has no parts"),
+            Self::MixedMessages => write!(f, "Parts belong to different messages"),
+            Self::InconsistentTotal => write!(f, "Parts disagree on total number of parts"),
+            Self::DuplicatePart(index) => write!(f, "Part {} appears more than once", index),
+            Self::MissingParts(indexes) => write!(f, "Missing parts {:?}", indexes),
+            Self::OutOfOrder { position, index } => {
+                write!(f, "Part {} found at position {}", index, position)
+            }
+            Self::Chunk(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for SequenceError {}
+
+impl From<ChunkParseError> for SequenceError {
+    fn from(err: ChunkParseError) -> Self {
+        Self::Chunk(err)
+    }
+}
+
+#[derive(Debug)]
+pub enum MessageError {
+    Sequence(SequenceError),
+    InvalidLabel,
+    InvalidLabelLength(usize),
+    UnsupportedVersion(u8),
+}
+
+impl fmt::Display for MessageError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Sequence(e) => e.fmt(f),
+            Self::InvalidLabel => write!(f, "Message label is malformed"),
+            Self::InvalidLabelLength(length) => {
+                write!(f, "Label should have 1 to 255 bytes, got {} bytes", length)
+            }
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Unsupported message label version {}", version)
+            }
+        }
+    }
+}
+
+impl std::error::Error for MessageError {}
+
+impl From<SequenceError> for MessageError {
+    fn from(err: SequenceError) -> Self {
+        Self::Sequence(err)
+    }
+}
+
+#[derive(Debug)]
+pub enum CompressionError {
+    InvalidHeader,
+    UnsupportedVersion(u8),
+    UnknownAlgorithm(String),
+    UnavailableAlgorithm(crate::compression::Algorithm),
+    InvalidLevel {
+        algorithm: crate::compression::Algorithm,
+        level: u32,
+    },
+    LengthMismatch,
+    TooLarge(u64),
+    Io(std::io::Error),
+}
+
+impl fmt::Display for CompressionError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidHeader => write!(f, "Data is not a compressed payload"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Unsupported compressed payload version {}", version)
+            }
+            Self::UnknownAlgorithm(name) => write!(
+                f,
+                "Unknown compression algorithm {:?}, expected deflate, zstd or brotli",
+                name
+            ),
+            Self::UnavailableAlgorithm(algorithm) => write!(
+                f,
+                "Compression algorithm {} is not enabled, rebuild with feature {}",
+                algorithm, algorithm
+            ),
+            Self::InvalidLevel { algorithm, level } => {
+                let (lowest, highest, _) = algorithm.levels();
+                write!(
+                    f,
+                    "Level {} is out of range {}..={} for {}",
+                    level, lowest, highest, algorithm
+                )
+            }
+            Self::LengthMismatch => write!(f, "Decompressed length differs from stored length"),
+            Self::TooLarge(length) => {
+                write!(f, "Length {} is larger than the compression limit", length)
+            }
+            Self::Io(e) => write!(f, "Compression failed: {}", e),
+        }
+    }
+}
+
+impl std::error::Error for CompressionError {}
+
+impl From<std::io::Error> for CompressionError {
+    fn from(err: std::io::Error) -> Self {
+        Self::Io(err)
+    }
+}
+
+#[derive(Debug)]
+pub enum EnvelopeError {
+    InvalidHeader,
+    UnsupportedVersion(u8),
+    UnsupportedFlags(u8),
+    FieldTooLong(usize),
+    AlreadyEncrypted,
+    AlreadyCompressed,
+    PassphraseRequired,
+    Crypto(CryptoError),
+    Compression(CompressionError),
+    ChecksumMismatch,
+}
+
+impl fmt::Display for EnvelopeError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::InvalidHeader => write!(f, "Data is not a valid envelope"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Unsupported envelope version {}", version)
+            }
+            Self::UnsupportedFlags(flags) => {
+                write!(f, "Unsupported envelope flags {:#010b}", flags)
+            }
+            Self::FieldTooLong(length) => {
+                write!(f, "Envelope field of {} bytes is too long", length)
+            }
+            Self::AlreadyEncrypted => write!(f, "Envelope payload is already encrypted"),
+            Self::AlreadyCompressed => write!(f, "Envelope payload is already compressed"),
+            Self::PassphraseRequired => write!(f, "Envelope payload is encrypted"),
+            Self::Crypto(e) => e.fmt(f),
+            Self::Compression(e) => e.fmt(f),
+            Self::ChecksumMismatch => write!(f, "Content does not match envelope checksum"),
+        }
+    }
+}
+
+impl std::error::Error for EnvelopeError {}
+
+impl From<CryptoError> for EnvelopeError {
+    fn from(err: CryptoError) -> Self {
+        Self::Crypto(err)
+    }
+}
+
+impl From<CompressionError> for EnvelopeError {
+    fn from(err: CompressionError) -> Self {
+        Self::Compression(err)
+    }
+}
+
 #[derive(Debug)]
 pub enum RemoveChunkError {
     NotFound(String),
@@ -78,6 +543,90 @@ impl fmt::Display for RemoveChunkError {
 
 impl std::error::Error for RemoveChunkError {}
 
+#[derive(Debug)]
+pub enum InsertChunkError {
+    NotFound(String),
+    IndexOutOfBounds { index: usize, len: usize },
+}
+
+impl fmt::Display for InsertChunkError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotFound(chunk_type) => {
+                write!(f, "Chunk with type {} not found", chunk_type)
+            }
+            Self::IndexOutOfBounds { index, len } => {
+                write!(f, "Index {} is out of bounds for {} chunks", index, len)
+            }
+        }
+    }
+}
+
+impl std::error::Error for InsertChunkError {}
+
+#[derive(Debug)]
+pub enum ChunkPositionParseError {
+    Unknown(String),
+}
+
+impl fmt::Display for ChunkPositionParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unknown(value) => write!(
+                f,
+                "unknown position {:?}, expected before-iend, after-ihdr, before-idat or index",
+                value
+            ),
+        }
+    }
+}
+
+impl std::error::Error for ChunkPositionParseError {}
+
+#[derive(Debug)]
+pub enum RepairOptionParseError {
+    UnknownCrcRepair(String),
+    UnknownAfterIend(String),
+}
+
+impl fmt::Display for RepairOptionParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::UnknownCrcRepair(value) => write!(
+                f,
+                "unknown crc repair {:?}, expected all, plausible or none",
+                value
+            ),
+            Self::UnknownAfterIend(value) => write!(
+                f,
+                "unknown handling of chunks after IEND {:?}, expected rescue or drop",
+                value
+            ),
+        }
+    }
+}
+
+impl std::error::Error for RepairOptionParseError {}
+
+#[derive(Debug)]
+pub enum StripPolicyParseError {
+    Unknown(String),
+}
+
+impl fmt::Display for StripPolicyParseError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::Unknown(value) => write!(
+                f,
+                "unknown strip policy {:?}, expected ancillary, private or unsafe",
+                value
+            ),
+        }
+    }
+}
+
+impl std::error::Error for StripPolicyParseError {}
+
 #[derive(Debug)]
 pub enum PngFromBytesError {
     InvalidHeader,
@@ -133,3 +682,62 @@ impl From<PngFromBytesError> for PngFromFileError {
         Self::PngFromBytesError(err)
     }
 }
+
+impl From<ChunkParseError> for PngFromFileError {
+    fn from(err: ChunkParseError) -> Self {
+        Self::PngFromBytesError(err.into())
+    }
+}
+
+#[derive(Debug)]
+pub enum SignatureError {
+    NotSigned,
+    MultipleSignatures(usize),
+    InvalidChunk,
+    UnsupportedVersion(u8),
+    UnsupportedAlgorithm(u8),
+    InvalidCoveredType(String),
+    InvalidKey,
+    KeyMismatch,
+    BadSignature,
+    RandomSource,
+    InsertChunk(InsertChunkError),
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Self::NotSigned => write!(f, "PNG file is not signed"),
+            Self::MultipleSignatures(count) => {
+                write!(f, "PNG file has {} signature chunks, expected one", count)
+            }
+            Self::InvalidChunk => write!(f, "Signature chunk is malformed"),
+            Self::UnsupportedVersion(version) => {
+                write!(f, "Unsupported signature version {}", version)
+            }
+            Self::UnsupportedAlgorithm(algorithm) => {
+                write!(f, "Unsupported signature algorithm {}", algorithm)
+            }
+            Self::InvalidCoveredType(chunk_type) => write!(
+                f,
+                "Chunk type {} can not be listed as covered, only ancillary types can",
+                chunk_type
+            ),
+            Self::InvalidKey => write!(f, "Key must be 32 bytes written as 64 hex digits"),
+            Self::KeyMismatch => write!(f, "PNG file is signed with a different key"),
+            Self::BadSignature => {
+                write!(f, "Signature does not match, PNG file has been altered")
+            }
+            Self::RandomSource => write!(f, "Could not get random bytes from operating system"),
+            Self::InsertChunk(e) => e.fmt(f),
+        }
+    }
+}
+
+impl std::error::Error for SignatureError {}
+
+impl From<InsertChunkError> for SignatureError {
+    fn from(err: InsertChunkError) -> Self {
+        Self::InsertChunk(err)
+    }
+}
@@ -0,0 +1,279 @@
+use std::convert::TryFrom;
+use std::str::FromStr;
+
+use byteorder::ByteOrder;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::IhdrParseError;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ColorType {
+    Grayscale,
+    Rgb,
+    Indexed,
+    GrayscaleAlpha,
+    Rgba,
+}
+
+impl ColorType {
+    pub fn code(&self) -> u8 {
+        match self {
+            Self::Grayscale => 0,
+            Self::Rgb => 2,
+            Self::Indexed => 3,
+            Self::GrayscaleAlpha => 4,
+            Self::Rgba => 6,
+        }
+    }
+    /// Number of samples in one pixel
+    pub fn channels(&self) -> u8 {
+        match self {
+            Self::Grayscale | Self::Indexed => 1,
+            Self::GrayscaleAlpha => 2,
+            Self::Rgb => 3,
+            Self::Rgba => 4,
+        }
+    }
+    /// Bit depths the PNG specification allows for this color type
+    pub fn allowed_bit_depths(&self) -> &'static [u8] {
+        match self {
+            Self::Grayscale => &[1, 2, 4, 8, 16],
+            Self::Indexed => &[1, 2, 4, 8],
+            Self::Rgb | Self::GrayscaleAlpha | Self::Rgba => &[8, 16],
+        }
+    }
+}
+
+impl TryFrom<u8> for ColorType {
+    type Error = IhdrParseError;
+
+    fn try_from(code: u8) -> Result<Self, Self::Error> {
+        match code {
+            0 => Ok(Self::Grayscale),
+            2 => Ok(Self::Rgb),
+            3 => Ok(Self::Indexed),
+            4 => Ok(Self::GrayscaleAlpha),
+            6 => Ok(Self::Rgba),
+            _ => Err(IhdrParseError::InvalidColorType(code)),
+        }
+    }
+}
+
+impl std::fmt::Display for ColorType {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let name = match self {
+            Self::Grayscale => "grayscale",
+            Self::Rgb => "truecolor",
+            Self::Indexed => "indexed-color",
+            Self::GrayscaleAlpha => "grayscale with alpha",
+            Self::Rgba => "truecolor with alpha",
+        };
+        write!(f, "{}", name)
+    }
+}
+
+/// Decoded contents of the IHDR chunk
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Ihdr {
+    width: u32,
+    height: u32,
+    bit_depth: u8,
+    color_type: ColorType,
+    compression: u8,
+    filter: u8,
+    interlace: u8,
+}
+
+impl Ihdr {
+    pub const LENGTH: usize = 13;
+    const MAX_DIMENSION: u32 = (1 << 31) - 1;
+
+    pub fn new(
+        width: u32,
+        height: u32,
+        bit_depth: u8,
+        color_type: ColorType,
+        interlaced: bool,
+    ) -> Result<Self, IhdrParseError> {
+        let ihdr = Self {
+            width,
+            height,
+            bit_depth,
+            color_type,
+            compression: 0,
+            filter: 0,
+            interlace: interlaced as u8,
+        };
+        ihdr.validate()?;
+        Ok(ihdr)
+    }
+    /// Parses data part of the IHDR chunk
+    pub fn from_data(data: &[u8]) -> Result<Self, IhdrParseError> {
+        if data.len() != Self::LENGTH {
+            return Err(IhdrParseError::InvalidLength(data.len()));
+        }
+        let ihdr = Self {
+            width: byteorder::BigEndian::read_u32(&data[0..4]),
+            height: byteorder::BigEndian::read_u32(&data[4..8]),
+            bit_depth: data[8],
+            color_type: ColorType::try_from(data[9])?,
+            compression: data[10],
+            filter: data[11],
+            interlace: data[12],
+        };
+        ihdr.validate()?;
+        Ok(ihdr)
+    }
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+    pub fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+    pub fn compression(&self) -> u8 {
+        self.compression
+    }
+    pub fn filter(&self) -> u8 {
+        self.filter
+    }
+    pub fn interlace(&self) -> u8 {
+        self.interlace
+    }
+    pub fn is_interlaced(&self) -> bool {
+        self.interlace == 1
+    }
+    pub fn as_bytes(&self) -> [u8; Self::LENGTH] {
+        let mut bytes = [0; Self::LENGTH];
+        bytes[0..4].copy_from_slice(&self.width.to_be_bytes());
+        bytes[4..8].copy_from_slice(&self.height.to_be_bytes());
+        bytes[8] = self.bit_depth;
+        bytes[9] = self.color_type.code();
+        bytes[10] = self.compression;
+        bytes[11] = self.filter;
+        bytes[12] = self.interlace;
+        bytes
+    }
+    pub fn to_chunk(&self) -> Chunk {
+        let chunk_type = ChunkType::from_str("IHDR").expect("IHDR is a valid chunk type");
+        Chunk::new(chunk_type, &self.as_bytes())
+    }
+
+    fn validate(&self) -> Result<(), IhdrParseError> {
+        let is_valid_dimension = |value| value > 0 && value <= Self::MAX_DIMENSION;
+        if !is_valid_dimension(self.width) || !is_valid_dimension(self.height) {
+            return Err(IhdrParseError::InvalidDimensions {
+                width: self.width,
+                height: self.height,
+            });
+        }
+        if !self
+            .color_type
+            .allowed_bit_depths()
+            .contains(&self.bit_depth)
+        {
+            return Err(IhdrParseError::InvalidBitDepth {
+                bit_depth: self.bit_depth,
+                color_type: self.color_type.code(),
+            });
+        }
+        if self.compression != 0 {
+            return Err(IhdrParseError::UnknownCompressionMethod(self.compression));
+        }
+        if self.filter != 0 {
+            return Err(IhdrParseError::UnknownFilterMethod(self.filter));
+        }
+        if self.interlace > 1 {
+            return Err(IhdrParseError::UnknownInterlaceMethod(self.interlace));
+        }
+        Ok(())
+    }
+}
+
+impl TryFrom<&Chunk> for Ihdr {
+    type Error = IhdrParseError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        if chunk.chunk_type().bytes() != *b"IHDR" {
+            return Err(IhdrParseError::WrongChunkType(
+                chunk.chunk_type().to_string(),
+            ));
+        }
+        Self::from_data(chunk.data())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn testing_data() -> Vec<u8> {
+        vec![0, 0, 0, 50, 0, 0, 0, 40, 8, 6, 0, 0, 0]
+    }
+
+    #[test]
+    fn test_ihdr_from_data() {
+        let ihdr = Ihdr::from_data(&testing_data()).unwrap();
+        assert_eq!(ihdr.width(), 50);
+        assert_eq!(ihdr.height(), 40);
+        assert_eq!(ihdr.bit_depth(), 8);
+        assert_eq!(ihdr.color_type(), ColorType::Rgba);
+        assert_eq!(ihdr.compression(), 0);
+        assert_eq!(ihdr.filter(), 0);
+        assert_eq!(ihdr.interlace(), 0);
+        assert!(!ihdr.is_interlaced());
+    }
+
+    #[test]
+    fn test_ihdr_chunk_round_trip() {
+        let ihdr = Ihdr::new(640, 480, 16, ColorType::Rgb, true).unwrap();
+        let chunk = ihdr.to_chunk();
+        assert_eq!(chunk.chunk_type().to_string(), "IHDR");
+        assert_eq!(chunk.length(), 13);
+        assert_eq!(Ihdr::try_from(&chunk).unwrap(), ihdr);
+    }
+
+    #[test]
+    fn test_ihdr_from_wrong_chunk() {
+        let chunk = Chunk::new(ChunkType::from_str("RuSt").unwrap(), &testing_data());
+        assert!(Ihdr::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_ihdr_invalid_length() {
+        assert!(Ihdr::from_data(&testing_data()[..12]).is_err());
+    }
+
+    #[test]
+    fn test_ihdr_invalid_fields() {
+        let invalid = |index: usize, value: u8| {
+            let mut data = testing_data();
+            data[index] = value;
+            Ihdr::from_data(&data).is_err()
+        };
+        assert!(invalid(3, 0)); // zero width
+        assert!(invalid(0, 0x80)); // width above 2^31 - 1
+        assert!(invalid(9, 5)); // unknown color type
+        assert!(invalid(8, 4)); // rgba requires 8 or 16 bits
+        assert!(invalid(10, 1)); // compression method
+        assert!(invalid(11, 1)); // filter method
+        assert!(invalid(12, 2)); // interlace method
+    }
+
+    #[test]
+    fn test_bit_depth_combinations() {
+        assert!(Ihdr::new(1, 1, 1, ColorType::Grayscale, false).is_ok());
+        assert!(Ihdr::new(1, 1, 16, ColorType::Grayscale, false).is_ok());
+        assert!(Ihdr::new(1, 1, 4, ColorType::Indexed, false).is_ok());
+        assert!(Ihdr::new(1, 1, 16, ColorType::Indexed, false).is_err());
+        assert!(Ihdr::new(1, 1, 8, ColorType::GrayscaleAlpha, false).is_ok());
+        assert!(Ihdr::new(1, 1, 2, ColorType::GrayscaleAlpha, false).is_err());
+        assert!(Ihdr::new(1, 1, 3, ColorType::Grayscale, false).is_err());
+    }
+}
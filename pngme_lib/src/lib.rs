@@ -1,4 +1,23 @@
 pub mod chunk;
 pub mod chunk_type;
+pub mod compression;
+pub mod crypto;
+pub mod detect;
+pub mod diff;
+pub mod encoder;
+pub mod envelope;
 pub mod error;
+pub mod ihdr;
+pub mod lsb;
+pub mod message;
+pub mod pixels;
 pub mod png;
+pub mod registry;
+pub mod repair;
+pub mod sequence;
+pub mod signature;
+pub mod strip;
+#[cfg(test)]
+mod test_utils;
+pub mod text;
+pub mod validate;
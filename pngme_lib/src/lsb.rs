@@ -0,0 +1,179 @@
+//! Hides payload in the least significant bits of pixel samples inside IDAT
+//!
+//! Payload is prefixed with magic `pmLS` and its length as big endian u32, every bit of
+//! the result is written to one sample starting from the most significant bit of each byte.
+use std::convert::TryFrom;
+
+use crate::encoder::Encoder;
+use crate::error::LsbError;
+use crate::ihdr::ColorType;
+use crate::pixels::{self, PixelBuffer};
+use crate::png::Png;
+
+const MAGIC: &[u8; 4] = b"pmLS";
+const HEADER_LENGTH: usize = MAGIC.len() + 4;
+
+/// Number of payload bytes that fit into the image
+pub fn capacity(png: &Png) -> Result<usize, LsbError> {
+    let image = read_pixels(png)?;
+    Ok((sample_positions(&image).len() / 8).saturating_sub(HEADER_LENGTH))
+}
+
+/// Writes payload into pixel samples and replaces IDAT chunks using default encoder settings
+pub fn embed(png: &mut Png, payload: &[u8]) -> Result<(), LsbError> {
+    embed_with_encoder(png, payload, &Encoder::default())
+}
+
+/// Writes payload into pixel samples and replaces IDAT chunks using `encoder`
+pub fn embed_with_encoder(
+    png: &mut Png,
+    payload: &[u8],
+    encoder: &Encoder,
+) -> Result<(), LsbError> {
+    let mut image = read_pixels(png)?;
+    let positions = sample_positions(&image);
+    let capacity = (positions.len() / 8).saturating_sub(HEADER_LENGTH);
+    let length = u32::try_from(payload.len())
+        .ok()
+        .filter(|_| payload.len() <= capacity);
+    let length = length.ok_or(LsbError::PayloadTooLarge {
+        payload: payload.len(),
+        capacity,
+    })?;
+    let length = length.to_be_bytes();
+    let bits = MAGIC
+        .iter()
+        .chain(length.iter())
+        .chain(payload.iter())
+        .flat_map(|byte| (0..8).rev().map(move |bit| (byte >> bit) & 1));
+    let data = image.data_mut();
+    for (position, bit) in positions.into_iter().zip(bits) {
+        data[position] = (data[position] & !1) | bit;
+    }
+    encoder.encode_into(png, &image)?;
+    Ok(())
+}
+
+/// Reads payload previously written by `embed`
+pub fn extract(png: &Png) -> Result<Vec<u8>, LsbError> {
+    let image = read_pixels(png)?;
+    let data = image.data();
+    let positions = sample_positions(&image);
+    let mut bytes = positions.chunks_exact(8).map(|bits| {
+        bits.iter()
+            .fold(0, |byte, &position| (byte << 1) | (data[position] & 1))
+    });
+    let header: Vec<u8> = bytes.by_ref().take(HEADER_LENGTH).collect();
+    if header.len() != HEADER_LENGTH || header[..MAGIC.len()] != MAGIC[..] {
+        return Err(LsbError::NoPayload);
+    }
+    let length = u32::from_be_bytes([header[4], header[5], header[6], header[7]]) as usize;
+    let payload: Vec<u8> = bytes.take(length).collect();
+    if payload.len() != length {
+        return Err(LsbError::NoPayload);
+    }
+    Ok(payload)
+}
+
+fn read_pixels(png: &Png) -> Result<PixelBuffer, LsbError> {
+    let ihdr = png.header()?;
+    if ihdr.color_type() == ColorType::Indexed || ihdr.bit_depth() < 8 {
+        return Err(LsbError::UnsupportedFormat(
+            "only 8 and 16 bit non-indexed images are supported".into(),
+        ));
+    }
+    Ok(pixels::decode(png)?)
+}
+
+/// Indexes of the least significant byte of every sample
+fn sample_positions(image: &PixelBuffer) -> Vec<usize> {
+    let length = image.data().len();
+    match image.format().bit_depth() {
+        16 => (1..length).step_by(2).collect(),
+        _ => (0..length).collect(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::ihdr::Ihdr;
+    use crate::pixels::PixelFormat;
+    use crate::test_utils::{chunk, dice};
+
+    fn testing_png(bit_depth: u8, interlaced: bool) -> Png {
+        let ihdr = Ihdr::new(16, 8, bit_depth, ColorType::Rgb, interlaced).unwrap();
+        let format = PixelFormat::from(&ihdr);
+        let data = (0..format.row_bytes(16) * 8)
+            .map(|i| (i * 7 % 256) as u8)
+            .collect();
+        let image = PixelBuffer::new(16, 8, format, data).unwrap();
+        let mut chunks = vec![ihdr.to_chunk(), chunk("tEXt", b"Comment\0kept")];
+        chunks.extend(Encoder::default().encode(&image, &ihdr).unwrap());
+        chunks.push(chunk("IEND", &[]));
+        Png::from_chunks(chunks)
+    }
+
+    #[test]
+    fn test_embed_and_extract() {
+        for &(bit_depth, interlaced) in [(8, false), (16, false), (8, true)].iter() {
+            let mut png = testing_png(bit_depth, interlaced);
+            embed(&mut png, b"secret").unwrap();
+            let png = Png::try_from(&png.as_bytes()[..]).unwrap();
+            assert_eq!(extract(&png).unwrap(), b"secret");
+            let types: Vec<_> = png
+                .chunks()
+                .iter()
+                .map(|c| c.chunk_type().to_string())
+                .collect();
+            assert_eq!(types, ["IHDR", "tEXt", "IDAT", "IEND"]);
+        }
+    }
+
+    #[test]
+    fn test_changes_only_least_significant_bits() {
+        let original = testing_png(8, false);
+        let mut png = testing_png(8, false);
+        embed(&mut png, b"secret").unwrap();
+        let before = pixels::decode(&original).unwrap();
+        let after = pixels::decode(&png).unwrap();
+        assert!(before
+            .data()
+            .iter()
+            .zip(after.data().iter())
+            .all(|(a, b)| a >> 1 == b >> 1));
+        assert_ne!(before.data(), after.data());
+    }
+
+    #[test]
+    fn test_capacity() {
+        // 16x8 rgb image has 384 samples
+        assert_eq!(
+            capacity(&testing_png(8, false)).unwrap(),
+            384 / 8 - HEADER_LENGTH
+        );
+        assert_eq!(
+            capacity(&testing_png(16, false)).unwrap(),
+            384 / 8 - HEADER_LENGTH
+        );
+        let mut png = testing_png(8, false);
+        let payload = vec![0; 384 / 8 - HEADER_LENGTH + 1];
+        let result = embed(&mut png, &payload);
+        assert!(matches!(result, Err(LsbError::PayloadTooLarge { .. })));
+    }
+
+    #[test]
+    fn test_extract_without_payload() {
+        assert!(matches!(
+            extract(&testing_png(8, false)),
+            Err(LsbError::NoPayload)
+        ));
+    }
+
+    #[test]
+    fn test_image_file() {
+        let mut png = dice();
+        embed(&mut png, b"hidden in pixels").unwrap();
+        assert_eq!(extract(&png).unwrap(), b"hidden in pixels");
+    }
+}
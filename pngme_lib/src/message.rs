@@ -0,0 +1,205 @@
+//! Messages stored in chunks of one type, addressable by index or label
+//!
+//! A message is either a single chunk or a sequence of chunks joined by `sequence`. Its data
+//! can start with a label: magic `pmLB`, format version, label length as u8 and UTF-8 label.
+use std::collections::HashMap;
+
+use crate::error::MessageError;
+use crate::sequence::{self, SequenceHeader};
+
+const MAGIC: &[u8; 4] = b"pmLB";
+const VERSION: u8 = 1;
+
+/// Message found in a file together with the chunks it is stored in
+#[derive(Debug)]
+pub struct Message {
+    chunk_indexes: Vec<usize>,
+    content: Result<(Option<String>, Vec<u8>), MessageError>,
+}
+
+impl Message {
+    /// Indexes of chunks holding the message, in file order
+    pub fn chunk_indexes(&self) -> &[usize] {
+        &self.chunk_indexes
+    }
+
+    /// Label or `None` when the message is not labeled or could not be read
+    pub fn label(&self) -> Option<&str> {
+        match &self.content {
+            Ok((label, _)) => label.as_deref(),
+            Err(_) => None,
+        }
+    }
+
+    /// Message data without sequence headers and label
+    pub fn data(&self) -> Result<&[u8], &MessageError> {
+        match &self.content {
+            Ok((_, data)) => Ok(data),
+            Err(e) => Err(e),
+        }
+    }
+
+    pub fn into_data(self) -> Result<Vec<u8>, MessageError> {
+        self.content.map(|(_, data)| data)
+    }
+}
+
+/// Prefixes payload with a label
+pub fn add_label(label: &str, payload: &[u8]) -> Result<Vec<u8>, MessageError> {
+    if label.is_empty() || label.len() > usize::from(u8::MAX) {
+        return Err(MessageError::InvalidLabelLength(label.len()));
+    }
+    let mut data = Vec::with_capacity(MAGIC.len() + 2 + label.len() + payload.len());
+    data.extend_from_slice(MAGIC);
+    data.extend_from_slice(&[VERSION, label.len() as u8]);
+    data.extend_from_slice(label.as_bytes());
+    data.extend_from_slice(payload);
+    Ok(data)
+}
+
+/// Separates label from payload, data without label is returned as is
+pub fn split_label(data: &[u8]) -> Result<(Option<&str>, &[u8]), MessageError> {
+    if !data.starts_with(MAGIC) {
+        return Ok((None, data));
+    }
+    let rest = &data[MAGIC.len()..];
+    match rest {
+        [VERSION, length, rest @ ..] if rest.len() >= usize::from(*length) => {
+            let (label, payload) = rest.split_at(usize::from(*length));
+            let label = std::str::from_utf8(label).map_err(|_| MessageError::InvalidLabel)?;
+            Ok((Some(label), payload))
+        }
+        [version, ..] if *version != VERSION => Err(MessageError::UnsupportedVersion(*version)),
+        _ => Err(MessageError::InvalidLabel),
+    }
+}
+
+/// Groups chunk data into messages ordered by their first chunk
+///
+/// Takes index and data of every chunk of one type. Parts of a sequence are grouped by
+/// message id, every other chunk is a message on its own.
+pub fn find_messages<'a>(chunks: impl IntoIterator<Item = (usize, &'a [u8])>) -> Vec<Message> {
+    let mut groups: Vec<Group> = Vec::new();
+    let mut sequences: HashMap<u32, usize> = HashMap::new();
+    for (index, data) in chunks {
+        let group = match SequenceHeader::parse(data) {
+            Ok((header, _)) => match sequences.get(&header.message_id()) {
+                Some(&group) => &mut groups[group],
+                None => {
+                    sequences.insert(header.message_id(), groups.len());
+                    groups.push(Group::new(true));
+                    groups.last_mut().expect("group is just pushed")
+                }
+            },
+            Err(_) => {
+                groups.push(Group::new(false));
+                groups.last_mut().expect("group is just pushed")
+            }
+        };
+        group.chunk_indexes.push(index);
+        group.parts.push(data);
+    }
+    groups.into_iter().map(Group::into_message).collect()
+}
+
+/// Chunks making up one message
+struct Group<'a> {
+    chunk_indexes: Vec<usize>,
+    parts: Vec<&'a [u8]>,
+    is_sequence: bool,
+}
+
+impl<'a> Group<'a> {
+    fn new(is_sequence: bool) -> Self {
+        Group {
+            chunk_indexes: Vec::new(),
+            parts: Vec::new(),
+            is_sequence,
+        }
+    }
+
+    fn into_message(self) -> Message {
+        let data = match self.is_sequence {
+            true => sequence::reassemble(self.parts).map_err(MessageError::from),
+            false => Ok(self.parts[0].to_vec()),
+        };
+        let content = data.and_then(|data| {
+            let (label, payload) = split_label(&data)?;
+            Ok((label.map(str::to_string), payload.to_vec()))
+        });
+        Message {
+            chunk_indexes: self.chunk_indexes,
+            content,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk::Chunk;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn chunk_type() -> ChunkType {
+        ChunkType::from_str("ruSt").unwrap()
+    }
+
+    fn plain(data: &[u8]) -> Vec<Chunk> {
+        vec![Chunk::new(chunk_type(), data)]
+    }
+
+    fn split(data: &[u8]) -> Vec<Chunk> {
+        sequence::split(&chunk_type(), data, sequence::HEADER_LENGTH + 4).unwrap()
+    }
+
+    fn messages(chunks: &[Chunk]) -> Vec<Message> {
+        find_messages(chunks.iter().map(Chunk::data).enumerate())
+    }
+
+    #[test]
+    fn test_label_round_trip() {
+        let data = add_label("notes", b"payload").unwrap();
+        assert_eq!(
+            split_label(&data).unwrap(),
+            (Some("notes"), &b"payload"[..])
+        );
+        assert_eq!(split_label(b"plain").unwrap(), (None, &b"plain"[..]));
+        assert!(add_label("", b"payload").is_err());
+        assert!(add_label(&"x".repeat(256), b"payload").is_err());
+        assert!(split_label(&data[..8]).is_err());
+    }
+
+    #[test]
+    fn test_find_messages() {
+        let mut chunks = plain(b"first");
+        let second = split(&add_label("second", b"sequence payload").unwrap());
+        let third = split(b"other sequence");
+        chunks.push(second[0].clone());
+        chunks.extend(third);
+        chunks.extend(second[1..].iter().cloned());
+        chunks.extend(plain(&add_label("fourth", b"labeled").unwrap()));
+
+        let messages = messages(&chunks);
+        assert_eq!(messages.len(), 4);
+        assert_eq!(messages[0].data().unwrap(), b"first");
+        assert_eq!(messages[0].label(), None);
+        assert_eq!(messages[1].data().unwrap(), b"sequence payload");
+        assert_eq!(messages[1].label(), Some("second"));
+        assert_eq!(messages[1].chunk_indexes()[0], 1);
+        assert_eq!(messages[2].data().unwrap(), b"other sequence");
+        assert_eq!(messages[3].label(), Some("fourth"));
+        let all: usize = messages.iter().map(|m| m.chunk_indexes().len()).sum();
+        assert_eq!(all, chunks.len());
+    }
+
+    #[test]
+    fn test_broken_sequence_keeps_chunk_indexes() {
+        let mut chunks = split(b"sequence payload");
+        chunks.remove(1);
+        let messages = messages(&chunks);
+        assert_eq!(messages.len(), 1);
+        assert!(messages[0].data().is_err());
+        assert_eq!(messages[0].chunk_indexes().len(), chunks.len());
+    }
+}
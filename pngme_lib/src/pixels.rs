@@ -0,0 +1,656 @@
+//! Decoding of image data stored in IDAT chunks into raw pixels
+//!
+//! IDAT chunks are concatenated and inflated, scanline filters are reversed and Adam7
+//! interlaced images are assembled into a single buffer. Rows in the resulting buffer are
+//! packed the same way as in PNG: samples are big endian and pixels with less than 8 bits
+//! share bytes starting from the most significant bit.
+use std::io::{Read, Write};
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::chunk::Chunk;
+use crate::error::ImageDataError;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+
+/// Starting column, starting row, column step and row step of the seven Adam7 passes
+pub(crate) const ADAM7: [(u32, u32, u32, u32); 7] = [
+    (0, 0, 8, 8),
+    (4, 0, 8, 8),
+    (0, 4, 4, 8),
+    (2, 0, 4, 4),
+    (0, 2, 2, 4),
+    (1, 0, 2, 2),
+    (0, 1, 1, 2),
+];
+
+/// Layout of a single pixel
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct PixelFormat {
+    color_type: ColorType,
+    bit_depth: u8,
+}
+
+impl PixelFormat {
+    pub fn color_type(&self) -> ColorType {
+        self.color_type
+    }
+
+    pub fn bit_depth(&self) -> u8 {
+        self.bit_depth
+    }
+
+    pub fn channels(&self) -> u8 {
+        self.color_type.channels()
+    }
+
+    pub fn bits_per_pixel(&self) -> usize {
+        usize::from(self.bit_depth) * usize::from(self.channels())
+    }
+
+    /// Bytes per complete pixel rounded up to one, distance used by scanline filters
+    pub fn bytes_per_pixel(&self) -> usize {
+        self.bits_per_pixel().div_ceil(8).max(1)
+    }
+
+    /// Bytes in a row of given width
+    pub fn row_bytes(&self, width: u32) -> usize {
+        (width as usize * self.bits_per_pixel()).div_ceil(8)
+    }
+
+    /// Bytes in a row of given width, `None` when it does not fit in `usize`
+    fn checked_row_bytes(&self, width: u32) -> Option<usize> {
+        let bits = (width as usize).checked_mul(self.bits_per_pixel())?;
+        Some(bits.div_ceil(8))
+    }
+}
+
+impl From<&Ihdr> for PixelFormat {
+    fn from(ihdr: &Ihdr) -> Self {
+        PixelFormat {
+            color_type: ihdr.color_type(),
+            bit_depth: ihdr.bit_depth(),
+        }
+    }
+}
+
+/// Unfiltered and deinterlaced pixels of an image
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PixelBuffer {
+    width: u32,
+    height: u32,
+    format: PixelFormat,
+    data: Vec<u8>,
+}
+
+impl PixelBuffer {
+    pub fn new(
+        width: u32,
+        height: u32,
+        format: PixelFormat,
+        data: Vec<u8>,
+    ) -> Result<Self, ImageDataError> {
+        let expected = format
+            .checked_row_bytes(width)
+            .and_then(|row_bytes| row_bytes.checked_mul(height as usize))
+            .ok_or(ImageDataError::TooLarge {
+                limit: MAX_IMAGE_DATA,
+            })?;
+        if data.len() != expected {
+            return Err(ImageDataError::UnexpectedLength {
+                expected,
+                actual: data.len(),
+            });
+        }
+        Ok(PixelBuffer {
+            width,
+            height,
+            format,
+            data,
+        })
+    }
+
+    pub fn width(&self) -> u32 {
+        self.width
+    }
+
+    pub fn height(&self) -> u32 {
+        self.height
+    }
+
+    pub fn format(&self) -> PixelFormat {
+        self.format
+    }
+
+    pub fn data(&self) -> &[u8] {
+        &self.data
+    }
+
+    pub fn data_mut(&mut self) -> &mut [u8] {
+        &mut self.data
+    }
+
+    pub fn into_data(self) -> Vec<u8> {
+        self.data
+    }
+
+    pub fn row_bytes(&self) -> usize {
+        self.format.row_bytes(self.width)
+    }
+
+    /// Packed bytes of row `y`
+    pub fn row(&self, y: u32) -> Option<&[u8]> {
+        let row_bytes = self.row_bytes();
+        let start = y as usize * row_bytes;
+        (y < self.height).then(|| &self.data[start..start + row_bytes])
+    }
+
+    /// Value of `channel` of pixel at column `x` and row `y`
+    pub fn sample(&self, x: u32, y: u32, channel: u8) -> Option<u16> {
+        if x >= self.width || channel >= self.format.channels() {
+            return None;
+        }
+        let row = self.row(y)?;
+        let bit_depth = usize::from(self.format.bit_depth);
+        let offset = x as usize * self.format.bits_per_pixel() + usize::from(channel) * bit_depth;
+        Some(match bit_depth {
+            16 => u16::from_be_bytes([row[offset / 8], row[offset / 8 + 1]]),
+            8 => u16::from(row[offset / 8]),
+            _ => {
+                let shift = 8 - bit_depth - offset % 8;
+                u16::from((row[offset / 8] >> shift) & ((1 << bit_depth) - 1))
+            }
+        })
+    }
+}
+
+/// Largest decoded image data in bytes, guards against crafted headers and zlib bombs
+pub const MAX_IMAGE_DATA: usize = 1 << 30;
+
+/// Decodes image data of a PNG into raw pixels
+pub fn decode(png: &Png) -> Result<PixelBuffer, ImageDataError> {
+    let ihdr = png.header()?;
+    let format = PixelFormat::from(&ihdr);
+    let too_large = ImageDataError::TooLarge {
+        limit: MAX_IMAGE_DATA,
+    };
+    let image_length = format
+        .checked_row_bytes(ihdr.width())
+        .and_then(|row_bytes| row_bytes.checked_mul(ihdr.height() as usize));
+    let expected = match (filtered_length(&ihdr, format), image_length) {
+        (Some(filtered), Some(image)) if filtered.max(image) <= MAX_IMAGE_DATA => filtered,
+        _ => return Err(too_large),
+    };
+    // Header is checked against the data before any buffer of its size is allocated
+    let filtered = inflate_up_to(png, expected)?;
+    if filtered.len() < expected {
+        return Err(ImageDataError::UnexpectedLength {
+            expected,
+            actual: filtered.len(),
+        });
+    }
+    if !ihdr.is_interlaced() {
+        let rows = ihdr.height() as usize;
+        let row_bytes = format.row_bytes(ihdr.width());
+        let data = unfilter(&filtered, row_bytes, rows, format.bytes_per_pixel())?;
+        return PixelBuffer::new(ihdr.width(), ihdr.height(), format, data);
+    }
+    let mut image = PixelBuffer {
+        width: ihdr.width(),
+        height: ihdr.height(),
+        format,
+        data: vec![0; format.row_bytes(ihdr.width()) * ihdr.height() as usize],
+    };
+    let mut offset = 0;
+    for pass in ADAM7.iter() {
+        let (width, height) = pass_size(ihdr.width(), ihdr.height(), pass);
+        if width == 0 || height == 0 {
+            continue;
+        }
+        let row_bytes = format.row_bytes(width);
+        let remaining = filtered.get(offset..).unwrap_or(&[]);
+        let pass_data = unfilter(
+            remaining,
+            row_bytes,
+            height as usize,
+            format.bytes_per_pixel(),
+        )?;
+        offset += (row_bytes + 1) * height as usize;
+        let &(x0, y0, dx, dy) = pass;
+        let image_row_bytes = image.row_bytes();
+        for y in 0..height {
+            let source = &pass_data[y as usize * row_bytes..(y as usize + 1) * row_bytes];
+            let target_start = (y0 + y * dy) as usize * image_row_bytes;
+            let target = &mut image.data[target_start..target_start + image_row_bytes];
+            for x in 0..width {
+                copy_pixel(source, x, target, x0 + x * dx, format.bits_per_pixel());
+            }
+        }
+    }
+    Ok(image)
+}
+/// Length of filtered scanlines of all passes described by `ihdr`, `None` on overflow
+fn filtered_length(ihdr: &Ihdr, format: PixelFormat) -> Option<usize> {
+    let (width, height) = (ihdr.width(), ihdr.height());
+    let passes: Vec<(u32, u32)> = match ihdr.is_interlaced() {
+        true => ADAM7
+            .iter()
+            .map(|pass| pass_size(width, height, pass))
+            .collect(),
+        false => vec![(width, height)],
+    };
+    passes
+        .into_iter()
+        .filter(|&(width, height)| width > 0 && height > 0)
+        .try_fold(0usize, |total, (width, height)| {
+            let line = format.checked_row_bytes(width)?.checked_add(1)?;
+            total.checked_add(line.checked_mul(height as usize)?)
+        })
+}
+
+/// Concatenates consecutive IDAT chunks and inflates the zlib stream
+pub(crate) fn inflate(png: &Png) -> Result<Vec<u8>, ImageDataError> {
+    let data = inflate_up_to(png, MAX_IMAGE_DATA + 1)?;
+    if data.len() > MAX_IMAGE_DATA {
+        return Err(ImageDataError::TooLarge {
+            limit: MAX_IMAGE_DATA,
+        });
+    }
+    Ok(data)
+}
+
+/// Inflates at most `limit` bytes of image data, the rest of the stream is ignored
+fn inflate_up_to(png: &Png, limit: usize) -> Result<Vec<u8>, ImageDataError> {
+    let compressed = compressed_data(png)?;
+    let mut data = Vec::new();
+    ZlibDecoder::new(&compressed[..])
+        .take(limit as u64)
+        .read_to_end(&mut data)
+        .map_err(ImageDataError::Decompression)?;
+    Ok(data)
+}
+
+/// Zlib stream made of consecutive IDAT chunks
+pub(crate) fn compressed_data(png: &Png) -> Result<Vec<u8>, ImageDataError> {
+    let is_idat = |chunk: &&Chunk| chunk.chunk_type().bytes() == *b"IDAT";
+    let mut chunks = png.chunks().iter().skip_while(|chunk| !is_idat(chunk));
+    let compressed: Vec<u8> = chunks
+        .by_ref()
+        .take_while(is_idat)
+        .flat_map(|chunk| chunk.data().iter().copied())
+        .collect();
+    if compressed.is_empty() {
+        return Err(ImageDataError::MissingImageData);
+    }
+    if chunks.any(|chunk| is_idat(&chunk)) {
+        return Err(ImageDataError::NonConsecutiveImageData);
+    }
+    Ok(compressed)
+}
+
+/// Width and height of an Adam7 pass, either can be zero for small images
+pub(crate) fn pass_size(width: u32, height: u32, pass: &(u32, u32, u32, u32)) -> (u32, u32) {
+    let &(x0, y0, dx, dy) = pass;
+    let size = |length: u32, start: u32, step: u32| match length > start {
+        true => (length - start).div_ceil(step),
+        false => 0,
+    };
+    (size(width, x0, dx), size(height, y0, dy))
+}
+
+/// Packed rows of the sub image that makes up an Adam7 pass
+pub(crate) fn pass_data(image: &PixelBuffer, pass: &(u32, u32, u32, u32)) -> Vec<u8> {
+    let (width, height) = pass_size(image.width, image.height, pass);
+    let &(x0, y0, dx, dy) = pass;
+    let row_bytes = image.format.row_bytes(width);
+    let mut data = vec![0; row_bytes * height as usize];
+    for y in 0..height {
+        let source = image.row(y0 + y * dy).expect("pass row is inside image");
+        let target = &mut data[y as usize * row_bytes..(y as usize + 1) * row_bytes];
+        for x in 0..width {
+            copy_pixel(
+                source,
+                x0 + x * dx,
+                target,
+                x,
+                image.format.bits_per_pixel(),
+            );
+        }
+    }
+    data
+}
+
+/// Copies packed pixel at column `from` of `source` row to column `to` of `target` row
+fn copy_pixel(source: &[u8], from: u32, target: &mut [u8], to: u32, bits: usize) {
+    if bits >= 8 {
+        let (from, to) = (from as usize * bits / 8, to as usize * bits / 8);
+        target[to..to + bits / 8].copy_from_slice(&source[from..from + bits / 8]);
+        return;
+    }
+    let (from, to) = (from as usize * bits, to as usize * bits);
+    let mask = (1u8 << bits) - 1;
+    let value = (source[from / 8] >> (8 - bits - from % 8)) & mask;
+    let shift = 8 - bits - to % 8;
+    target[to / 8] = (target[to / 8] & !(mask << shift)) | (value << shift);
+}
+
+pub(crate) fn deflate(data: &[u8], level: u32) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::new(level));
+    encoder
+        .write_all(data)
+        .and_then(|_| encoder.finish())
+        .expect("writing to vector does not fail")
+}
+
+/// Reverses filtering of `rows` scanlines each `row_bytes` long plus leading filter type byte
+pub(crate) fn unfilter(
+    filtered: &[u8],
+    row_bytes: usize,
+    rows: usize,
+    unit: usize,
+) -> Result<Vec<u8>, ImageDataError> {
+    let expected = row_bytes
+        .checked_add(1)
+        .and_then(|line| line.checked_mul(rows))
+        .ok_or(ImageDataError::TooLarge {
+            limit: MAX_IMAGE_DATA,
+        })?;
+    if filtered.len() < expected {
+        return Err(ImageDataError::UnexpectedLength {
+            expected,
+            actual: filtered.len(),
+        });
+    }
+    let mut data = vec![0; row_bytes * rows];
+    for row in 0..rows {
+        let line = &filtered[row * (row_bytes + 1)..(row + 1) * (row_bytes + 1)];
+        let filter_type = line[0];
+        let (previous, current) = data.split_at_mut(row * row_bytes);
+        let previous = match row {
+            0 => None,
+            _ => Some(&previous[(row - 1) * row_bytes..]),
+        };
+        let current = &mut current[..row_bytes];
+        current.copy_from_slice(&line[1..]);
+        for i in 0..row_bytes {
+            let left = if i >= unit { current[i - unit] } else { 0 };
+            let up = previous.map_or(0, |p| p[i]);
+            let up_left = match (previous, i >= unit) {
+                (Some(p), true) => p[i - unit],
+                _ => 0,
+            };
+            let predictor = match filter_type {
+                0 => 0,
+                1 => left,
+                2 => up,
+                3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+                4 => paeth(left, up, up_left),
+                _ => return Err(ImageDataError::InvalidFilterType(filter_type)),
+            };
+            current[i] = current[i].wrapping_add(predictor);
+        }
+    }
+    Ok(data)
+}
+
+/// Applies filter to one unfiltered scanline, `previous` is `None` for the first row
+pub(crate) fn filter_row<'a>(
+    filter_type: u8,
+    current: &'a [u8],
+    previous: Option<&'a [u8]>,
+    unit: usize,
+) -> impl Iterator<Item = u8> + 'a {
+    (0..current.len()).map(move |i| {
+        let left = if i >= unit { current[i - unit] } else { 0 };
+        let up = previous.map_or(0, |p| p[i]);
+        let up_left = match (previous, i >= unit) {
+            (Some(p), true) => p[i - unit],
+            _ => 0,
+        };
+        let predictor = match filter_type {
+            1 => left,
+            2 => up,
+            3 => ((u16::from(left) + u16::from(up)) / 2) as u8,
+            4 => paeth(left, up, up_left),
+            _ => 0,
+        };
+        current[i].wrapping_sub(predictor)
+    })
+}
+
+fn paeth(left: u8, up: u8, up_left: u8) -> u8 {
+    let estimate = i16::from(left) + i16::from(up) - i16::from(up_left);
+    let distance_left = (estimate - i16::from(left)).abs();
+    let distance_up = (estimate - i16::from(up)).abs();
+    let distance_up_left = (estimate - i16::from(up_left)).abs();
+    if distance_left <= distance_up && distance_left <= distance_up_left {
+        left
+    } else if distance_up <= distance_up_left {
+        up
+    } else {
+        up_left
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::chunk_type::ChunkType;
+    use std::str::FromStr;
+
+    fn decode_file(path: &str) -> PixelBuffer {
+        decode(&Png::from_file(path).unwrap()).unwrap()
+    }
+
+    fn pixel(image: &PixelBuffer, x: u32, y: u32) -> Vec<u16> {
+        let channels = image.format().channels();
+        (0..channels)
+            .map(|channel| image.sample(x, y, channel).unwrap())
+            .collect()
+    }
+
+    /// Builds PNG from pixels with filter type None, interlaced when `ihdr` says so
+    fn encode_unfiltered(image: &PixelBuffer, ihdr: Ihdr) -> Png {
+        let format = image.format();
+        let passes: Vec<_> = match ihdr.is_interlaced() {
+            true => ADAM7.to_vec(),
+            false => vec![(0, 0, 1, 1)],
+        };
+        let mut filtered = Vec::new();
+        for pass in passes.iter() {
+            let (width, _) = pass_size(image.width(), image.height(), pass);
+            let row_bytes = format.row_bytes(width);
+            for row in pass_data(image, pass).chunks(row_bytes.max(1)) {
+                filtered.push(0);
+                filtered.extend(row);
+            }
+        }
+        let idat = ChunkType::from_str("IDAT").unwrap();
+        let iend = ChunkType::from_str("IEND").unwrap();
+        Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            Chunk::new(idat, &deflate(&filtered, 6)),
+            Chunk::new(iend, &[]),
+        ])
+    }
+
+    #[test]
+    fn test_decode_rgba_image() {
+        let image = decode_file("../images/dice.png");
+        assert_eq!((image.width(), image.height()), (361, 288));
+        assert_eq!(image.format().color_type(), ColorType::Rgba);
+        assert_eq!(image.format().bytes_per_pixel(), 4);
+        assert_eq!(image.data().len(), 361 * 288 * 4);
+        assert_eq!(pixel(&image, 0, 0), [116, 112, 109, 255]);
+        assert_eq!(pixel(&image, 180, 144), [213, 206, 222, 255]);
+        assert_eq!(pixel(&image, 360, 287), [171, 205, 203, 255]);
+        assert_eq!(image.sample(361, 0, 0), None);
+        assert_eq!(image.sample(0, 0, 4), None);
+    }
+
+    #[test]
+    fn test_decode_grayscale_alpha_image() {
+        let image = decode_file("../images/icon.png");
+        assert_eq!(image.format().color_type(), ColorType::GrayscaleAlpha);
+        assert_eq!(image.row_bytes(), 128 * 2);
+        assert_eq!(pixel(&image, 0, 0), [0, 0]);
+        assert_eq!(pixel(&image, 64, 64), [85, 29]);
+        assert_eq!(pixel(&image, 127, 127), [0, 0]);
+    }
+
+    #[test]
+    fn test_decode_ignores_ancillary_chunks() {
+        assert_eq!(
+            decode_file("../images/secret.png"),
+            decode_file("../images/dice.png")
+        );
+    }
+
+    #[test]
+    fn test_decode_interlaced() {
+        let image = decode_file("../images/dice.png");
+        let ihdr = Ihdr::new(361, 288, 8, ColorType::Rgba, true).unwrap();
+        assert_eq!(decode(&encode_unfiltered(&image, ihdr)).unwrap(), image);
+    }
+
+    #[test]
+    fn test_decode_interlaced_sub_byte_pixels() {
+        for &bit_depth in [1, 2, 4].iter() {
+            let format = PixelFormat {
+                color_type: ColorType::Grayscale,
+                bit_depth,
+            };
+            let data = (0..format.row_bytes(13) * 11)
+                .map(|i| (i * 73 % 256) as u8)
+                .collect();
+            let image = PixelBuffer::new(13, 11, format, data).unwrap();
+            // padding bits at the end of rows are not part of any pixel
+            let encoded = encode_unfiltered(
+                &image,
+                Ihdr::new(13, 11, bit_depth, ColorType::Grayscale, true).unwrap(),
+            );
+            let decoded = decode(&encoded).unwrap();
+            for y in 0..11 {
+                for x in 0..13 {
+                    assert_eq!(decoded.sample(x, y, 0), image.sample(x, y, 0));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_tiny_interlaced_image_skips_empty_passes() {
+        let format = PixelFormat {
+            color_type: ColorType::Rgb,
+            bit_depth: 16,
+        };
+        let image = PixelBuffer::new(1, 1, format, vec![1, 2, 3, 4, 5, 6]).unwrap();
+        let ihdr = Ihdr::new(1, 1, 16, ColorType::Rgb, true).unwrap();
+        let decoded = decode(&encode_unfiltered(&image, ihdr)).unwrap();
+        assert_eq!(decoded, image);
+        assert_eq!(pixel(&decoded, 0, 0), [0x102, 0x304, 0x506]);
+    }
+
+    #[test]
+    fn test_pixel_buffer_checks_length() {
+        let format = PixelFormat {
+            color_type: ColorType::Grayscale,
+            bit_depth: 1,
+        };
+        assert!(PixelBuffer::new(9, 2, format, vec![0; 4]).is_ok());
+        assert!(PixelBuffer::new(9, 2, format, vec![0; 3]).is_err());
+    }
+
+    #[test]
+    fn test_non_consecutive_idat() {
+        let mut png = encode_unfiltered(
+            &decode_file("../images/icon.png"),
+            Ihdr::new(128, 128, 8, ColorType::GrayscaleAlpha, false).unwrap(),
+        );
+        let idat = png.chunks()[1].data().to_vec();
+        let (first, second) = idat.split_at(10);
+        png.retain_chunks(|chunk| chunk.chunk_type().bytes() != *b"IDAT");
+        let chunk = |t: &str, data: &[u8]| Chunk::new(ChunkType::from_str(t).unwrap(), data);
+        png.insert_chunk_at(1, chunk("IDAT", first)).unwrap();
+        png.insert_chunk_at(2, chunk("tEXt", b"a\0b")).unwrap();
+        png.insert_chunk_at(3, chunk("IDAT", second)).unwrap();
+        assert!(matches!(
+            decode(&png),
+            Err(ImageDataError::NonConsecutiveImageData)
+        ));
+    }
+
+    /// PNG with a given header and a small IDAT
+    fn png_with_header(ihdr: Ihdr) -> Png {
+        let idat = ChunkType::from_str("IDAT").unwrap();
+        let iend = ChunkType::from_str("IEND").unwrap();
+        Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            Chunk::new(idat, &deflate(&[0; 64], 6)),
+            Chunk::new(iend, &[]),
+        ])
+    }
+
+    #[test]
+    fn test_oversized_header() {
+        let max = 0x7fff_ffff;
+        for interlaced in [false, true] {
+            let ihdr = Ihdr::new(max, max, 16, ColorType::Rgba, interlaced).unwrap();
+            assert!(matches!(
+                decode(&png_with_header(ihdr)),
+                Err(ImageDataError::TooLarge { .. })
+            ));
+        }
+        assert!(unfilter(&[0], usize::MAX, 2, 1).is_err());
+    }
+
+    #[test]
+    fn test_header_larger_than_data() {
+        let ihdr = Ihdr::new(10_000, 10_000, 8, ColorType::Grayscale, false).unwrap();
+        assert!(matches!(
+            decode(&png_with_header(ihdr)),
+            Err(ImageDataError::UnexpectedLength {
+                expected: 100_010_000,
+                actual: 64
+            })
+        ));
+    }
+
+    #[test]
+    fn test_inflate_is_capped() {
+        let png = png_with_header(Ihdr::new(1, 1, 8, ColorType::Grayscale, false).unwrap());
+        assert_eq!(inflate_up_to(&png, 10).unwrap().len(), 10);
+        assert_eq!(inflate(&png).unwrap().len(), 64);
+    }
+
+    #[test]
+    fn test_filter_round_trip() {
+        let row_bytes = 6;
+        let data: Vec<u8> = (0..24).map(|i| (i * 37 % 256) as u8).collect();
+        let mut filtered = Vec::new();
+        for (row, filter_type) in (1..=4).enumerate() {
+            let current = &data[row * row_bytes..(row + 1) * row_bytes];
+            let previous = (row > 0).then(|| &data[(row - 1) * row_bytes..row * row_bytes]);
+            filtered.push(filter_type);
+            filtered.extend(filter_row(filter_type, current, previous, 3));
+        }
+        assert_eq!(filtered.len(), 28);
+        assert_ne!(filtered[1..7], data[..6]);
+        assert_eq!(unfilter(&filtered, row_bytes, 4, 3).unwrap(), data);
+    }
+
+    #[test]
+    fn test_unfilter_invalid_input() {
+        assert!(unfilter(&[5, 1, 2], 2, 1, 1).is_err());
+        assert!(unfilter(&[0, 1], 2, 1, 1).is_err());
+    }
+
+    #[test]
+    fn test_paeth() {
+        assert_eq!(paeth(10, 20, 10), 20);
+        assert_eq!(paeth(20, 10, 10), 20);
+        assert_eq!(paeth(10, 10, 20), 10);
+    }
+}
@@ -1,33 +1,132 @@
 use std::convert::TryFrom;
 use std::fs;
+use std::io::{self, BufReader, Read, Write};
 use std::path::Path;
 
-use byteorder::ByteOrder;
-
-use crate::chunk::Chunk;
+use crate::chunk::{Chunk, ChunkRef};
 use crate::chunk_type::ChunkType;
 use crate::error;
+use crate::ihdr::Ihdr;
+use crate::message::{self, Message};
 
 pub struct Png {
     chunks: Vec<Chunk>,
+    trailing_data: Vec<u8>,
+}
+
+/// Place where a new chunk is inserted into a `Png`
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChunkPosition {
+    /// Before IEND, at the end for files without IEND like `append_chunk`
+    BeforeIend,
+    AfterIhdr,
+    BeforeFirstIdat,
+    Index(usize),
+}
+
+impl std::str::FromStr for ChunkPosition {
+    type Err = error::ChunkPositionParseError;
+
+    fn from_str(value: &str) -> Result<Self, Self::Err> {
+        match value {
+            "before-iend" => Ok(Self::BeforeIend),
+            "after-ihdr" => Ok(Self::AfterIhdr),
+            "before-idat" => Ok(Self::BeforeFirstIdat),
+            _ => value
+                .parse()
+                .map(Self::Index)
+                .map_err(|_| error::ChunkPositionParseError::Unknown(value.into())),
+        }
+    }
 }
 
 impl Png {
     pub const STANDARD_HEADER: [u8; 8] = [137, 80, 78, 71, 13, 10, 26, 10];
 
     pub fn from_file(path: impl AsRef<Path>) -> Result<Self, error::PngFromFileError> {
-        let bytes = fs::read(path)?;
-        Ok(Self::try_from(&bytes[..])?)
+        let file = fs::File::open(path)?;
+        Self::from_reader(BufReader::new(file))
+    }
+    pub fn from_reader(reader: impl Read) -> Result<Self, error::PngFromFileError> {
+        let mut reader = PngReader::new(reader)?;
+        let chunks = reader.by_ref().collect::<Result<Vec<Chunk>, _>>()?;
+        let mut trailing_data = reader.trailing_data().to_vec();
+        reader.into_inner().read_to_end(&mut trailing_data)?;
+        Ok(Self {
+            chunks,
+            trailing_data,
+        })
     }
     pub fn from_chunks(chunks: Vec<Chunk>) -> Self {
-        Self { chunks }
+        Self {
+            chunks,
+            trailing_data: Vec::new(),
+        }
     }
     pub fn chunks(&self) -> &[Chunk] {
         &self.chunks[..]
     }
+    pub(crate) fn chunks_mut(&mut self) -> &mut Vec<Chunk> {
+        &mut self.chunks
+    }
+    /// Bytes after IEND that do not form chunks, written back after the last chunk
+    pub fn trailing_data(&self) -> &[u8] {
+        &self.trailing_data
+    }
+    pub fn remove_trailing_data(&mut self) -> Vec<u8> {
+        std::mem::take(&mut self.trailing_data)
+    }
     pub fn append_chunk(&mut self, chunk: Chunk) {
         self.chunks.push(chunk)
     }
+    pub fn insert_chunk(
+        &mut self,
+        chunk: Chunk,
+        position: ChunkPosition,
+    ) -> Result<(), error::InsertChunkError> {
+        self.insert_chunks(vec![chunk], position)
+    }
+    /// Inserts chunks next to each other keeping their order
+    pub fn insert_chunks(
+        &mut self,
+        chunks: Vec<Chunk>,
+        position: ChunkPosition,
+    ) -> Result<(), error::InsertChunkError> {
+        let index = match position {
+            ChunkPosition::BeforeIend => self.position_of(b"IEND").unwrap_or(self.chunks.len()),
+            ChunkPosition::AfterIhdr => self.position_of(b"IHDR")? + 1,
+            ChunkPosition::BeforeFirstIdat => self.position_of(b"IDAT")?,
+            ChunkPosition::Index(index) => index,
+        };
+        let len = self.chunks.len();
+        if index > len {
+            return Err(error::InsertChunkError::IndexOutOfBounds { index, len });
+        }
+        self.chunks.splice(index..index, chunks);
+        Ok(())
+    }
+    pub fn insert_chunk_before_iend(
+        &mut self,
+        chunk: Chunk,
+    ) -> Result<(), error::InsertChunkError> {
+        self.insert_chunk(chunk, ChunkPosition::BeforeIend)
+    }
+    pub fn insert_chunk_after_ihdr(&mut self, chunk: Chunk) -> Result<(), error::InsertChunkError> {
+        self.insert_chunk(chunk, ChunkPosition::AfterIhdr)
+    }
+    pub fn insert_chunk_before_first_idat(
+        &mut self,
+        chunk: Chunk,
+    ) -> Result<(), error::InsertChunkError> {
+        self.insert_chunk(chunk, ChunkPosition::BeforeFirstIdat)
+    }
+    pub fn insert_chunk_at(
+        &mut self,
+        index: usize,
+        chunk: Chunk,
+    ) -> Result<(), error::InsertChunkError> {
+        self.insert_chunk(chunk, ChunkPosition::Index(index))
+    }
     pub fn remove_chunk(
         &mut self,
         chunk_type: &ChunkType,
@@ -36,22 +135,169 @@ impl Png {
             .chunks
             .iter()
             .enumerate()
-            .find_map(|(i, chunk)| (chunk.chunk_type() == chunk_type).then(|| i))
+            .find_map(|(i, chunk)| (chunk.chunk_type() == chunk_type).then_some(i))
             .ok_or_else(|| error::RemoveChunkError::NotFound(chunk_type.to_string()))?;
         Ok(self.chunks.remove(index))
     }
+    /// Keeps only chunks for which `keep` returns true, returns removed chunks in order
+    pub fn retain_chunks(&mut self, mut keep: impl FnMut(&Chunk) -> bool) -> Vec<Chunk> {
+        let (kept, removed) = self.chunks.drain(..).partition(|chunk| keep(chunk));
+        self.chunks = kept;
+        removed
+    }
     pub fn chunk_by_type(&self, chunk_type: &ChunkType) -> Option<&Chunk> {
         self.chunks
             .iter()
             .find(|chunk| chunk.chunk_type() == chunk_type)
     }
+    /// All chunks of given type in file order
+    pub fn chunks_by_type<'a>(
+        &'a self,
+        chunk_type: &'a ChunkType,
+    ) -> impl Iterator<Item = &'a Chunk> + 'a {
+        self.chunks
+            .iter()
+            .filter(move |chunk| chunk.chunk_type() == chunk_type)
+    }
+    /// Messages stored in chunks of given type, see `message::find_messages`
+    pub fn messages(&self, chunk_type: &ChunkType) -> Vec<Message> {
+        let chunks = self.chunks.iter().enumerate();
+        message::find_messages(
+            chunks
+                .filter(|(_, chunk)| chunk.chunk_type() == chunk_type)
+                .map(|(index, chunk)| (index, chunk.data())),
+        )
+    }
+    /// Removes chunks at given indexes, returns removed chunks in order
+    pub fn remove_chunks_at(&mut self, indexes: &[usize]) -> Vec<Chunk> {
+        let mut index = 0;
+        self.retain_chunks(|_| {
+            index += 1;
+            !indexes.contains(&(index - 1))
+        })
+    }
     pub fn as_bytes(&self) -> Vec<u8> {
         Self::STANDARD_HEADER
             .iter()
             .copied()
             .chain(self.chunks.iter().flat_map(|chunk| chunk.as_bytes()))
+            .chain(self.trailing_data.iter().copied())
             .collect()
     }
+    pub fn write_to<W: Write>(&self, writer: W) -> io::Result<W> {
+        let mut writer = PngWriter::new(writer)?;
+        for chunk in self.chunks.iter() {
+            writer.write_chunk(chunk)?;
+        }
+        writer.write_trailing_data(&self.trailing_data)?;
+        writer.finish()
+    }
+    /// Saves the file so that `path` holds either the old or the new contents even on failure
+    pub fn write_to_path(&self, path: impl AsRef<Path>) -> io::Result<()> {
+        self.write_to_path_with_options(path, &WriteOptions::default())
+    }
+    /// Saves the file like `write_to_path` using `options`
+    ///
+    /// Data goes to a temporary file in the same directory which is synced and renamed over
+    /// `path`. Permissions and timestamps of the replaced file are kept where possible.
+    pub fn write_to_path_with_options(
+        &self,
+        path: impl AsRef<Path>,
+        options: &WriteOptions,
+    ) -> io::Result<()> {
+        let mut path = path.as_ref().to_path_buf();
+        let existing = fs::symlink_metadata(&path).ok();
+        // Replace the file a link points to rather than the link itself
+        if existing
+            .as_ref()
+            .is_some_and(|m| m.file_type().is_symlink())
+        {
+            path = fs::canonicalize(&path)?;
+        }
+        let existing = existing.and_then(|_| fs::metadata(&path).ok());
+        let directory = match path.parent() {
+            Some(parent) if !parent.as_os_str().is_empty() => parent,
+            _ => Path::new("."),
+        };
+        let file_name = path.file_name().unwrap_or_default().to_string_lossy();
+        let prefix = format!(".{}.", file_name);
+        let mut builder = tempfile::Builder::new();
+        builder.prefix(&prefix).suffix(".tmp");
+        // Temporary files are private, new files get the usual 0o666 masked by umask
+        #[cfg(unix)]
+        if existing.is_none() {
+            use std::os::unix::fs::PermissionsExt;
+            builder.permissions(fs::Permissions::from_mode(0o666));
+        }
+        let mut file = builder.tempfile_in(directory)?;
+        self.write_to(io::BufWriter::new(file.as_file_mut()))?;
+        if let Some(metadata) = existing.as_ref() {
+            // Failures leave the temporary file private and with current time
+            file.as_file().set_permissions(metadata.permissions()).ok();
+            let times = fs::FileTimes::new();
+            let times = match metadata.accessed() {
+                Ok(accessed) => times.set_accessed(accessed),
+                Err(_) => times,
+            };
+            let times = match metadata.modified() {
+                Ok(modified) => times.set_modified(modified),
+                Err(_) => times,
+            };
+            file.as_file().set_times(times).ok();
+        }
+        file.as_file().sync_all()?;
+        if let (Some(suffix), Some(_)) = (options.backup_suffix.as_deref(), existing.as_ref()) {
+            let mut backup = path.clone().into_os_string();
+            backup.push(suffix);
+            match fs::remove_file(&backup) {
+                Err(e) if e.kind() != io::ErrorKind::NotFound => return Err(e),
+                _ => {}
+            }
+            // Hard link keeps the original in place until the rename below
+            if fs::hard_link(&path, &backup).is_err() {
+                fs::copy(&path, &backup)?;
+            }
+        }
+        file.persist(&path).map_err(|e| e.error)?;
+        // Makes the rename durable, not supported on every platform
+        if let Ok(directory) = fs::File::open(directory) {
+            directory.sync_all().ok();
+        }
+        Ok(())
+    }
+
+    pub fn header(&self) -> Result<Ihdr, error::IhdrParseError> {
+        let chunk = self
+            .chunks
+            .iter()
+            .find(|chunk| chunk.chunk_type().bytes() == *b"IHDR")
+            .ok_or(error::IhdrParseError::NotFound)?;
+        Ihdr::try_from(chunk)
+    }
+
+    /// Replaces all IDAT chunks with `chunks` placed where the first IDAT was, or before IEND
+    /// when there was no image data
+    pub fn replace_image_data(
+        &mut self,
+        chunks: Vec<Chunk>,
+    ) -> Result<(), error::InsertChunkError> {
+        let index = match self.position_of(b"IDAT") {
+            Ok(index) => index,
+            Err(_) => self.position_of(b"IEND")?,
+        };
+        self.retain_chunks(|chunk| chunk.chunk_type().bytes() != *b"IDAT");
+        self.chunks.splice(index..index, chunks);
+        Ok(())
+    }
+    fn position_of(&self, chunk_type: &[u8; 4]) -> Result<usize, error::InsertChunkError> {
+        self.chunks
+            .iter()
+            .position(|chunk| &chunk.chunk_type().bytes() == chunk_type)
+            .ok_or_else(|| {
+                let chunk_type = String::from_utf8_lossy(chunk_type).into_owned();
+                error::InsertChunkError::NotFound(chunk_type)
+            })
+    }
 }
 
 impl std::fmt::Display for Png {
@@ -64,37 +310,510 @@ impl std::convert::TryFrom<&[u8]> for Png {
     type Error = error::PngFromBytesError;
 
     fn try_from(bytes: &[u8]) -> Result<Png, Self::Error> {
-        let mut bytes_iter = bytes.iter();
-        let header: Vec<u8> = bytes_iter
-            .by_ref()
-            .take(Self::STANDARD_HEADER.len())
-            .copied()
-            .collect();
-        if header != Self::STANDARD_HEADER {
+        Ok(PngRef::try_from(bytes)?.to_png())
+    }
+}
+
+/// How parsing treats damaged input, the default is strict and fails on the first problem
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub struct ParseOptions {
+    ignore_crc: bool,
+    skip_bad_chunks: bool,
+    stop_at_truncation: bool,
+}
+
+impl ParseOptions {
+    pub fn strict() -> Self {
+        Self::default()
+    }
+    /// Enables every recovery option
+    pub fn lenient() -> Self {
+        Self {
+            ignore_crc: true,
+            skip_bad_chunks: true,
+            stop_at_truncation: true,
+        }
+    }
+    /// Keeps chunks whose crc does not match their data
+    pub fn ignore_crc(mut self, ignore: bool) -> Self {
+        self.ignore_crc = ignore;
+        self
+    }
+    /// Skips chunks that can not be parsed and continues with the next chunk that can
+    pub fn skip_bad_chunks(mut self, skip: bool) -> Self {
+        self.skip_bad_chunks = skip;
+        self
+    }
+    /// Drops incomplete chunk at the end of input instead of failing
+    pub fn stop_at_truncation(mut self, stop: bool) -> Self {
+        self.stop_at_truncation = stop;
+        self
+    }
+    pub fn is_strict(&self) -> bool {
+        *self == Self::strict()
+    }
+}
+
+/// How `Png::write_to_path_with_options` replaces an existing file
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct WriteOptions {
+    backup_suffix: Option<String>,
+}
+
+impl WriteOptions {
+    /// Keeps the replaced file next to the new one with `suffix` added to its name
+    pub fn backup(mut self, suffix: impl Into<String>) -> Self {
+        self.backup_suffix = Some(suffix.into());
+        self
+    }
+    pub fn backup_suffix(&self) -> Option<&str> {
+        self.backup_suffix.as_deref()
+    }
+}
+
+/// Problem found and worked around while parsing with lenient `ParseOptions`
+#[derive(Debug)]
+pub enum ParseWarning {
+    /// Chunk was kept even though its crc does not match
+    CrcMismatch { offset: u64, chunk_type: String },
+    /// Bytes that did not form a valid chunk were skipped
+    SkippedBytes {
+        offset: u64,
+        length: usize,
+        reason: error::ChunkParseError,
+    },
+    /// Input ended inside a chunk, remaining bytes were dropped
+    Truncated { offset: u64, length: usize },
+}
+
+impl ParseWarning {
+    /// Offset in input where the problem starts
+    pub fn offset(&self) -> u64 {
+        match self {
+            Self::CrcMismatch { offset, .. }
+            | Self::SkippedBytes { offset, .. }
+            | Self::Truncated { offset, .. } => *offset,
+        }
+    }
+}
+
+impl std::fmt::Display for ParseWarning {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CrcMismatch { offset, chunk_type } => write!(
+                f,
+                "Chunk {:?} at {:#x} has wrong crc, kept anyway",
+                chunk_type, offset
+            ),
+            Self::SkippedBytes {
+                offset,
+                length,
+                reason,
+            } => write!(
+                f,
+                "Skipped {} bytes at {:#x} that are not a valid chunk: {}",
+                length, offset, reason
+            ),
+            Self::Truncated { offset, length } => write!(
+                f,
+                "Input ends inside a chunk, dropped {} bytes at {:#x}",
+                length, offset
+            ),
+        }
+    }
+}
+
+impl Png {
+    /// Parses possibly damaged input, returns what could be read together with the problems
+    /// that were worked around
+    pub fn parse(
+        bytes: &[u8],
+        options: ParseOptions,
+    ) -> Result<(Self, Vec<ParseWarning>), error::PngFromBytesError> {
+        let (png, warnings) = PngRef::parse(bytes, options)?;
+        Ok((png.to_png(), warnings))
+    }
+}
+
+/// Read-only view of a PNG whose chunks borrow from the input slice, e.g. a memory-mapped file
+pub struct PngRef<'a> {
+    chunks: Vec<ChunkRef<'a>>,
+    trailing_data: &'a [u8],
+    trailing_offset: u64,
+}
+
+impl<'a> PngRef<'a> {
+    pub fn chunks(&self) -> &[ChunkRef<'a>] {
+        &self.chunks[..]
+    }
+    /// Bytes after IEND that do not form chunks
+    pub fn trailing_data(&self) -> &'a [u8] {
+        self.trailing_data
+    }
+    /// Offset of the first trailing byte, which is the input length when there is none
+    pub fn trailing_offset(&self) -> u64 {
+        self.trailing_offset
+    }
+    pub fn chunk_by_type(&self, chunk_type: &ChunkType) -> Option<&ChunkRef<'a>> {
+        let bytes = chunk_type.bytes();
+        self.chunks
+            .iter()
+            .find(|chunk| chunk.chunk_type_bytes() == bytes)
+    }
+    /// All chunks of given type in file order
+    pub fn chunks_by_type<'b>(
+        &'b self,
+        chunk_type: &ChunkType,
+    ) -> impl Iterator<Item = &'b ChunkRef<'a>> + 'b {
+        let bytes = chunk_type.bytes();
+        self.chunks
+            .iter()
+            .filter(move |chunk| chunk.chunk_type_bytes() == bytes)
+    }
+    /// Messages stored in chunks of given type, see `message::find_messages`
+    pub fn messages(&self, chunk_type: &ChunkType) -> Vec<Message> {
+        let bytes = chunk_type.bytes();
+        let chunks = self.chunks.iter().enumerate();
+        message::find_messages(
+            chunks
+                .filter(|(_, chunk)| chunk.chunk_type_bytes() == bytes)
+                .map(|(index, chunk)| (index, chunk.data())),
+        )
+    }
+    pub fn header(&self) -> Result<Ihdr, error::IhdrParseError> {
+        let chunk = self
+            .chunks
+            .iter()
+            .find(|chunk| chunk.chunk_type_bytes() == *b"IHDR")
+            .ok_or(error::IhdrParseError::NotFound)?;
+        Ihdr::from_data(chunk.data())
+    }
+    pub fn to_png(&self) -> Png {
+        Png {
+            chunks: self.chunks.iter().map(ChunkRef::to_chunk).collect(),
+            trailing_data: self.trailing_data.to_vec(),
+        }
+    }
+}
+
+impl<'a> std::convert::TryFrom<&'a [u8]> for PngRef<'a> {
+    type Error = error::PngFromBytesError;
+
+    fn try_from(bytes: &'a [u8]) -> Result<PngRef<'a>, Self::Error> {
+        let (png, _) = Self::parse(bytes, ParseOptions::strict())?;
+        Ok(png)
+    }
+}
+
+impl<'a> PngRef<'a> {
+    /// Parses possibly damaged input, see `Png::parse`
+    pub fn parse(
+        bytes: &'a [u8],
+        options: ParseOptions,
+    ) -> Result<(Self, Vec<ParseWarning>), error::PngFromBytesError> {
+        let header_length = Png::STANDARD_HEADER.len();
+        if bytes.len() < header_length || bytes[..header_length] != Png::STANDARD_HEADER {
             return Err(error::PngFromBytesError::InvalidHeader);
         }
-        let mut chunks: Vec<Chunk> = vec![];
-        loop {
-            let length_vec: Vec<u8> = bytes_iter.by_ref().take(4).copied().collect();
-            if length_vec.is_empty() {
-                break;
+        let mut rest = &bytes[header_length..];
+        let mut chunks = vec![];
+        let mut warnings = vec![];
+        while !rest.is_empty() {
+            let offset = (bytes.len() - rest.len()) as u64;
+            let reason = match parse_chunk(rest, options) {
+                // a wrong crc may come from a damaged length, then the chunk is not kept
+                Ok((chunk, remaining))
+                    if !chunk.has_valid_crc() && !is_followed_by_chunk(&chunk, remaining) =>
+                {
+                    error::ChunkParseError::CrcMismatch
+                }
+                Ok((chunk, remaining)) => {
+                    if !chunk.has_valid_crc() {
+                        let chunk_type = chunk.chunk_type().to_string();
+                        warnings.push(ParseWarning::CrcMismatch { offset, chunk_type });
+                    }
+                    chunks.push(chunk.with_source_offset(offset));
+                    rest = remaining;
+                    if chunk.chunk_type_bytes() == *b"IEND" {
+                        let offset = (bytes.len() - rest.len()) as u64;
+                        let (after_iend, trailing) = split_after_iend(rest, offset, options);
+                        for chunk in after_iend.iter().filter(|chunk| !chunk.has_valid_crc()) {
+                            warnings.push(ParseWarning::CrcMismatch {
+                                offset: chunk.source_offset().unwrap_or_default(),
+                                chunk_type: chunk.chunk_type().to_string(),
+                            });
+                        }
+                        chunks.extend(after_iend);
+                        rest = trailing;
+                        break;
+                    }
+                    continue;
+                }
+                Err(reason) => reason,
+            };
+            let skipped = match options.skip_bad_chunks {
+                true => next_chunk_start(rest),
+                false => None,
+            };
+            match skipped {
+                Some(length) => {
+                    warnings.push(ParseWarning::SkippedBytes {
+                        offset,
+                        length,
+                        reason,
+                    });
+                    rest = &rest[length..];
+                }
+                None if options.stop_at_truncation && is_truncated(rest) => {
+                    let length = rest.len();
+                    warnings.push(ParseWarning::Truncated { offset, length });
+                    rest = &rest[length..];
+                }
+                None if rest.len() < 4 => return Err(error::PngFromBytesError::LengthParse),
+                None => return Err(reason.into()),
             }
-            if length_vec.len() != 4 {
-                return Err(error::PngFromBytesError::LengthParse);
+        }
+        let png = PngRef {
+            chunks,
+            trailing_data: rest,
+            trailing_offset: (bytes.len() - rest.len()) as u64,
+        };
+        Ok((png, warnings))
+    }
+}
+
+fn parse_chunk(
+    bytes: &[u8],
+    options: ParseOptions,
+) -> Result<(ChunkRef<'_>, &[u8]), error::ChunkParseError> {
+    match options.ignore_crc {
+        true => ChunkRef::parse_prefix_ignoring_crc(bytes),
+        false => ChunkRef::parse_prefix(bytes),
+    }
+}
+
+fn is_followed_by_chunk(chunk: &ChunkRef, rest: &[u8]) -> bool {
+    // input may end before the header of the next chunk
+    chunk.chunk_type_bytes() == *b"IEND" || rest.len() < 8 || starts_with_chunk_header(rest)
+}
+
+/// Whether `bytes` start with a sane length and a valid chunk type, the rest of the chunk may be
+/// damaged or missing
+fn starts_with_chunk_header(bytes: &[u8]) -> bool {
+    if bytes.len() < 8 {
+        return false;
+    }
+    let length = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    let type_bytes = [bytes[4], bytes[5], bytes[6], bytes[7]];
+    length as usize <= Chunk::MAX_LENGTH && ChunkType::try_from(type_bytes).is_ok()
+}
+
+/// Whether `bytes` hold less than the chunk declared by its length field
+fn is_truncated(bytes: &[u8]) -> bool {
+    if bytes.len() < 12 {
+        return true;
+    }
+    let length = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+    12 + length as usize > bytes.len()
+}
+
+/// Finds where parsing can continue after a bad chunk at the start of `bytes`. Right after the
+/// bad chunk when another chunk header follows it, otherwise at the next position where a chunk
+/// with valid crc starts.
+fn next_chunk_start(bytes: &[u8]) -> Option<usize> {
+    if !is_truncated(bytes) {
+        let length = 12 + u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]) as usize;
+        let next = &bytes[length..];
+        if next.is_empty() || starts_with_chunk_header(next) {
+            return Some(length);
+        }
+    }
+    (1..bytes.len()).find(|&start| {
+        let candidate = &bytes[start..];
+        starts_with_chunk_header(candidate)
+            && !is_truncated(candidate)
+            && ChunkRef::parse_prefix(candidate).is_ok()
+    })
+}
+
+/// Parses chunks that follow IEND at `offset`, everything from the first byte that does not
+/// start a valid chunk is returned as trailing data
+fn split_after_iend(
+    mut bytes: &[u8],
+    mut offset: u64,
+    options: ParseOptions,
+) -> (Vec<ChunkRef<'_>>, &[u8]) {
+    let mut chunks = Vec::new();
+    while let Ok((chunk, rest)) = parse_chunk(bytes, options) {
+        chunks.push(chunk.with_source_offset(offset));
+        offset += chunk.as_bytes().len() as u64;
+        bytes = rest;
+    }
+    (chunks, bytes)
+}
+
+/// Reads PNG signature and then yields chunks one at a time without loading the whole stream
+pub struct PngReader<R: Read> {
+    reader: R,
+    finished: bool,
+    offset: u64,
+    after_iend: bool,
+    /// Bytes of the first thing after IEND that is not a valid chunk
+    trailing_data: Vec<u8>,
+}
+
+impl<R: Read> PngReader<R> {
+    pub fn new(mut reader: R) -> Result<Self, error::PngFromFileError> {
+        let mut header = [0; 8];
+        let header_length = read_up_to(&mut reader, &mut header)?;
+        if header_length != header.len() || header != Png::STANDARD_HEADER {
+            return Err(error::PngFromBytesError::InvalidHeader.into());
+        }
+        Ok(Self {
+            reader,
+            finished: false,
+            offset: header.len() as u64,
+            after_iend: false,
+            trailing_data: Vec::new(),
+        })
+    }
+    /// Returns next chunk or `None` when stream ends exactly on a chunk boundary or at bytes
+    /// after IEND that do not form a chunk
+    pub fn read_chunk(&mut self) -> Result<Option<Chunk>, error::PngFromFileError> {
+        let chunk = match self.after_iend {
+            true => self.read_chunk_after_iend()?,
+            false => self.read_next_chunk()?,
+        };
+        let chunk = match chunk {
+            Some(chunk) => chunk.with_source_offset(self.offset),
+            None => return Ok(None),
+        };
+        self.offset += 12 + u64::from(chunk.length());
+        if chunk.chunk_type().bytes() == *b"IEND" {
+            self.after_iend = true;
+        }
+        Ok(Some(chunk))
+    }
+    /// Start of bytes after IEND that do not form chunks, available once all chunks are read.
+    /// Only the bytes read while looking for a chunk are kept, the rest stays in the inner reader.
+    pub fn trailing_data(&self) -> &[u8] {
+        &self.trailing_data
+    }
+    /// Reads a chunk after IEND, anything that is not a valid chunk ends the chunks and is kept
+    /// as the start of trailing data. Like any chunk, a candidate is read up to its declared
+    /// length before its crc can be checked.
+    fn read_chunk_after_iend(&mut self) -> io::Result<Option<Chunk>> {
+        if !self.trailing_data.is_empty() {
+            return Ok(None);
+        }
+        let mut bytes = vec![0; 8];
+        let header_length = read_up_to(&mut self.reader, &mut bytes)?;
+        bytes.truncate(header_length);
+        if !starts_with_chunk_header(&bytes) {
+            self.trailing_data = bytes;
+            return Ok(None);
+        }
+        let length = u32::from_be_bytes([bytes[0], bytes[1], bytes[2], bytes[3]]);
+        self.reader
+            .by_ref()
+            .take(u64::from(length) + 4)
+            .read_to_end(&mut bytes)?;
+        match ChunkRef::try_from(&bytes[..]) {
+            Ok(chunk) => Ok(Some(chunk.to_chunk())),
+            Err(_) => {
+                self.trailing_data = bytes;
+                Ok(None)
             }
-            let length = byteorder::BigEndian::read_u32(&length_vec[..]);
-            let data: Vec<u8> = length
-                .to_be_bytes()
-                .iter()
-                .copied()
-                .chain(bytes_iter.by_ref().take((length + 8) as usize).copied())
-                .collect();
-            chunks.push(Chunk::try_from(&data[..])?)
         }
-        Ok(Png::from_chunks(chunks))
+    }
+    fn read_next_chunk(&mut self) -> Result<Option<Chunk>, error::PngFromFileError> {
+        let mut length = [0; 4];
+        match read_up_to(&mut self.reader, &mut length)? {
+            0 => return Ok(None),
+            4 => {}
+            _ => return Err(error::PngFromBytesError::LengthParse.into()),
+        }
+        let length = u32::from_be_bytes(length);
+        let mut type_bytes = [0; 4];
+        if read_up_to(&mut self.reader, &mut type_bytes)? != 4 {
+            return Err(error::ChunkParseError::NotEnoughBytesToParseType.into());
+        }
+        let chunk_type = ChunkType::try_from(type_bytes).map_err(error::ChunkParseError::from)?;
+        let mut data = Vec::new();
+        self.reader
+            .by_ref()
+            .take(u64::from(length))
+            .read_to_end(&mut data)?;
+        if data.len() != length as usize {
+            return Err(error::ChunkParseError::EncodedLengthNotEqualToActual.into());
+        }
+        let mut crc = [0; 4];
+        if read_up_to(&mut self.reader, &mut crc)? != 4 {
+            return Err(error::ChunkParseError::CouldNotParseCrc.into());
+        }
+        let chunk = Chunk::from_parts(chunk_type, data, u32::from_be_bytes(crc))?;
+        Ok(Some(chunk))
+    }
+    pub fn into_inner(self) -> R {
+        self.reader
     }
 }
 
+impl<R: Read> Iterator for PngReader<R> {
+    type Item = Result<Chunk, error::PngFromFileError>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.finished {
+            return None;
+        }
+        let result = self.read_chunk().transpose();
+        if !matches!(result, Some(Ok(_))) {
+            self.finished = true;
+        }
+        result
+    }
+}
+
+/// Writes PNG signature on creation and then chunks as they are passed in
+pub struct PngWriter<W: Write> {
+    writer: W,
+}
+
+impl<W: Write> PngWriter<W> {
+    pub fn new(mut writer: W) -> io::Result<Self> {
+        writer.write_all(&Png::STANDARD_HEADER)?;
+        Ok(Self { writer })
+    }
+    pub fn write_chunk(&mut self, chunk: &Chunk) -> io::Result<()> {
+        self.writer.write_all(&chunk.length().to_be_bytes())?;
+        self.writer.write_all(&chunk.chunk_type().bytes())?;
+        self.writer.write_all(chunk.data())?;
+        self.writer.write_all(&chunk.crc().to_be_bytes())
+    }
+    /// Writes bytes after the last chunk as they are
+    pub fn write_trailing_data(&mut self, data: &[u8]) -> io::Result<()> {
+        self.writer.write_all(data)
+    }
+    pub fn finish(mut self) -> io::Result<W> {
+        self.writer.flush()?;
+        Ok(self.writer)
+    }
+}
+
+/// Fills buffer until it is full or reader is exhausted, returns number of bytes read
+fn read_up_to(reader: &mut impl Read, buf: &mut [u8]) -> io::Result<usize> {
+    let mut filled = 0;
+    while filled < buf.len() {
+        match reader.read(&mut buf[filled..]) {
+            Ok(0) => break,
+            Ok(n) => filled += n,
+            Err(e) if e.kind() == io::ErrorKind::Interrupted => {}
+            Err(e) => return Err(e),
+        }
+    }
+    Ok(filled)
+}
+
 #[cfg(test)]
 mod tests {
     use std::convert::TryFrom;
@@ -230,6 +949,159 @@ mod tests {
         assert!(chunk.is_none());
     }
 
+    fn testing_image_png() -> Png {
+        Png::from_chunks(vec![
+            chunk_from_strings("IHDR", "header").unwrap(),
+            chunk_from_strings("gAMA", "gamma").unwrap(),
+            chunk_from_strings("IDAT", "first data").unwrap(),
+            chunk_from_strings("IDAT", "second data").unwrap(),
+            chunk_from_strings("IEND", "").unwrap(),
+        ])
+    }
+
+    fn insert_and_reparse(position: ChunkPosition) -> Vec<String> {
+        let mut png = testing_image_png();
+        let chunk = chunk_from_strings("RuSt", "Message").unwrap();
+        png.insert_chunk(chunk, position).unwrap();
+        let png = Png::try_from(&png.as_bytes()[..]).unwrap();
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_insert_chunk_before_iend() {
+        let types = insert_and_reparse(ChunkPosition::BeforeIend);
+        assert_eq!(types, ["IHDR", "gAMA", "IDAT", "IDAT", "RuSt", "IEND"]);
+    }
+
+    #[test]
+    fn test_insert_chunk_after_ihdr() {
+        let types = insert_and_reparse(ChunkPosition::AfterIhdr);
+        assert_eq!(types, ["IHDR", "RuSt", "gAMA", "IDAT", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_insert_chunk_before_first_idat() {
+        let types = insert_and_reparse(ChunkPosition::BeforeFirstIdat);
+        assert_eq!(types, ["IHDR", "gAMA", "RuSt", "IDAT", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_insert_chunk_at_index() {
+        let types = insert_and_reparse(ChunkPosition::Index(0));
+        assert_eq!(types, ["RuSt", "IHDR", "gAMA", "IDAT", "IDAT", "IEND"]);
+        let types = insert_and_reparse(ChunkPosition::Index(5));
+        assert_eq!(types, ["IHDR", "gAMA", "IDAT", "IDAT", "IEND", "RuSt"]);
+    }
+
+    #[test]
+    fn test_insert_chunk_at_invalid_index() {
+        let mut png = testing_image_png();
+        let chunk = chunk_from_strings("RuSt", "Message").unwrap();
+        assert!(png.insert_chunk(chunk, ChunkPosition::Index(6)).is_err());
+        assert_eq!(png.chunks().len(), 5);
+    }
+
+    #[test]
+    fn test_insert_chunk_without_anchor() {
+        let mut png = testing_png();
+        let chunk = chunk_from_strings("RuSt", "Message").unwrap();
+        assert!(png.insert_chunk(chunk, ChunkPosition::AfterIhdr).is_err());
+    }
+
+    #[test]
+    fn test_insert_chunk_without_iend_appends() {
+        let mut png = testing_png();
+        let chunk = chunk_from_strings("RuSt", "Message").unwrap();
+        png.insert_chunk_before_iend(chunk).unwrap();
+        assert_eq!(png.chunks().len(), 4);
+        assert_eq!(png.chunks()[3].chunk_type().to_string(), "RuSt");
+    }
+
+    #[test]
+    fn test_chunk_position_from_str() {
+        let position = ChunkPosition::from_str("before-iend").unwrap();
+        assert_eq!(position, ChunkPosition::BeforeIend);
+        let position = ChunkPosition::from_str("after-ihdr").unwrap();
+        assert_eq!(position, ChunkPosition::AfterIhdr);
+        let position = ChunkPosition::from_str("before-idat").unwrap();
+        assert_eq!(position, ChunkPosition::BeforeFirstIdat);
+        let position = ChunkPosition::from_str("3").unwrap();
+        assert_eq!(position, ChunkPosition::Index(3));
+        assert!(ChunkPosition::from_str("after-iend").is_err());
+    }
+
+    #[test]
+    fn test_retain_chunks() {
+        let mut png = testing_png();
+        let removed = png.retain_chunks(|chunk| chunk.chunk_type().is_critical());
+        let removed: Vec<_> = removed.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(removed, ["miDl"]);
+        assert_eq!(png.chunks().len(), 2);
+    }
+
+    #[test]
+    fn test_chunks_by_type_and_messages() {
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("miDl", "Second message").unwrap());
+        let chunk_type = ChunkType::from_str("miDl").unwrap();
+        assert_eq!(png.chunks_by_type(&chunk_type).count(), 2);
+        let messages = png.messages(&chunk_type);
+        assert_eq!(messages[1].data().unwrap(), b"Second message");
+        assert_eq!(messages[1].chunk_indexes(), [3]);
+
+        let bytes = png.as_bytes();
+        let png_ref = PngRef::try_from(&bytes[..]).unwrap();
+        assert_eq!(png_ref.chunks_by_type(&chunk_type).count(), 2);
+        assert_eq!(png_ref.messages(&chunk_type)[1].chunk_indexes(), [3]);
+
+        let removed = png.remove_chunks_at(&[0, 3]);
+        let removed: Vec<_> = removed.iter().map(|c| c.chunk_type().to_string()).collect();
+        assert_eq!(removed, ["FrSt", "miDl"]);
+        assert_eq!(png.chunks().len(), 2);
+    }
+
+    #[test]
+    fn test_insert_chunks_keeps_order() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let chunks = vec![
+            chunk_from_strings("ruSa", "1").unwrap(),
+            chunk_from_strings("ruSb", "2").unwrap(),
+        ];
+        png.insert_chunks(chunks, ChunkPosition::AfterIhdr).unwrap();
+        let types: Vec<_> = png.chunks()[..3]
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(types, ["IHDR", "ruSa", "ruSb"]);
+        let result = png.insert_chunks(Vec::new(), ChunkPosition::Index(100));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_replace_image_data() {
+        let mut png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let idat = || chunk_from_strings("IDAT", "new").unwrap();
+        png.replace_image_data(vec![idat(), idat()]).unwrap();
+        let types: Vec<_> = png
+            .chunks()
+            .iter()
+            .map(|c| c.chunk_type().to_string())
+            .collect();
+        assert_eq!(
+            types,
+            ["IHDR", "sRGB", "gAMA", "pHYs", "IDAT", "IDAT", "RuSt", "IEND"]
+        );
+        assert_eq!(png.chunks()[5].data(), b"new");
+
+        let mut png = testing_png();
+        png.append_chunk(chunk_from_strings("IEND", "").unwrap());
+        png.replace_image_data(vec![idat()]).unwrap();
+        assert_eq!(png.chunks()[3].chunk_type().to_string(), "IDAT");
+    }
+
     #[test]
     fn test_png_from_image_file() {
         let png = Png::try_from(&PNG_FILE[..]);
@@ -240,10 +1112,306 @@ mod tests {
     fn test_as_bytes() {
         let png = Png::try_from(&PNG_FILE[..]).unwrap();
         let actual = png.as_bytes();
-        let expected: Vec<u8> = PNG_FILE.iter().copied().collect();
+        let expected: Vec<u8> = PNG_FILE.to_vec();
         assert_eq!(actual, expected);
     }
 
+    #[test]
+    fn test_reader_reads_all_chunks() {
+        let reader = PngReader::new(&PNG_FILE[..]).unwrap();
+        let chunks: Vec<Chunk> = reader.collect::<Result<_, _>>().unwrap();
+        let expected = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(chunks.len(), expected.chunks().len());
+        for (actual, expected) in chunks.iter().zip(expected.chunks()) {
+            assert_eq!(actual.as_bytes(), expected.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_reader_does_not_buffer_trailing_data() {
+        let tail_length = 1 << 30;
+        // length above the chunk limit, so the tail can not start a chunk
+        let garbage = b"\xffgarbage";
+        let bytes = [&PNG_FILE[..], garbage].concat();
+        let input = bytes.chain(io::repeat(0).take(tail_length));
+        let mut reader = PngReader::new(input).unwrap();
+        let chunks = reader.by_ref().collect::<Result<Vec<_>, _>>().unwrap();
+        assert_eq!(chunks.last().unwrap().chunk_type().to_string(), "IEND");
+        assert_eq!(reader.trailing_data(), garbage);
+        let (_, tail) = reader.into_inner().into_inner();
+        assert_eq!(tail.limit(), tail_length);
+    }
+
+    #[test]
+    fn test_reader_invalid_header() {
+        let bytes = [13, 80, 78, 71, 13, 10, 26, 10];
+        assert!(PngReader::new(&bytes[..]).is_err());
+        assert!(PngReader::new(&Png::STANDARD_HEADER[..4]).is_err());
+    }
+
+    #[test]
+    fn test_reader_truncated_chunk() {
+        let truncated = &PNG_FILE[..PNG_FILE.len() - 2];
+        let mut reader = PngReader::new(truncated).unwrap();
+        let results: Vec<_> = reader.by_ref().collect();
+        assert!(results.last().unwrap().is_err());
+        assert!(results[..results.len() - 1].iter().all(|r| r.is_ok()));
+        assert!(reader.next().is_none());
+    }
+
+    #[test]
+    fn test_from_reader() {
+        let png = Png::from_reader(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.as_bytes(), PNG_FILE.to_vec());
+    }
+
+    #[test]
+    fn test_writer_matches_as_bytes() {
+        let png = testing_png();
+        let mut writer = PngWriter::new(Vec::new()).unwrap();
+        for chunk in png.chunks() {
+            writer.write_chunk(chunk).unwrap();
+        }
+        assert_eq!(writer.finish().unwrap(), png.as_bytes());
+        assert_eq!(png.write_to(Vec::new()).unwrap(), png.as_bytes());
+    }
+
+    #[test]
+    fn test_png_ref_from_image_file() {
+        let png = PngRef::try_from(&PNG_FILE[..]).unwrap();
+        let owned = Png::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png.chunks().len(), owned.chunks().len());
+        assert_eq!(png.to_png().as_bytes(), PNG_FILE.to_vec());
+        let idat = png.chunks()[4];
+        assert_eq!(idat.chunk_type().to_string(), "IDAT");
+        let start = idat.data().as_ptr() as usize - PNG_FILE.as_ptr() as usize;
+        assert_eq!(&PNG_FILE[start..start + idat.data().len()], idat.data());
+    }
+
+    #[test]
+    fn test_png_ref_chunk_by_type() {
+        let bytes = testing_png().as_bytes();
+        let png = PngRef::try_from(&bytes[..]).unwrap();
+        let chunk_type = ChunkType::from_str("miDl").unwrap();
+        let chunk = png.chunk_by_type(&chunk_type).unwrap();
+        assert_eq!(chunk.data_as_str().unwrap(), "I am another chunk");
+        let chunk_type = ChunkType::from_str("NoNe").unwrap();
+        assert!(png.chunk_by_type(&chunk_type).is_none());
+    }
+
+    #[test]
+    fn test_png_ref_invalid_input() {
+        assert!(PngRef::try_from(&Png::STANDARD_HEADER[..4]).is_err());
+        assert!(PngRef::try_from(&PNG_FILE[..PNG_FILE.len() - 1]).is_err());
+        assert!(PngRef::try_from(&PNG_FILE[..PNG_FILE.len() - 10]).is_err());
+    }
+
+    #[test]
+    fn test_source_offsets() {
+        let png = PngRef::try_from(&PNG_FILE[..]).unwrap();
+        let mut expected = Png::STANDARD_HEADER.len() as u64;
+        for chunk in png.chunks() {
+            let layout = chunk.layout().unwrap();
+            assert_eq!(layout.offset(), expected);
+            let data = layout.data();
+            assert_eq!(
+                &PNG_FILE[data.start as usize..data.end as usize],
+                chunk.data()
+            );
+            expected = layout.end();
+        }
+        assert_eq!(expected, PNG_FILE.len() as u64);
+        assert_eq!(png.trailing_offset(), PNG_FILE.len() as u64);
+
+        let owned = Png::from_reader(&PNG_FILE[..]).unwrap();
+        let offsets: Vec<_> = owned.chunks().iter().map(Chunk::source_offset).collect();
+        let expected: Vec<_> = png.chunks().iter().map(ChunkRef::source_offset).collect();
+        assert_eq!(offsets, expected);
+        assert!(testing_png().chunks()[0].source_offset().is_none());
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut bytes = PNG_FILE.to_vec();
+        let iend = bytes.len() - 12;
+        // a chunk after IEND is still a chunk, bytes that do not form one are trailing data
+        bytes.extend(testing_chunks()[0].as_bytes());
+        bytes.extend(b"trailing");
+        let png = PngRef::try_from(&bytes[..]).unwrap();
+        assert_eq!(png.trailing_data(), b"trailing");
+        assert_eq!(png.trailing_offset(), (bytes.len() - 8) as u64);
+        let last = png.chunks().last().unwrap();
+        assert_eq!(last.chunk_type().to_string(), "FrSt");
+        assert_eq!(last.source_offset(), Some(iend as u64 + 12));
+
+        let mut owned = Png::from_reader(&bytes[..]).unwrap();
+        assert_eq!(owned.chunks().len(), png.chunks().len());
+        assert_eq!(owned.trailing_data(), b"trailing");
+        assert_eq!(owned.as_bytes(), bytes);
+        assert_eq!(owned.write_to(Vec::new()).unwrap(), bytes);
+        assert_eq!(owned.remove_trailing_data(), b"trailing");
+        assert_eq!(owned.as_bytes().len(), bytes.len() - 8);
+    }
+
+    #[test]
+    fn test_garbage_before_iend_is_error() {
+        let mut bytes = PNG_FILE[..PNG_FILE.len() - 12].to_vec();
+        bytes.extend(b"garbage");
+        bytes.extend(&PNG_FILE[PNG_FILE.len() - 12..]);
+        assert!(PngRef::try_from(&bytes[..]).is_err());
+        assert!(Png::from_reader(&bytes[..]).is_err());
+    }
+
+    fn chunk_types(png: &PngRef) -> Vec<String> {
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_parse_strict_matches_try_from() {
+        let (png, warnings) = PngRef::parse(&PNG_FILE, ParseOptions::strict()).unwrap();
+        assert!(warnings.is_empty());
+        assert_eq!(
+            chunk_types(&png),
+            ["IHDR", "sRGB", "gAMA", "pHYs", "IDAT", "RuSt", "IEND"]
+        );
+        assert!(ParseOptions::strict().is_strict());
+        assert!(!ParseOptions::lenient().is_strict());
+    }
+
+    #[test]
+    fn test_parse_ignore_crc() {
+        let mut bytes = testing_image_png().as_bytes();
+        // last byte of gAMA crc
+        bytes[42] ^= 1;
+        let options = ParseOptions::strict().ignore_crc(true);
+        assert!(PngRef::parse(&bytes, ParseOptions::strict()).is_err());
+        let (png, warnings) = PngRef::parse(&bytes, options).unwrap();
+        assert_eq!(png.chunks().len(), 5);
+        assert!(!png.chunks()[1].has_valid_crc());
+        assert!(matches!(
+            &warnings[..],
+            [ParseWarning::CrcMismatch { offset: 26, chunk_type }] if chunk_type == "gAMA"
+        ));
+        let (owned, _) = Png::parse(&bytes, options).unwrap();
+        assert_eq!(owned.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_parse_skip_bad_chunks() {
+        let mut bytes = testing_image_png().as_bytes();
+        // data of the first IDAT
+        bytes[52] ^= 1;
+        let options = ParseOptions::strict().skip_bad_chunks(true);
+        let (png, warnings) = PngRef::parse(&bytes, options).unwrap();
+        assert_eq!(chunk_types(&png), ["IHDR", "gAMA", "IDAT", "IEND"]);
+        assert_eq!(png.chunks()[2].data(), b"second data");
+        assert!(matches!(
+            &warnings[..],
+            [ParseWarning::SkippedBytes {
+                offset: 43,
+                length: 22,
+                reason: error::ChunkParseError::CrcMismatch
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_parse_skip_chunk_with_broken_length() {
+        let mut bytes = testing_image_png().as_bytes();
+        // length of gAMA points far beyond the end of input
+        bytes[26] = 0x7f;
+        let options = ParseOptions::strict().skip_bad_chunks(true);
+        let (png, warnings) = PngRef::parse(&bytes, options).unwrap();
+        assert_eq!(chunk_types(&png), ["IHDR", "IDAT", "IDAT", "IEND"]);
+        assert_eq!(png.chunks()[1].source_offset(), Some(43));
+        assert_eq!(warnings.len(), 1);
+        assert_eq!(warnings[0].offset(), 26);
+    }
+
+    #[test]
+    fn test_parse_ignore_crc_does_not_trust_broken_length() {
+        let mut bytes = testing_image_png().as_bytes();
+        // length of gAMA points into the middle of the first IDAT
+        bytes[29] += 9;
+        let (png, warnings) = PngRef::parse(&bytes, ParseOptions::lenient()).unwrap();
+        assert_eq!(chunk_types(&png), ["IHDR", "IDAT", "IDAT", "IEND"]);
+        assert_eq!(png.chunks()[1].source_offset(), Some(43));
+        assert!(matches!(
+            &warnings[..],
+            [ParseWarning::SkippedBytes {
+                offset: 26,
+                length: 17,
+                ..
+            }]
+        ));
+    }
+
+    #[test]
+    fn test_parse_stop_at_truncation() {
+        let bytes = testing_image_png().as_bytes();
+        // input ends in the middle of the second IDAT
+        let bytes = &bytes[..80];
+        assert!(PngRef::parse(bytes, ParseOptions::strict()).is_err());
+        let options = ParseOptions::strict().stop_at_truncation(true);
+        let (png, warnings) = PngRef::parse(bytes, options).unwrap();
+        assert_eq!(chunk_types(&png), ["IHDR", "gAMA", "IDAT"]);
+        assert!(png.trailing_data().is_empty());
+        assert!(matches!(
+            &warnings[..],
+            [ParseWarning::Truncated {
+                offset: 65,
+                length: 15
+            }]
+        ));
+        let (png, warnings) = PngRef::parse(&bytes[..45], options).unwrap();
+        assert_eq!(chunk_types(&png), ["IHDR", "gAMA"]);
+        assert_eq!(warnings[0].offset(), 43);
+    }
+
+    #[test]
+    fn test_parse_lenient_damaged_and_truncated() {
+        let mut bytes = testing_image_png().as_bytes();
+        // data of the second IDAT and the end of IEND
+        bytes[75] ^= 1;
+        let bytes = &bytes[..95];
+        let (png, warnings) = PngRef::parse(bytes, ParseOptions::lenient()).unwrap();
+        assert_eq!(chunk_types(&png), ["IHDR", "gAMA", "IDAT", "IDAT"]);
+        assert_eq!(png.chunks()[3].data(), b"sebond data");
+        let offsets: Vec<_> = warnings.iter().map(ParseWarning::offset).collect();
+        assert_eq!(offsets, [65, 88]);
+    }
+
+    #[test]
+    fn test_parse_lenient_keeps_chunk_after_iend_with_bad_crc() {
+        let mut bytes = testing_image_png().as_bytes();
+        bytes.extend(testing_chunks()[0].as_bytes());
+        let last = bytes.len() - 1;
+        bytes[last] ^= 1;
+        let (png, _) = PngRef::parse(&bytes, ParseOptions::strict()).unwrap();
+        assert_eq!(png.chunks().len(), 5);
+        assert_eq!(png.trailing_data().len(), 32);
+        let (png, warnings) = PngRef::parse(&bytes, ParseOptions::lenient()).unwrap();
+        assert_eq!(png.chunks().len(), 6);
+        assert!(png.trailing_data().is_empty());
+        assert_eq!(warnings[0].offset(), 100);
+    }
+
+    #[test]
+    fn test_header() {
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        let header = png.header().unwrap();
+        assert_eq!(header.width(), 50);
+        assert_eq!(header.height(), 50);
+        assert_eq!(header.bit_depth(), 8);
+        assert_eq!(header.color_type(), crate::ihdr::ColorType::Rgba);
+        let png_ref = PngRef::try_from(&PNG_FILE[..]).unwrap();
+        assert_eq!(png_ref.header().unwrap(), header);
+        assert!(testing_png().header().is_err());
+    }
+
     #[test]
     fn test_png_trait_impls() {
         let chunk_bytes: Vec<u8> = testing_chunks()
@@ -262,6 +1430,70 @@ mod tests {
         let _png_string = format!("{}", png);
     }
 
+    #[test]
+    fn test_write_to_path() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("image.png");
+        testing_png().write_to_path(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), testing_png().as_bytes());
+
+        let png = Png::try_from(&PNG_FILE[..]).unwrap();
+        png.write_to_path(&path).unwrap();
+        assert_eq!(fs::read(&path).unwrap(), PNG_FILE);
+        let names: Vec<_> = fs::read_dir(directory.path())
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name())
+            .collect();
+        assert_eq!(names, ["image.png"]);
+    }
+
+    #[test]
+    fn test_write_to_path_keeps_metadata() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("image.png");
+        fs::write(&path, b"old").unwrap();
+        let modified = std::time::UNIX_EPOCH + std::time::Duration::from_secs(1_000_000);
+        let file = fs::File::options().write(true).open(&path).unwrap();
+        file.set_modified(modified).unwrap();
+        let mut permissions = file.metadata().unwrap().permissions();
+        permissions.set_readonly(true);
+        fs::set_permissions(&path, permissions).unwrap();
+
+        let options = WriteOptions::default().backup(".bak");
+        testing_png()
+            .write_to_path_with_options(&path, &options)
+            .unwrap();
+        let metadata = fs::metadata(&path).unwrap();
+        assert_eq!(metadata.modified().unwrap(), modified);
+        assert!(metadata.permissions().readonly());
+        assert_eq!(fs::read(&path).unwrap(), testing_png().as_bytes());
+        assert_eq!(
+            fs::read(directory.path().join("image.png.bak")).unwrap(),
+            b"old"
+        );
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_write_to_path_new_file_permissions() {
+        use std::os::unix::fs::PermissionsExt;
+        let directory = tempfile::tempdir().unwrap();
+        let created = directory.path().join("created.png");
+        fs::File::create(&created).unwrap();
+        let path = directory.path().join("image.png");
+        testing_png().write_to_path(&path).unwrap();
+        let mode = |path: &Path| fs::metadata(path).unwrap().permissions().mode() & 0o777;
+        assert_eq!(mode(&path), mode(&created));
+    }
+
+    #[test]
+    fn test_write_to_path_missing_directory() {
+        let directory = tempfile::tempdir().unwrap();
+        let path = directory.path().join("missing").join("image.png");
+        assert!(testing_png().write_to_path(&path).is_err());
+        assert!(!path.exists());
+    }
+
     // This is the raw bytes for a shrunken version of the `dice.png` image on Wikipedia
     const PNG_FILE: [u8; 4803] = [
         137, 80, 78, 71, 13, 10, 26, 10, 0, 0, 0, 13, 73, 72, 68, 82, 0, 0, 0, 50, 0, 0, 0, 50, 8,
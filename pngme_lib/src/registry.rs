@@ -0,0 +1,399 @@
+//! Chunk types defined by the PNG specification, third edition, and registered extensions
+//!
+//! Every entry records where the chunk may appear, how many times and what it holds. The third
+//! edition includes APNG animation chunks, eXIf and HDR metadata, the extensions document adds
+//! chunks such as oFFs, pCAL, sCAL and dSIG.
+use std::convert::TryFrom;
+
+use crate::chunk_type::ChunkType;
+
+/// Where a chunk may appear relative to the critical chunks
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Placement {
+    First,
+    /// Before PLTE and IDAT
+    BeforePlte,
+    /// After PLTE and before IDAT
+    AfterPlte,
+    BeforeIdat,
+    /// Consecutively with the other IDAT chunks
+    ImageData,
+    AfterIdat,
+    /// Anywhere between IHDR and IEND
+    Anywhere,
+    Last,
+}
+
+impl std::fmt::Display for Placement {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::First => write!(f, "first"),
+            Self::BeforePlte => write!(f, "before PLTE and IDAT"),
+            Self::AfterPlte => write!(f, "after PLTE and before IDAT"),
+            Self::BeforeIdat => write!(f, "before IDAT"),
+            Self::ImageData => write!(f, "consecutive with other IDAT chunks"),
+            Self::AfterIdat => write!(f, "after IDAT"),
+            Self::Anywhere => write!(f, "anywhere between IHDR and IEND"),
+            Self::Last => write!(f, "last"),
+        }
+    }
+}
+
+/// How many chunks of a type a file may contain
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Multiplicity {
+    Once,
+    AtMostOnce,
+    AtLeastOnce,
+    Any,
+}
+
+impl std::fmt::Display for Multiplicity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Once => write!(f, "exactly once"),
+            Self::AtMostOnce => write!(f, "at most once"),
+            Self::AtLeastOnce => write!(f, "one or more"),
+            Self::Any => write!(f, "any number"),
+        }
+    }
+}
+
+/// What the specification says about a chunk type
+#[derive(Debug, PartialEq, Eq)]
+pub struct ChunkSpec {
+    bytes: [u8; 4],
+    name: &'static str,
+    description: &'static str,
+    placement: Placement,
+    multiplicity: Multiplicity,
+}
+
+impl ChunkSpec {
+    const fn new(
+        bytes: &[u8; 4],
+        name: &'static str,
+        description: &'static str,
+        placement: Placement,
+        multiplicity: Multiplicity,
+    ) -> Self {
+        Self {
+            bytes: *bytes,
+            name,
+            description,
+            placement,
+            multiplicity,
+        }
+    }
+    pub fn chunk_type(&self) -> ChunkType {
+        ChunkType::try_from(self.bytes).expect("registered chunk types are valid")
+    }
+    pub fn name(&self) -> &'static str {
+        self.name
+    }
+    pub fn description(&self) -> &'static str {
+        self.description
+    }
+    pub fn placement(&self) -> Placement {
+        self.placement
+    }
+    pub fn multiplicity(&self) -> Multiplicity {
+        self.multiplicity
+    }
+}
+
+impl std::fmt::Display for ChunkSpec {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}: {}", self.name, self.description)
+    }
+}
+
+use Multiplicity::*;
+use Placement::*;
+
+static REGISTRY: [ChunkSpec; 32] = [
+    ChunkSpec::new(
+        b"IHDR",
+        "Image header",
+        "width, height, bit depth, color type, compression, filter and interlace methods",
+        First,
+        Once,
+    ),
+    ChunkSpec::new(
+        b"PLTE",
+        "Palette",
+        "colors of an indexed-color image, suggested colors for true color images",
+        BeforeIdat,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"IDAT",
+        "Image data",
+        "zlib stream of filtered scanlines, may be split into several chunks",
+        ImageData,
+        AtLeastOnce,
+    ),
+    ChunkSpec::new(
+        b"IEND",
+        "Image trailer",
+        "marks the end of the datastream",
+        Last,
+        Once,
+    ),
+    ChunkSpec::new(
+        b"cHRM",
+        "Primary chromaticities and white point",
+        "CIE 1931 coordinates of the red, green and blue primaries and the white point",
+        BeforePlte,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"gAMA",
+        "Image gamma",
+        "relation between sample values and display output intensity",
+        BeforePlte,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"iCCP",
+        "Embedded ICC profile",
+        "compressed ICC color profile, should not appear together with sRGB",
+        BeforePlte,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"sBIT",
+        "Significant bits",
+        "number of significant bits in samples of the original image",
+        BeforePlte,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"sRGB",
+        "Standard RGB color space",
+        "image uses the sRGB color space with the given rendering intent",
+        BeforePlte,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"cICP",
+        "Coding-independent code points",
+        "color primaries, transfer function and matrix of video color spaces such as HDR",
+        BeforePlte,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"mDCV",
+        "Mastering display color volume",
+        "primaries and luminance range of the display HDR content was mastered on",
+        BeforePlte,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"cLLI",
+        "Content light level information",
+        "maximum and maximum frame-average light level of HDR content",
+        BeforePlte,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"bKGD",
+        "Background color",
+        "default color to present the image against",
+        AfterPlte,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"hIST",
+        "Image histogram",
+        "approximate usage frequency of every palette entry",
+        AfterPlte,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"tRNS",
+        "Transparency",
+        "alpha of palette entries or a single transparent color",
+        AfterPlte,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"eXIf",
+        "Exchangeable image file profile",
+        "Exif metadata such as camera settings and orientation",
+        BeforeIdat,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"pHYs",
+        "Physical pixel dimensions",
+        "pixels per unit or aspect ratio of pixels",
+        BeforeIdat,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"sPLT",
+        "Suggested palette",
+        "named reduced palette for displays with few colors",
+        BeforeIdat,
+        Any,
+    ),
+    ChunkSpec::new(
+        b"tIME",
+        "Image last-modification time",
+        "UTC time of the last change of the image",
+        Anywhere,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"tEXt",
+        "Textual data",
+        "Latin-1 text with a keyword",
+        Anywhere,
+        Any,
+    ),
+    ChunkSpec::new(
+        b"zTXt",
+        "Compressed textual data",
+        "compressed Latin-1 text with a keyword",
+        Anywhere,
+        Any,
+    ),
+    ChunkSpec::new(
+        b"iTXt",
+        "International textual data",
+        "UTF-8 text with a keyword and language tag, optionally compressed",
+        Anywhere,
+        Any,
+    ),
+    ChunkSpec::new(
+        b"acTL",
+        "Animation control",
+        "number of frames and plays of an APNG animation",
+        BeforeIdat,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"fcTL",
+        "Frame control",
+        "size, position, delay, disposal and blending of an APNG frame",
+        Anywhere,
+        Any,
+    ),
+    ChunkSpec::new(
+        b"fdAT",
+        "Frame data",
+        "sequence number and compressed pixels of an APNG frame after the default image",
+        AfterIdat,
+        Any,
+    ),
+    ChunkSpec::new(
+        b"oFFs",
+        "Image offset",
+        "position of the image on a page or screen",
+        BeforeIdat,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"pCAL",
+        "Calibration of pixel values",
+        "equation that maps sample values to physical values",
+        BeforeIdat,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"sCAL",
+        "Physical scale of image subject",
+        "physical width and height of the scene a pixel covers",
+        BeforeIdat,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"gIFg",
+        "GIF graphic control extension",
+        "disposal method and delay kept from a converted GIF",
+        Anywhere,
+        Any,
+    ),
+    ChunkSpec::new(
+        b"gIFx",
+        "GIF application extension",
+        "application data kept from a converted GIF",
+        Anywhere,
+        Any,
+    ),
+    ChunkSpec::new(
+        b"sTER",
+        "Indicator of stereo image",
+        "image is a side by side pair of views for the left and right eye",
+        BeforeIdat,
+        AtMostOnce,
+    ),
+    ChunkSpec::new(
+        b"dSIG",
+        "Digital signature",
+        "signature of the datastream, pairs of dSIG chunks enclose signed chunks",
+        Anywhere,
+        Any,
+    ),
+];
+
+/// Every registered chunk type in the order of the specification
+pub fn all() -> &'static [ChunkSpec] {
+    &REGISTRY
+}
+
+/// Specification of chunk type given as bytes, `None` for private and unknown types
+pub fn lookup(bytes: [u8; 4]) -> Option<&'static ChunkSpec> {
+    REGISTRY.iter().find(|spec| spec.bytes == bytes)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::HashSet;
+
+    use super::*;
+
+    #[test]
+    fn test_lookup() {
+        let spec = lookup(*b"tIME").unwrap();
+        assert_eq!(spec.name(), "Image last-modification time");
+        assert_eq!(spec.placement(), Anywhere);
+        assert_eq!(spec.multiplicity(), AtMostOnce);
+        assert_eq!(spec.chunk_type().to_string(), "tIME");
+        assert_eq!(lookup(*b"IHDR").unwrap().placement(), First);
+        assert!(lookup(*b"RuSt").is_none());
+        assert!(lookup(*b"time").is_none());
+    }
+
+    #[test]
+    fn test_registry_is_consistent() {
+        let types: HashSet<_> = all().iter().map(|spec| spec.bytes).collect();
+        assert_eq!(types.len(), all().len());
+        assert!(all().iter().all(|spec| spec.chunk_type().is_valid()));
+        // APNG chunks predate their standardization and keep lowercase private bit
+        let private: Vec<_> = all()
+            .iter()
+            .filter(|spec| !spec.chunk_type().is_public())
+            .map(|spec| spec.chunk_type().to_string())
+            .collect();
+        assert_eq!(private, ["acTL", "fcTL", "fdAT"]);
+        let critical: Vec<_> = all()
+            .iter()
+            .filter(|spec| spec.chunk_type().is_critical())
+            .map(|spec| spec.chunk_type().to_string())
+            .collect();
+        assert_eq!(critical, ["IHDR", "PLTE", "IDAT", "IEND"]);
+    }
+
+    #[test]
+    fn test_display() {
+        let spec = lookup(*b"IEND").unwrap();
+        assert_eq!(
+            spec.to_string(),
+            "Image trailer: marks the end of the datastream"
+        );
+        assert_eq!(AfterPlte.to_string(), "after PLTE and before IDAT");
+        assert_eq!(AtLeastOnce.to_string(), "one or more");
+    }
+}
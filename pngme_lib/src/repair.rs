@@ -0,0 +1,471 @@
+//! Fixes damaged PNG files: wrong crcs and chunk layout that breaks the rules checked by
+//! `validate`
+//!
+//! Structure is repaired first so image data can be decoded when deciding whether a crc should be
+//! recomputed. Bytes after IEND that do not form chunks are left as they are.
+use std::convert::TryFrom;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::RepairOptionParseError;
+use crate::ihdr::Ihdr;
+use crate::pixels;
+use crate::png::Png;
+use crate::text::TextualChunk;
+
+/// Which chunks with wrong crc get the crc recomputed
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CrcRepair {
+    All,
+    /// Only chunks whose data passes the checks of its chunk type, chunks of types that can
+    /// not be checked are left as they are
+    Plausible,
+    None,
+}
+
+impl std::str::FromStr for CrcRepair {
+    type Err = RepairOptionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "all" => Ok(Self::All),
+            "plausible" => Ok(Self::Plausible),
+            "none" => Ok(Self::None),
+            _ => Err(RepairOptionParseError::UnknownCrcRepair(s.to_string())),
+        }
+    }
+}
+
+/// What happens to chunks found after IEND
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AfterIend {
+    /// Move them right before IEND
+    Rescue,
+    Drop,
+}
+
+impl std::str::FromStr for AfterIend {
+    type Err = RepairOptionParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "rescue" => Ok(Self::Rescue),
+            "drop" => Ok(Self::Drop),
+            _ => Err(RepairOptionParseError::UnknownAfterIend(s.to_string())),
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct RepairOptions {
+    crc: CrcRepair,
+    after_iend: AfterIend,
+}
+
+impl Default for RepairOptions {
+    fn default() -> Self {
+        Self {
+            crc: CrcRepair::All,
+            after_iend: AfterIend::Rescue,
+        }
+    }
+}
+
+impl RepairOptions {
+    pub fn crc(mut self, crc: CrcRepair) -> Self {
+        self.crc = crc;
+        self
+    }
+    pub fn after_iend(mut self, after_iend: AfterIend) -> Self {
+        self.after_iend = after_iend;
+        self
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RepairKind {
+    CrcFixed {
+        stored: u32,
+        computed: u32,
+    },
+    /// Crc is wrong but the data does not look valid or can not be checked
+    CrcKept,
+    DuplicateIhdrRemoved,
+    IhdrMovedFirst,
+    ChunkAfterIendMoved,
+    ChunkAfterIendDropped,
+    DuplicateIendRemoved,
+    IendAdded,
+    IdatMoved,
+    PlteMoved,
+}
+
+impl std::fmt::Display for RepairKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::CrcFixed { stored, computed } => {
+                write!(f, "crc {:08x} replaced with {:08x}", stored, computed)
+            }
+            Self::CrcKept => write!(f, "crc is wrong but data can not be trusted, kept"),
+            Self::DuplicateIhdrRemoved => write!(f, "duplicate IHDR removed"),
+            Self::IhdrMovedFirst => write!(f, "moved to the start"),
+            Self::ChunkAfterIendMoved => write!(f, "moved from after IEND to before it"),
+            Self::ChunkAfterIendDropped => write!(f, "removed from after IEND"),
+            Self::DuplicateIendRemoved => write!(f, "duplicate IEND removed"),
+            Self::IendAdded => write!(f, "missing IEND added"),
+            Self::IdatMoved => write!(f, "moved next to the other IDAT chunks"),
+            Self::PlteMoved => write!(f, "moved before image data"),
+        }
+    }
+}
+
+/// Single change made by `repair`
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Repair {
+    kind: RepairKind,
+    chunk_type: String,
+    offset: Option<u64>,
+}
+
+impl Repair {
+    fn new(kind: RepairKind, chunk: &Chunk) -> Self {
+        Self {
+            kind,
+            chunk_type: chunk.chunk_type().to_string(),
+            offset: chunk.source_offset(),
+        }
+    }
+    pub fn kind(&self) -> &RepairKind {
+        &self.kind
+    }
+    pub fn chunk_type(&self) -> &str {
+        &self.chunk_type
+    }
+    /// Offset of the chunk in the file it was read from
+    pub fn offset(&self) -> Option<u64> {
+        self.offset
+    }
+}
+
+impl std::fmt::Display for Repair {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.offset {
+            Some(offset) => write!(f, "{} at {:#x}: {}", self.chunk_type, offset, self.kind),
+            None => write!(f, "{}: {}", self.chunk_type, self.kind),
+        }
+    }
+}
+
+/// Fixes structure and crcs of `png` in place, returns every change in the order it was made
+pub fn repair(png: &mut Png, options: RepairOptions) -> Vec<Repair> {
+    let mut repairs = vec![];
+    let chunks = png.chunks_mut();
+    repair_ihdr(chunks, &mut repairs);
+    repair_iend(chunks, options.after_iend, &mut repairs);
+    repair_idat(chunks, &mut repairs);
+    repair_plte(chunks, &mut repairs);
+    let image_is_valid = match options.crc {
+        CrcRepair::Plausible => pixels::decode(png).is_ok(),
+        _ => false,
+    };
+    for chunk in png.chunks_mut().iter_mut() {
+        if chunk.has_valid_crc() || options.crc == CrcRepair::None {
+            continue;
+        }
+        if options.crc == CrcRepair::Plausible && !is_plausible(chunk, image_is_valid) {
+            repairs.push(Repair::new(RepairKind::CrcKept, chunk));
+            continue;
+        }
+        let mut fixed = Chunk::new(chunk.chunk_type().clone(), chunk.data());
+        if let Some(offset) = chunk.source_offset() {
+            fixed = fixed.with_source_offset(offset);
+        }
+        let kind = RepairKind::CrcFixed {
+            stored: chunk.crc(),
+            computed: fixed.crc(),
+        };
+        repairs.push(Repair::new(kind, chunk));
+        *chunk = fixed;
+    }
+    repairs
+}
+
+fn positions(chunks: &[Chunk], chunk_type: &[u8; 4]) -> Vec<usize> {
+    chunks
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| chunk.chunk_type().bytes() == *chunk_type)
+        .map(|(i, _)| i)
+        .collect()
+}
+
+/// Keeps the first IHDR with valid data, or the first one when none is valid, as the first chunk
+fn repair_ihdr(chunks: &mut Vec<Chunk>, repairs: &mut Vec<Repair>) {
+    let ihdr = positions(chunks, b"IHDR");
+    let keep = match ihdr
+        .iter()
+        .find(|&&i| Ihdr::try_from(&chunks[i]).is_ok())
+        .or_else(|| ihdr.first())
+    {
+        Some(&keep) => keep,
+        None => return,
+    };
+    for &index in ihdr.iter().rev().filter(|&&i| i != keep) {
+        let removed = chunks.remove(index);
+        repairs.push(Repair::new(RepairKind::DuplicateIhdrRemoved, &removed));
+    }
+    let index = chunks
+        .iter()
+        .position(|chunk| chunk.chunk_type().bytes() == *b"IHDR")
+        .expect("kept IHDR is present");
+    if index != 0 {
+        let header = chunks.remove(index);
+        repairs.push(Repair::new(RepairKind::IhdrMovedFirst, &header));
+        chunks.insert(0, header);
+    }
+}
+
+/// Handles chunks after the first IEND or appends IEND when there is none
+fn repair_iend(chunks: &mut Vec<Chunk>, after_iend: AfterIend, repairs: &mut Vec<Repair>) {
+    let iend = match positions(chunks, b"IEND").first() {
+        Some(&iend) => iend,
+        None => {
+            let chunk_type = ChunkType::try_from(*b"IEND").expect("IEND is a valid chunk type");
+            let chunk = Chunk::new(chunk_type, &[]);
+            repairs.push(Repair::new(RepairKind::IendAdded, &chunk));
+            chunks.push(chunk);
+            return;
+        }
+    };
+    let mut rescued = vec![];
+    for chunk in chunks.drain(iend + 1..).collect::<Vec<_>>() {
+        let kind = match (chunk.chunk_type().bytes() == *b"IEND", after_iend) {
+            (true, _) => RepairKind::DuplicateIendRemoved,
+            (false, AfterIend::Drop) => RepairKind::ChunkAfterIendDropped,
+            (false, AfterIend::Rescue) => RepairKind::ChunkAfterIendMoved,
+        };
+        repairs.push(Repair::new(kind.clone(), &chunk));
+        if kind == RepairKind::ChunkAfterIendMoved {
+            rescued.push(chunk);
+        }
+    }
+    chunks.splice(iend..iend, rescued);
+}
+
+/// Gathers all IDAT chunks in their order at the place of the first one
+fn repair_idat(chunks: &mut Vec<Chunk>, repairs: &mut Vec<Repair>) {
+    let idat = positions(chunks, b"IDAT");
+    let first = match idat.first() {
+        Some(&first) => first,
+        None => return,
+    };
+    let (idat, others): (Vec<_>, Vec<_>) = chunks
+        .drain(..)
+        .enumerate()
+        .partition(|(_, chunk)| chunk.chunk_type().bytes() == *b"IDAT");
+    for (k, (index, chunk)) in idat.iter().enumerate() {
+        if *index != first + k {
+            repairs.push(Repair::new(RepairKind::IdatMoved, chunk));
+        }
+    }
+    chunks.extend(others.into_iter().map(|(_, chunk)| chunk));
+    chunks.splice(first..first, idat.into_iter().map(|(_, chunk)| chunk));
+}
+
+/// Moves PLTE found after image data right before the first IDAT
+fn repair_plte(chunks: &mut Vec<Chunk>, repairs: &mut Vec<Repair>) {
+    let first_idat = match positions(chunks, b"IDAT").first() {
+        Some(&first) => first,
+        None => return,
+    };
+    for index in positions(chunks, b"PLTE").into_iter().rev() {
+        if index > first_idat {
+            let plte = chunks.remove(index);
+            repairs.push(Repair::new(RepairKind::PlteMoved, &plte));
+            chunks.insert(first_idat, plte);
+        }
+    }
+}
+
+/// Whether chunk data passes the checks known for its type, IDAT is judged by decoding the
+/// whole image
+fn is_plausible(chunk: &Chunk, image_is_valid: bool) -> bool {
+    let length = chunk.data().len();
+    match &chunk.chunk_type().bytes() {
+        b"IHDR" => Ihdr::try_from(chunk).is_ok(),
+        b"IDAT" => image_is_valid,
+        b"IEND" => length == 0,
+        b"PLTE" => length > 0 && length <= 3 * 256 && length.is_multiple_of(3),
+        b"tEXt" | b"zTXt" | b"iTXt" => TextualChunk::try_from(chunk).is_ok(),
+        b"gAMA" => length == 4,
+        b"sRGB" => length == 1 && chunk.data()[0] <= 3,
+        b"pHYs" => length == 9 && chunk.data()[8] <= 1,
+        b"tIME" => length == 7,
+        b"cHRM" => length == 32,
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::ihdr::ColorType;
+    use crate::png::ParseOptions;
+    use crate::test_utils::{chunk, chunk_types, dice_bytes};
+    use crate::validate;
+
+    fn kinds(repairs: &[Repair]) -> Vec<RepairKind> {
+        repairs.iter().map(|repair| repair.kind().clone()).collect()
+    }
+
+    /// Flips a bit of chunk data at `index` in file bytes, the crc of the chunk becomes wrong
+    fn damage_chunk(bytes: &mut [u8], index: usize) {
+        let (png, _) = Png::parse(bytes, ParseOptions::lenient()).unwrap();
+        let layout = png.chunks()[index].layout().unwrap();
+        bytes[layout.data().start as usize] ^= 1;
+    }
+
+    #[test]
+    fn test_valid_file_is_unchanged() {
+        let mut png = Png::try_from(&dice_bytes()[..]).unwrap();
+        assert!(repair(&mut png, RepairOptions::default()).is_empty());
+        assert_eq!(png.as_bytes(), dice_bytes());
+    }
+
+    #[test]
+    fn test_fix_crc() {
+        let mut bytes = dice_bytes();
+        damage_chunk(&mut bytes, 2);
+        let (mut png, _) = Png::parse(&bytes, ParseOptions::lenient()).unwrap();
+        let repairs = repair(&mut png, RepairOptions::default());
+        assert_eq!(repairs.len(), 1);
+        assert!(matches!(repairs[0].kind(), RepairKind::CrcFixed { .. }));
+        assert_eq!(repairs[0].chunk_type(), "gAMA");
+        assert_eq!(repairs[0].offset(), Some(0x2e));
+        assert!(png.chunks().iter().all(Chunk::has_valid_crc));
+        assert!(Png::try_from(&png.as_bytes()[..]).is_ok());
+    }
+
+    #[test]
+    fn test_fix_crc_only_when_plausible() {
+        let mut bytes = dice_bytes();
+        // sRGB rendering intent becomes 1, still valid
+        damage_chunk(&mut bytes, 1);
+        // first IDAT, zlib stream no longer matches its checksum
+        damage_chunk(&mut bytes, 4);
+        let parse = || Png::parse(&bytes, ParseOptions::lenient()).unwrap().0;
+        let options = RepairOptions::default().crc(CrcRepair::Plausible);
+        let mut plausible = parse();
+        let repairs = repair(&mut plausible, options);
+        assert!(matches!(repairs[0].kind(), RepairKind::CrcFixed { .. }));
+        assert_eq!(repairs[1].kind(), &RepairKind::CrcKept);
+        assert_eq!(repairs[1].chunk_type(), "IDAT");
+        assert!(!plausible.chunks()[4].has_valid_crc());
+
+        let mut untouched = parse();
+        let options = RepairOptions::default().crc(CrcRepair::None);
+        assert!(repair(&mut untouched, options).is_empty());
+        assert_eq!(untouched.as_bytes(), bytes);
+    }
+
+    #[test]
+    fn test_plausible_crc_with_oversized_header() {
+        let ihdr = Ihdr::new(0x7fff_ffff, 0x7fff_ffff, 8, ColorType::Rgba, true).unwrap();
+        let png = Png::from_chunks(vec![
+            ihdr.to_chunk(),
+            chunk("IDAT", &pixels::deflate(&[0; 16], 6)),
+            chunk("IEND", &[]),
+        ]);
+        let mut bytes = png.as_bytes();
+        damage_chunk(&mut bytes, 1);
+        let mut png = Png::parse(&bytes, ParseOptions::lenient()).unwrap().0;
+        let repairs = repair(&mut png, RepairOptions::default().crc(CrcRepair::Plausible));
+        assert_eq!(kinds(&repairs), [RepairKind::CrcKept]);
+    }
+
+    #[test]
+    fn test_fix_structure() {
+        let mut png = Png::try_from(&dice_bytes()[..]).unwrap();
+        let header = png.chunks()[0].clone();
+        let idat = png.chunks()[5].clone();
+        let chunks = png.chunks_mut();
+        // second IDAT after IEND, duplicate IHDR, no PLTE issues in a truecolor image
+        chunks.remove(5);
+        chunks.push(chunk("tEXt", b"Comment\0after end"));
+        chunks.push(idat);
+        chunks.insert(3, header);
+        let repairs = repair(&mut png, RepairOptions::default());
+        assert_eq!(
+            kinds(&repairs),
+            [
+                RepairKind::DuplicateIhdrRemoved,
+                RepairKind::ChunkAfterIendMoved,
+                RepairKind::ChunkAfterIendMoved,
+                RepairKind::IdatMoved,
+            ]
+        );
+        assert_eq!(
+            chunk_types(&png),
+            ["IHDR", "sRGB", "gAMA", "pHYs", "IDAT", "IDAT", "tEXt", "IEND"]
+        );
+        assert!(validate::validate(&png).is_empty());
+        assert!(pixels::decode(&png).is_ok());
+    }
+
+    #[test]
+    fn test_drop_after_iend() {
+        let mut png = Png::from_chunks(vec![
+            chunk("IHDR", &[0, 0, 0, 1, 0, 0, 0, 1, 8, 0, 0, 0, 0]),
+            chunk("IEND", &[]),
+            chunk("ruSt", b"hidden"),
+            chunk("IEND", &[]),
+        ]);
+        let options = RepairOptions::default().after_iend(AfterIend::Drop);
+        let repairs = repair(&mut png, options);
+        assert_eq!(
+            kinds(&repairs),
+            [
+                RepairKind::ChunkAfterIendDropped,
+                RepairKind::DuplicateIendRemoved
+            ]
+        );
+        assert_eq!(chunk_types(&png), ["IHDR", "IEND"]);
+    }
+
+    #[test]
+    fn test_add_iend_and_move_ihdr_and_plte() {
+        let header = [0, 0, 0, 1, 0, 0, 0, 1, 8, 3, 0, 0, 0];
+        let mut png = Png::from_chunks(vec![
+            chunk("IHDR", &[0; 13]),
+            chunk("IDAT", b"data"),
+            chunk("IHDR", &header),
+            chunk("PLTE", &[0, 0, 0]),
+        ]);
+        let repairs = repair(&mut png, RepairOptions::default());
+        assert_eq!(
+            kinds(&repairs),
+            [
+                RepairKind::DuplicateIhdrRemoved,
+                RepairKind::IhdrMovedFirst,
+                RepairKind::IendAdded,
+                RepairKind::PlteMoved,
+            ]
+        );
+        assert_eq!(chunk_types(&png), ["IHDR", "PLTE", "IDAT", "IEND"]);
+        assert_eq!(png.chunks()[0].data(), header);
+        assert_eq!(repairs[2].offset(), None);
+    }
+
+    #[test]
+    fn test_options_from_str() {
+        assert_eq!(
+            CrcRepair::from_str("plausible").unwrap(),
+            CrcRepair::Plausible
+        );
+        assert_eq!(AfterIend::from_str("drop").unwrap(), AfterIend::Drop);
+        assert!(CrcRepair::from_str("some").is_err());
+        assert!(AfterIend::from_str("keep").is_err());
+    }
+}
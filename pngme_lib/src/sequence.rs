@@ -0,0 +1,277 @@
+//! Splitting of payloads into a numbered sequence of chunks
+//!
+//! Every part starts with a header: magic `pmSQ`, format version, message id, zero based
+//! part index and total number of parts. Numbers are big endian u32. Message id is random
+//! and tells apart sequences of the same chunk type.
+use std::collections::HashSet;
+use std::convert::TryFrom;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::SequenceError;
+
+const MAGIC: &[u8; 4] = b"pmSQ";
+const VERSION: u8 = 1;
+pub const HEADER_LENGTH: usize = MAGIC.len() + 1 + 3 * 4;
+/// Missing parts listed in an error, the total of a damaged header can be huge
+const MAX_REPORTED_MISSING: usize = 100;
+
+/// Header found at the start of every part
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct SequenceHeader {
+    message_id: u32,
+    index: u32,
+    total: u32,
+}
+
+impl SequenceHeader {
+    pub fn message_id(&self) -> u32 {
+        self.message_id
+    }
+
+    pub fn index(&self) -> u32 {
+        self.index
+    }
+
+    pub fn total(&self) -> u32 {
+        self.total
+    }
+
+    /// Parses header of a part and returns it together with the rest of the data
+    pub fn parse(data: &[u8]) -> Result<(Self, &[u8]), SequenceError> {
+        if !is_sequence_part(data) {
+            return Err(SequenceError::InvalidHeader);
+        }
+        if data[MAGIC.len()] != VERSION {
+            return Err(SequenceError::UnsupportedVersion(data[MAGIC.len()]));
+        }
+        let number = |i: usize| {
+            let start = MAGIC.len() + 1 + i * 4;
+            u32::from_be_bytes([
+                data[start],
+                data[start + 1],
+                data[start + 2],
+                data[start + 3],
+            ])
+        };
+        let header = SequenceHeader {
+            message_id: number(0),
+            index: number(1),
+            total: number(2),
+        };
+        if header.index >= header.total {
+            return Err(SequenceError::InvalidHeader);
+        }
+        Ok((header, &data[HEADER_LENGTH..]))
+    }
+
+    fn to_bytes(self) -> Vec<u8> {
+        let mut bytes = Vec::with_capacity(HEADER_LENGTH);
+        bytes.extend_from_slice(MAGIC);
+        bytes.push(VERSION);
+        bytes.extend_from_slice(&self.message_id.to_be_bytes());
+        bytes.extend_from_slice(&self.index.to_be_bytes());
+        bytes.extend_from_slice(&self.total.to_be_bytes());
+        bytes
+    }
+}
+
+/// Checks whether chunk data starts like a sequence part
+pub fn is_sequence_part(data: &[u8]) -> bool {
+    data.len() >= HEADER_LENGTH && data.starts_with(MAGIC)
+}
+
+/// Splits payload into chunks with data no longer than `max_chunk_size` including header
+pub fn split(
+    chunk_type: &ChunkType,
+    payload: &[u8],
+    max_chunk_size: usize,
+) -> Result<Vec<Chunk>, SequenceError> {
+    if max_chunk_size <= HEADER_LENGTH || max_chunk_size > Chunk::MAX_LENGTH {
+        return Err(SequenceError::InvalidMaxChunkSize(max_chunk_size));
+    }
+    let part_size = max_chunk_size - HEADER_LENGTH;
+    let parts: Vec<&[u8]> = match payload.is_empty() {
+        true => vec![payload],
+        false => payload.chunks(part_size).collect(),
+    };
+    let total = u32::try_from(parts.len()).map_err(|_| SequenceError::TooManyParts)?;
+    let mut message_id = [0; 4];
+    getrandom::getrandom(&mut message_id).map_err(|_| SequenceError::RandomSource)?;
+    let message_id = u32::from_be_bytes(message_id);
+    parts
+        .into_iter()
+        .zip(0..)
+        .map(|(part, index)| {
+            let header = SequenceHeader {
+                message_id,
+                index,
+                total,
+            };
+            let mut data = header.to_bytes();
+            data.extend_from_slice(part);
+            Ok(Chunk::try_new(chunk_type.clone(), &data)?)
+        })
+        .collect()
+}
+
+/// Joins parts of one message given in file order
+pub fn reassemble<'a>(parts: impl IntoIterator<Item = &'a [u8]>) -> Result<Vec<u8>, SequenceError> {
+    let parts = parts
+        .into_iter()
+        .map(SequenceHeader::parse)
+        .collect::<Result<Vec<_>, _>>()?;
+    let (first, _) = parts.first().ok_or(SequenceError::NoParts)?;
+    if parts.iter().any(|(h, _)| h.message_id != first.message_id) {
+        return Err(SequenceError::MixedMessages);
+    }
+    if parts.iter().any(|(h, _)| h.total != first.total) {
+        return Err(SequenceError::InconsistentTotal);
+    }
+    // Total is not trusted before the parts it announces are known to be present
+    if first.total as usize > parts.len() {
+        let present: HashSet<u32> = parts.iter().map(|(header, _)| header.index).collect();
+        let missing = (0..first.total)
+            .filter(|index| !present.contains(index))
+            .take(MAX_REPORTED_MISSING)
+            .collect();
+        return Err(SequenceError::MissingParts(missing));
+    }
+    let mut seen = vec![false; first.total as usize];
+    for (header, _) in parts.iter() {
+        if std::mem::replace(&mut seen[header.index as usize], true) {
+            return Err(SequenceError::DuplicatePart(header.index));
+        }
+    }
+    let missing: Vec<u32> = (0..first.total).filter(|&i| !seen[i as usize]).collect();
+    if !missing.is_empty() {
+        return Err(SequenceError::MissingParts(missing));
+    }
+    if let Some((position, (header, _))) = parts
+        .iter()
+        .enumerate()
+        .find(|(position, (header, _))| header.index as usize != *position)
+    {
+        return Err(SequenceError::OutOfOrder {
+            position: position as u32,
+            index: header.index,
+        });
+    }
+    Ok(parts
+        .into_iter()
+        .flat_map(|(_, data)| data)
+        .copied()
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn parts(payload: &[u8], max_chunk_size: usize) -> Vec<Chunk> {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        split(&chunk_type, payload, max_chunk_size).unwrap()
+    }
+
+    fn data(chunks: &[Chunk]) -> Vec<&[u8]> {
+        chunks.iter().map(|chunk| chunk.data()).collect()
+    }
+
+    #[test]
+    fn test_split_and_reassemble() {
+        let payload: Vec<u8> = (0..=255).collect();
+        let chunks = parts(&payload, HEADER_LENGTH + 100);
+        assert_eq!(chunks.len(), 3);
+        assert!(chunks
+            .iter()
+            .all(|c| c.length() as usize <= HEADER_LENGTH + 100));
+        assert!(chunks.iter().all(|c| c.chunk_type().to_string() == "ruSt"));
+        let (header, _) = SequenceHeader::parse(chunks[2].data()).unwrap();
+        assert_eq!((header.index(), header.total()), (2, 3));
+        assert_eq!(reassemble(data(&chunks)).unwrap(), payload);
+    }
+
+    #[test]
+    fn test_empty_payload() {
+        let chunks = parts(b"", 100);
+        assert_eq!(chunks.len(), 1);
+        assert_eq!(reassemble(data(&chunks)).unwrap(), b"");
+    }
+
+    #[test]
+    fn test_invalid_max_chunk_size() {
+        let chunk_type = ChunkType::from_str("ruSt").unwrap();
+        let result = split(&chunk_type, b"data", HEADER_LENGTH);
+        assert!(matches!(result, Err(SequenceError::InvalidMaxChunkSize(_))));
+    }
+
+    #[test]
+    fn test_missing_part() {
+        let chunks = parts(&[1; 50], HEADER_LENGTH + 10);
+        let mut parts = data(&chunks);
+        parts.remove(3);
+        parts.remove(1);
+        let result = reassemble(parts);
+        assert!(matches!(result, Err(SequenceError::MissingParts(m)) if m == [1, 3]));
+    }
+
+    #[test]
+    fn test_huge_total() {
+        let header = SequenceHeader {
+            message_id: 1,
+            index: 0,
+            total: u32::MAX,
+        };
+        let part = [header.to_bytes(), b"data".to_vec()].concat();
+        let result = reassemble(vec![&part[..]]);
+        assert!(matches!(
+            result,
+            Err(SequenceError::MissingParts(m)) if m.len() == MAX_REPORTED_MISSING && m[0] == 1
+        ));
+    }
+
+    #[test]
+    fn test_reordered_parts() {
+        let chunks = parts(&[1; 50], HEADER_LENGTH + 10);
+        let mut parts = data(&chunks);
+        parts.swap(1, 2);
+        let result = reassemble(parts);
+        assert!(matches!(
+            result,
+            Err(SequenceError::OutOfOrder {
+                position: 1,
+                index: 2
+            })
+        ));
+    }
+
+    #[test]
+    fn test_duplicate_and_mixed_parts() {
+        let chunks = parts(&[1; 50], HEADER_LENGTH + 10);
+        let mut parts = data(&chunks);
+        parts.push(parts[0]);
+        assert!(matches!(
+            reassemble(parts),
+            Err(SequenceError::DuplicatePart(0))
+        ));
+        let other = self::parts(&[2; 50], HEADER_LENGTH + 10);
+        let mut parts = data(&chunks);
+        parts[4] = other[4].data();
+        assert!(matches!(
+            reassemble(parts),
+            Err(SequenceError::MixedMessages)
+        ));
+    }
+
+    #[test]
+    fn test_not_a_sequence() {
+        assert!(!is_sequence_part(b"plain message"));
+        let result = reassemble(vec![&b"plain message that is long"[..]]);
+        assert!(matches!(result, Err(SequenceError::InvalidHeader)));
+        assert!(matches!(
+            reassemble(Vec::new()),
+            Err(SequenceError::NoParts)
+        ));
+    }
+}
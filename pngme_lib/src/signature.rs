@@ -0,0 +1,371 @@
+//! Ed25519 signatures over the content of a PNG file
+//!
+//! Signature is stored in a private ancillary `sgNT` chunk, it is not safe to copy so editors
+//! drop it together with the guarantee when they change critical chunks. Chunk layout, all
+//! numbers are big endian:
+//!
+//! | bytes | field                                               |
+//! |-------|-----------------------------------------------------|
+//! | 1     | format version, currently 1                         |
+//! | 1     | signature algorithm, 1 is Ed25519                   |
+//! | 32    | SHA-256 fingerprint of the public key               |
+//! | 2     | number `n` of covered ancillary chunk types         |
+//! | 4 × n | covered ancillary chunk types                       |
+//! | 64    | signature                                           |
+//!
+//! Signed message is a SHA-256 digest of the fields above except the signature followed by
+//! type, length and data of every covered chunk in file order. Critical chunks are always
+//! covered, ancillary ones only when their type is listed, signature chunks never.
+use std::convert::{TryFrom, TryInto};
+
+use byteorder::ByteOrder;
+use ed25519_dalek::{Signer, Verifier};
+use sha2::{Digest, Sha256};
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::SignatureError;
+use crate::png::Png;
+
+pub use ed25519_dalek::{SigningKey, VerifyingKey};
+
+/// Type of the chunk holding a signature
+pub const CHUNK_TYPE: [u8; 4] = *b"sgNT";
+
+const VERSION: u8 = 1;
+const ALGORITHM_ED25519: u8 = 1;
+const FINGERPRINT_LENGTH: usize = 32;
+const SIGNATURE_LENGTH: usize = ed25519_dalek::SIGNATURE_LENGTH;
+const KEY_LENGTH: usize = ed25519_dalek::SECRET_KEY_LENGTH;
+
+/// Content of a signature chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Signature {
+    fingerprint: [u8; FINGERPRINT_LENGTH],
+    covered_types: Vec<ChunkType>,
+    signature: [u8; SIGNATURE_LENGTH],
+}
+
+impl Signature {
+    /// SHA-256 of the public key that verifies this signature
+    pub fn fingerprint(&self) -> &[u8; FINGERPRINT_LENGTH] {
+        &self.fingerprint
+    }
+
+    /// Ancillary chunk types covered in addition to critical chunks
+    pub fn covered_types(&self) -> &[ChunkType] {
+        &self.covered_types
+    }
+
+    /// Indexes of chunks covered by this signature in file order
+    pub fn covered_chunks(&self, png: &Png) -> Vec<usize> {
+        covered_chunks(png, &self.covered_types)
+    }
+
+    pub fn as_bytes(&self) -> Vec<u8> {
+        let mut bytes = self.header();
+        bytes.extend_from_slice(&self.signature);
+        bytes
+    }
+
+    fn header(&self) -> Vec<u8> {
+        let mut header = Vec::with_capacity(4 + FINGERPRINT_LENGTH + 4 * self.covered_types.len());
+        header.extend_from_slice(&[VERSION, ALGORITHM_ED25519]);
+        header.extend_from_slice(&self.fingerprint);
+        header.extend_from_slice(&(self.covered_types.len() as u16).to_be_bytes());
+        for chunk_type in self.covered_types.iter() {
+            header.extend_from_slice(&chunk_type.bytes());
+        }
+        header
+    }
+
+    fn digest(&self, png: &Png) -> [u8; 32] {
+        let mut hasher = Sha256::new();
+        hasher.update(self.header());
+        for index in self.covered_chunks(png) {
+            let chunk = &png.chunks()[index];
+            hasher.update(chunk.chunk_type().bytes());
+            hasher.update(chunk.length().to_be_bytes());
+            hasher.update(chunk.data());
+        }
+        hasher.finalize().into()
+    }
+}
+
+impl TryFrom<&[u8]> for Signature {
+    type Error = SignatureError;
+
+    fn try_from(bytes: &[u8]) -> Result<Self, Self::Error> {
+        if bytes.len() < 4 + FINGERPRINT_LENGTH + SIGNATURE_LENGTH {
+            return Err(SignatureError::InvalidChunk);
+        }
+        if bytes[0] != VERSION {
+            return Err(SignatureError::UnsupportedVersion(bytes[0]));
+        }
+        if bytes[1] != ALGORITHM_ED25519 {
+            return Err(SignatureError::UnsupportedAlgorithm(bytes[1]));
+        }
+        let (fingerprint, rest) = bytes[2..].split_at(FINGERPRINT_LENGTH);
+        let count = usize::from(byteorder::BigEndian::read_u16(rest));
+        let rest = &rest[2..];
+        if rest.len() != 4 * count + SIGNATURE_LENGTH {
+            return Err(SignatureError::InvalidChunk);
+        }
+        let (types, signature) = rest.split_at(4 * count);
+        let covered_types = types
+            .chunks(4)
+            .map(|bytes| {
+                let bytes: [u8; 4] = bytes.try_into().expect("chunk type has 4 bytes");
+                ChunkType::try_from(bytes).map_err(|_| SignatureError::InvalidChunk)
+            })
+            .collect::<Result<_, _>>()?;
+        Ok(Signature {
+            fingerprint: fingerprint.try_into().expect("length is checked above"),
+            covered_types,
+            signature: signature.try_into().expect("length is checked above"),
+        })
+    }
+}
+
+/// Creates a new signing key from the operating system random source
+pub fn generate_key() -> Result<SigningKey, SignatureError> {
+    let mut secret = [0; KEY_LENGTH];
+    getrandom::getrandom(&mut secret).map_err(|_| SignatureError::RandomSource)?;
+    Ok(SigningKey::from_bytes(&secret))
+}
+
+/// SHA-256 of the public key
+pub fn fingerprint(key: &VerifyingKey) -> [u8; FINGERPRINT_LENGTH] {
+    Sha256::digest(key.as_bytes()).into()
+}
+
+/// Lowercase hexadecimal representation of bytes, used for keys and fingerprints
+pub fn to_hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub fn signing_key_from_hex(hex: &str) -> Result<SigningKey, SignatureError> {
+    Ok(SigningKey::from_bytes(&key_from_hex(hex)?))
+}
+
+pub fn verifying_key_from_hex(hex: &str) -> Result<VerifyingKey, SignatureError> {
+    VerifyingKey::from_bytes(&key_from_hex(hex)?).map_err(|_| SignatureError::InvalidKey)
+}
+
+fn key_from_hex(hex: &str) -> Result<[u8; KEY_LENGTH], SignatureError> {
+    let hex = hex.trim();
+    if hex.len() != 2 * KEY_LENGTH || !hex.is_ascii() {
+        return Err(SignatureError::InvalidKey);
+    }
+    let mut key = [0; KEY_LENGTH];
+    for (byte, pair) in key.iter_mut().zip(hex.as_bytes().chunks(2)) {
+        let pair = std::str::from_utf8(pair).expect("hex is ASCII");
+        *byte = u8::from_str_radix(pair, 16).map_err(|_| SignatureError::InvalidKey)?;
+    }
+    Ok(key)
+}
+
+/// Indexes of critical chunks and ancillary chunks of given types, signature chunks excluded
+pub fn covered_chunks(png: &Png, ancillary: &[ChunkType]) -> Vec<usize> {
+    png.chunks()
+        .iter()
+        .enumerate()
+        .filter(|(_, chunk)| chunk.chunk_type().bytes() != CHUNK_TYPE)
+        .filter(|(_, chunk)| {
+            chunk.chunk_type().is_critical() || ancillary.contains(chunk.chunk_type())
+        })
+        .map(|(index, _)| index)
+        .collect()
+}
+
+/// Signs critical chunks and ancillary chunks of given types, replacing previous signature
+///
+/// Signature chunk is inserted before IEND.
+pub fn sign(
+    png: &mut Png,
+    key: &SigningKey,
+    ancillary: &[ChunkType],
+) -> Result<Signature, SignatureError> {
+    let mut covered_types: Vec<ChunkType> = Vec::new();
+    for chunk_type in ancillary.iter() {
+        if chunk_type.is_critical() || chunk_type.bytes() == CHUNK_TYPE {
+            return Err(SignatureError::InvalidCoveredType(chunk_type.to_string()));
+        }
+        if !covered_types.contains(chunk_type) {
+            covered_types.push(chunk_type.clone());
+        }
+    }
+    png.retain_chunks(|chunk| chunk.chunk_type().bytes() != CHUNK_TYPE);
+    let mut signature = Signature {
+        fingerprint: fingerprint(&key.verifying_key()),
+        covered_types,
+        signature: [0; SIGNATURE_LENGTH],
+    };
+    signature.signature = key.sign(&signature.digest(png)).to_bytes();
+    let chunk_type = ChunkType::try_from(CHUNK_TYPE).expect("signature chunk type is valid");
+    png.insert_chunk_before_iend(Chunk::new(chunk_type, &signature.as_bytes()))?;
+    Ok(signature)
+}
+
+/// Finds the only signature chunk of a PNG file
+pub fn find_signature(png: &Png) -> Result<Signature, SignatureError> {
+    let mut chunks = png
+        .chunks()
+        .iter()
+        .filter(|chunk| chunk.chunk_type().bytes() == CHUNK_TYPE);
+    let chunk = chunks.next().ok_or(SignatureError::NotSigned)?;
+    let others = chunks.count();
+    if others > 0 {
+        return Err(SignatureError::MultipleSignatures(others + 1));
+    }
+    Signature::try_from(chunk.data())
+}
+
+/// Checks signature of a PNG file with a public key and returns it when it is valid
+pub fn verify(png: &Png, key: &VerifyingKey) -> Result<Signature, SignatureError> {
+    let signature = find_signature(png)?;
+    if signature.fingerprint != fingerprint(key) {
+        return Err(SignatureError::KeyMismatch);
+    }
+    let ed25519 = ed25519_dalek::Signature::from_bytes(&signature.signature);
+    key.verify(&signature.digest(png), &ed25519)
+        .map_err(|_| SignatureError::BadSignature)?;
+    Ok(signature)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::str::FromStr;
+
+    fn signed(ancillary: &[&str]) -> (Png, SigningKey) {
+        let mut png = Png::from_file("../images/secret.png").unwrap();
+        let key = generate_key().unwrap();
+        let ancillary: Vec<ChunkType> = ancillary
+            .iter()
+            .map(|chunk_type| ChunkType::from_str(chunk_type).unwrap())
+            .collect();
+        sign(&mut png, &key, &ancillary).unwrap();
+        (png, key)
+    }
+
+    fn text_chunk() -> Chunk {
+        Chunk::new(ChunkType::from_str("tEXt").unwrap(), b"Comment\0hello")
+    }
+
+    #[test]
+    fn test_sign_and_verify() {
+        let (png, key) = signed(&[]);
+        let signature = verify(&png, &key.verifying_key()).unwrap();
+        assert!(signature.covered_types().is_empty());
+        let covered = signature.covered_chunks(&png);
+        assert!(!covered.is_empty());
+        assert!(covered
+            .iter()
+            .all(|&index| png.chunks()[index].chunk_type().is_critical()));
+        let signature_chunk = png.chunk_by_type(&ChunkType::try_from(CHUNK_TYPE).unwrap());
+        assert!(signature_chunk.is_some());
+    }
+
+    #[test]
+    fn test_uncovered_ancillary_chunk_can_change() {
+        let (mut png, key) = signed(&[]);
+        png.insert_chunk_before_iend(text_chunk()).unwrap();
+        assert!(verify(&png, &key.verifying_key()).is_ok());
+    }
+
+    #[test]
+    fn test_covered_ancillary_chunk_can_not_change() {
+        let (mut png, key) = signed(&["tEXt"]);
+        png.insert_chunk_before_iend(text_chunk()).unwrap();
+        let result = verify(&png, &key.verifying_key());
+        assert!(matches!(result, Err(SignatureError::BadSignature)));
+    }
+
+    #[test]
+    fn test_modified_image_data() {
+        let (png, key) = signed(&[]);
+        let mut chunks = png.chunks().to_vec();
+        let index = chunks
+            .iter()
+            .position(|chunk| chunk.chunk_type().bytes() == *b"IDAT")
+            .unwrap();
+        let mut data = chunks[index].data().to_vec();
+        data[10] ^= 1;
+        chunks[index] = Chunk::new(chunks[index].chunk_type().clone(), &data);
+        let result = verify(&Png::from_chunks(chunks), &key.verifying_key());
+        assert!(matches!(result, Err(SignatureError::BadSignature)));
+    }
+
+    #[test]
+    fn test_wrong_key() {
+        let (png, _) = signed(&[]);
+        let other = generate_key().unwrap();
+        let result = verify(&png, &other.verifying_key());
+        assert!(matches!(result, Err(SignatureError::KeyMismatch)));
+    }
+
+    #[test]
+    fn test_resign_replaces_signature() {
+        let (mut png, key) = signed(&[]);
+        let ancillary = [ChunkType::from_str("tEXt").unwrap()];
+        sign(&mut png, &key, &ancillary).unwrap();
+        let signature = verify(&png, &key.verifying_key()).unwrap();
+        assert_eq!(signature.covered_types(), &ancillary[..]);
+    }
+
+    #[test]
+    fn test_not_signed_and_multiple_signatures() {
+        let png = Png::from_file("../images/secret.png").unwrap();
+        assert!(matches!(
+            find_signature(&png),
+            Err(SignatureError::NotSigned)
+        ));
+
+        let (mut png, _) = signed(&[]);
+        let chunk = png
+            .chunk_by_type(&ChunkType::try_from(CHUNK_TYPE).unwrap())
+            .unwrap()
+            .clone();
+        png.append_chunk(chunk);
+        let result = find_signature(&png);
+        assert!(matches!(result, Err(SignatureError::MultipleSignatures(2))));
+    }
+
+    #[test]
+    fn test_invalid_covered_type() {
+        let mut png = Png::from_file("../images/secret.png").unwrap();
+        let key = generate_key().unwrap();
+        for chunk_type in ["IDAT", "sgNT"].iter() {
+            let ancillary = [ChunkType::from_str(chunk_type).unwrap()];
+            let result = sign(&mut png, &key, &ancillary);
+            assert!(matches!(result, Err(SignatureError::InvalidCoveredType(_))));
+        }
+    }
+
+    #[test]
+    fn test_signature_round_trip() {
+        let (png, _) = signed(&["tEXt", "iTXt"]);
+        let signature = find_signature(&png).unwrap();
+        let bytes = signature.as_bytes();
+        assert_eq!(bytes.len(), 4 + 32 + 8 + 64);
+        assert_eq!(Signature::try_from(&bytes[..]).unwrap(), signature);
+        assert!(matches!(
+            Signature::try_from(&bytes[..bytes.len() - 1]),
+            Err(SignatureError::InvalidChunk)
+        ));
+    }
+
+    #[test]
+    fn test_key_hex() {
+        let key = generate_key().unwrap();
+        let hex = to_hex(key.as_bytes());
+        assert_eq!(hex.len(), 64);
+        let parsed = signing_key_from_hex(&format!("{}\n", hex)).unwrap();
+        assert_eq!(parsed.as_bytes(), key.as_bytes());
+        let public = verifying_key_from_hex(&to_hex(key.verifying_key().as_bytes())).unwrap();
+        assert_eq!(public, key.verifying_key());
+        assert!(signing_key_from_hex("abc").is_err());
+        assert!(signing_key_from_hex(&"zz".repeat(32)).is_err());
+    }
+}
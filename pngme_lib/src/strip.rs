@@ -0,0 +1,176 @@
+//! Removes metadata and hidden data from a `Png` according to a policy based on chunk type bits
+//!
+//! Public critical chunks (IHDR, PLTE, IDAT, IEND) are needed to display the image and are never
+//! removed. Chunks after IEND and bytes that follow them are always removed.
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::StripPolicyParseError;
+use crate::png::Png;
+
+/// Which chunks `strip` removes besides everything after IEND
+#[derive(Debug, Clone)]
+pub enum StripPolicy {
+    /// Every ancillary chunk
+    Ancillary,
+    /// Every private chunk, including private critical ones
+    Private,
+    /// Every chunk that is not safe to copy
+    NotSafeToCopy,
+    /// Every chunk whose type is not listed
+    Allowlist(Vec<ChunkType>),
+}
+
+impl StripPolicy {
+    /// Whether chunk of given type is removed, chunks after IEND are removed regardless
+    pub fn removes(&self, chunk_type: &ChunkType) -> bool {
+        if chunk_type.is_critical() && chunk_type.is_public() {
+            return false;
+        }
+        match self {
+            Self::Ancillary => !chunk_type.is_critical(),
+            Self::Private => !chunk_type.is_public(),
+            Self::NotSafeToCopy => !chunk_type.is_safe_to_copy(),
+            Self::Allowlist(types) => !types.contains(chunk_type),
+        }
+    }
+}
+
+impl std::str::FromStr for StripPolicy {
+    type Err = StripPolicyParseError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "ancillary" => Ok(Self::Ancillary),
+            "private" => Ok(Self::Private),
+            "unsafe" => Ok(Self::NotSafeToCopy),
+            _ => Err(StripPolicyParseError::Unknown(s.to_string())),
+        }
+    }
+}
+
+/// What `strip` removed
+pub struct Stripped {
+    chunks: Vec<Chunk>,
+    trailing_data: Vec<u8>,
+}
+
+impl Stripped {
+    /// Removed chunks in file order
+    pub fn chunks(&self) -> &[Chunk] {
+        &self.chunks
+    }
+    /// Bytes after IEND that did not form chunks
+    pub fn trailing_data(&self) -> &[u8] {
+        &self.trailing_data
+    }
+    /// Number of bytes the file shrinks by
+    pub fn len(&self) -> usize {
+        let chunks: usize = self.chunks.iter().map(|chunk| chunk.as_bytes().len()).sum();
+        chunks + self.trailing_data.len()
+    }
+    pub fn is_empty(&self) -> bool {
+        self.chunks.is_empty() && self.trailing_data.is_empty()
+    }
+}
+
+/// Removes chunks matching `policy`, chunks after the first IEND and trailing data from `png`
+pub fn strip(png: &mut Png, policy: &StripPolicy) -> Stripped {
+    let mut after_iend = false;
+    let chunks = png.retain_chunks(|chunk| {
+        let keep = !after_iend && !policy.removes(chunk.chunk_type());
+        after_iend = after_iend || chunk.chunk_type().bytes() == *b"IEND";
+        keep
+    });
+    Stripped {
+        chunks,
+        trailing_data: png.remove_trailing_data(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::convert::TryFrom;
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::test_utils::chunk;
+
+    fn testing_png() -> Png {
+        Png::from_chunks(
+            [
+                "IHDR", "gAMA", "tEXt", "prVt", "RuSt", "IDAT", "IEND", "ruSt",
+            ]
+            .iter()
+            .map(|chunk_type| chunk(chunk_type, b"data"))
+            .collect(),
+        )
+    }
+
+    fn kept_after(policy: StripPolicy) -> Vec<String> {
+        let mut png = testing_png();
+        strip(&mut png, &policy);
+        png.chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect()
+    }
+
+    #[test]
+    fn test_policies() {
+        assert_eq!(
+            kept_after(StripPolicy::Ancillary),
+            ["IHDR", "RuSt", "IDAT", "IEND"]
+        );
+        assert_eq!(
+            kept_after(StripPolicy::Private),
+            ["IHDR", "gAMA", "tEXt", "IDAT", "IEND"]
+        );
+        assert_eq!(
+            kept_after(StripPolicy::NotSafeToCopy),
+            ["IHDR", "tEXt", "prVt", "RuSt", "IDAT", "IEND"]
+        );
+        let allowlist = vec![ChunkType::from_str("gAMA").unwrap()];
+        assert_eq!(
+            kept_after(StripPolicy::Allowlist(allowlist)),
+            ["IHDR", "gAMA", "IDAT", "IEND"]
+        );
+    }
+
+    #[test]
+    fn test_report() {
+        let mut png = testing_png();
+        let stripped = strip(&mut png, &StripPolicy::Private);
+        let removed: Vec<_> = stripped
+            .chunks()
+            .iter()
+            .map(|chunk| chunk.chunk_type().to_string())
+            .collect();
+        assert_eq!(removed, ["prVt", "RuSt", "ruSt"]);
+        assert_eq!(stripped.len(), 3 * 16);
+        assert!(!stripped.is_empty());
+    }
+
+    #[test]
+    fn test_trailing_data() {
+        let mut bytes = std::fs::read("../images/secret.png").unwrap();
+        bytes.extend(b"trailing");
+        let mut png = Png::try_from(&bytes[..]).unwrap();
+        let stripped = strip(&mut png, &StripPolicy::Ancillary);
+        assert_eq!(stripped.trailing_data(), b"trailing");
+        assert_eq!(stripped.chunks()[3].chunk_type().to_string(), "RuSt");
+        assert_eq!(png.as_bytes().len(), bytes.len() - stripped.len());
+        assert_eq!(png.chunks().len(), 4);
+
+        let stripped = strip(&mut png, &StripPolicy::Ancillary);
+        assert!(stripped.is_empty());
+    }
+
+    #[test]
+    fn test_policy_from_str() {
+        assert!(matches!(
+            StripPolicy::from_str("unsafe"),
+            Ok(StripPolicy::NotSafeToCopy)
+        ));
+        assert!(StripPolicy::from_str("everything").is_err());
+    }
+}
@@ -0,0 +1,28 @@
+//! Helpers shared by unit tests of several modules
+use std::str::FromStr;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::png::Png;
+
+const DICE_PATH: &str = "../images/dice.png";
+
+pub(crate) fn chunk(chunk_type: &str, data: &[u8]) -> Chunk {
+    Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+}
+
+pub(crate) fn chunk_types(png: &Png) -> Vec<String> {
+    png.chunks()
+        .iter()
+        .map(|chunk| chunk.chunk_type().to_string())
+        .collect()
+}
+
+/// Bytes of the sample image in images/dice.png
+pub(crate) fn dice_bytes() -> Vec<u8> {
+    std::fs::read(DICE_PATH).unwrap()
+}
+
+pub(crate) fn dice() -> Png {
+    Png::from_file(DICE_PATH).unwrap()
+}
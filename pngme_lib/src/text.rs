@@ -0,0 +1,464 @@
+//! Typed access to the textual chunks tEXt, zTXt and iTXt
+use std::convert::TryFrom;
+use std::io::{Read, Write};
+use std::str::FromStr;
+
+use flate2::read::ZlibDecoder;
+use flate2::write::ZlibEncoder;
+use flate2::Compression;
+
+use crate::chunk::Chunk;
+use crate::chunk_type::ChunkType;
+use crate::error::TextChunkError;
+
+/// Largest decompressed text, a few bytes of zlib stream can expand to gigabytes
+const MAX_TEXT_LENGTH: usize = 16 << 20;
+
+/// Uncompressed Latin-1 text stored in a tEXt chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct TextChunk {
+    keyword: String,
+    text: String,
+}
+
+impl TextChunk {
+    pub const TYPE: &'static str = "tEXt";
+
+    pub fn new(keyword: &str, text: &str) -> Result<Self, TextChunkError> {
+        validate_keyword(keyword)?;
+        encode_latin1(text)?;
+        Ok(Self {
+            keyword: keyword.into(),
+            text: text.into(),
+        })
+    }
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    pub fn to_chunk(&self) -> Result<Chunk, TextChunkError> {
+        let mut data = encode_latin1(&self.keyword).expect("keyword is validated");
+        data.push(0);
+        data.extend(encode_latin1(&self.text).expect("text is validated"));
+        Ok(Chunk::try_new(chunk_type(Self::TYPE), &data)?)
+    }
+}
+
+impl TryFrom<&Chunk> for TextChunk {
+    type Error = TextChunkError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        check_type(chunk, Self::TYPE)?;
+        let (keyword, text) = split_keyword(chunk.data())?;
+        Ok(Self {
+            keyword,
+            text: decode_latin1(text),
+        })
+    }
+}
+
+/// zlib-compressed Latin-1 text stored in a zTXt chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompressedTextChunk {
+    keyword: String,
+    text: String,
+}
+
+impl CompressedTextChunk {
+    pub const TYPE: &'static str = "zTXt";
+
+    pub fn new(keyword: &str, text: &str) -> Result<Self, TextChunkError> {
+        validate_keyword(keyword)?;
+        encode_latin1(text)?;
+        Ok(Self {
+            keyword: keyword.into(),
+            text: text.into(),
+        })
+    }
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    pub fn to_chunk(&self) -> Result<Chunk, TextChunkError> {
+        let mut data = encode_latin1(&self.keyword).expect("keyword is validated");
+        data.extend([0, 0].iter());
+        data.extend(compress(
+            &encode_latin1(&self.text).expect("text is validated"),
+        ));
+        Ok(Chunk::try_new(chunk_type(Self::TYPE), &data)?)
+    }
+}
+
+impl TryFrom<&Chunk> for CompressedTextChunk {
+    type Error = TextChunkError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        check_type(chunk, Self::TYPE)?;
+        let (keyword, rest) = split_keyword(chunk.data())?;
+        let (&method, compressed) = rest.split_first().ok_or(TextChunkError::Truncated)?;
+        check_compression_method(method)?;
+        Ok(Self {
+            keyword,
+            text: decode_latin1(&decompress(compressed)?),
+        })
+    }
+}
+
+/// UTF-8 text with language tag stored in an iTXt chunk, optionally compressed
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InternationalTextChunk {
+    keyword: String,
+    compressed: bool,
+    language_tag: String,
+    translated_keyword: String,
+    text: String,
+}
+
+impl InternationalTextChunk {
+    pub const TYPE: &'static str = "iTXt";
+
+    pub fn new(
+        keyword: &str,
+        text: &str,
+        language_tag: &str,
+        translated_keyword: &str,
+        compressed: bool,
+    ) -> Result<Self, TextChunkError> {
+        validate_keyword(keyword)?;
+        validate_language_tag(language_tag)?;
+        if translated_keyword.contains('\0') {
+            return Err(TextChunkError::InvalidTranslatedKeyword);
+        }
+        Ok(Self {
+            keyword: keyword.into(),
+            compressed,
+            language_tag: language_tag.into(),
+            translated_keyword: translated_keyword.into(),
+            text: text.into(),
+        })
+    }
+    pub fn keyword(&self) -> &str {
+        &self.keyword
+    }
+    pub fn is_compressed(&self) -> bool {
+        self.compressed
+    }
+    pub fn language_tag(&self) -> &str {
+        &self.language_tag
+    }
+    pub fn translated_keyword(&self) -> &str {
+        &self.translated_keyword
+    }
+    pub fn text(&self) -> &str {
+        &self.text
+    }
+    pub fn to_chunk(&self) -> Result<Chunk, TextChunkError> {
+        let mut data = encode_latin1(&self.keyword).expect("keyword is validated");
+        data.extend([0, self.compressed as u8, 0].iter());
+        data.extend(self.language_tag.bytes());
+        data.push(0);
+        data.extend(self.translated_keyword.bytes());
+        data.push(0);
+        match self.compressed {
+            true => data.extend(compress(self.text.as_bytes())),
+            false => data.extend(self.text.bytes()),
+        }
+        Ok(Chunk::try_new(chunk_type(Self::TYPE), &data)?)
+    }
+}
+
+impl TryFrom<&Chunk> for InternationalTextChunk {
+    type Error = TextChunkError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        check_type(chunk, Self::TYPE)?;
+        let (keyword, rest) = split_keyword(chunk.data())?;
+        let (flag, method, rest) = match rest {
+            [flag, method, rest @ ..] => (*flag, *method, rest),
+            _ => return Err(TextChunkError::Truncated),
+        };
+        let compressed = match flag {
+            0 => false,
+            1 => true,
+            _ => return Err(TextChunkError::InvalidCompressionFlag(flag)),
+        };
+        if compressed {
+            check_compression_method(method)?;
+        }
+        let (language_tag, rest) = split_null(rest)?;
+        let (translated_keyword, text) = split_null(rest)?;
+        let text = match compressed {
+            true => decompress(text)?,
+            false => text.to_vec(),
+        };
+        let to_string =
+            |bytes: Vec<u8>| String::from_utf8(bytes).or(Err(TextChunkError::InvalidUtf8));
+        Ok(Self {
+            keyword,
+            compressed,
+            language_tag: decode_latin1(language_tag),
+            translated_keyword: to_string(translated_keyword.to_vec())?,
+            text: to_string(text)?,
+        })
+    }
+}
+
+/// Any of the three textual chunk kinds
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum TextualChunk {
+    Text(TextChunk),
+    Compressed(CompressedTextChunk),
+    International(InternationalTextChunk),
+}
+
+impl TextualChunk {
+    pub fn is_textual(chunk: &Chunk) -> bool {
+        let chunk_type = chunk.chunk_type().to_string();
+        [
+            TextChunk::TYPE,
+            CompressedTextChunk::TYPE,
+            InternationalTextChunk::TYPE,
+        ]
+        .contains(&chunk_type.as_str())
+    }
+    pub fn keyword(&self) -> &str {
+        match self {
+            Self::Text(chunk) => chunk.keyword(),
+            Self::Compressed(chunk) => chunk.keyword(),
+            Self::International(chunk) => chunk.keyword(),
+        }
+    }
+    pub fn text(&self) -> &str {
+        match self {
+            Self::Text(chunk) => chunk.text(),
+            Self::Compressed(chunk) => chunk.text(),
+            Self::International(chunk) => chunk.text(),
+        }
+    }
+    pub fn chunk_type(&self) -> &'static str {
+        match self {
+            Self::Text(_) => TextChunk::TYPE,
+            Self::Compressed(_) => CompressedTextChunk::TYPE,
+            Self::International(_) => InternationalTextChunk::TYPE,
+        }
+    }
+    pub fn to_chunk(&self) -> Result<Chunk, TextChunkError> {
+        match self {
+            Self::Text(chunk) => chunk.to_chunk(),
+            Self::Compressed(chunk) => chunk.to_chunk(),
+            Self::International(chunk) => chunk.to_chunk(),
+        }
+    }
+}
+
+impl TryFrom<&Chunk> for TextualChunk {
+    type Error = TextChunkError;
+
+    fn try_from(chunk: &Chunk) -> Result<Self, Self::Error> {
+        match chunk.chunk_type().to_string().as_str() {
+            TextChunk::TYPE => TextChunk::try_from(chunk).map(Self::Text),
+            CompressedTextChunk::TYPE => CompressedTextChunk::try_from(chunk).map(Self::Compressed),
+            InternationalTextChunk::TYPE => {
+                InternationalTextChunk::try_from(chunk).map(Self::International)
+            }
+            other => Err(TextChunkError::WrongChunkType(other.into())),
+        }
+    }
+}
+
+/// Keyword must be 1-79 printable Latin-1 characters without leading, trailing or double spaces
+pub fn validate_keyword(keyword: &str) -> Result<(), TextChunkError> {
+    let invalid = || Err(TextChunkError::InvalidKeyword(keyword.into()));
+    let bytes = match encode_latin1(keyword) {
+        Ok(bytes) => bytes,
+        Err(_) => return invalid(),
+    };
+    let is_printable = |&b: &u8| (32..=126).contains(&b) || b >= 161;
+    if bytes.is_empty() || bytes.len() > 79 || !bytes.iter().all(is_printable) {
+        return invalid();
+    }
+    if keyword.starts_with(' ') || keyword.ends_with(' ') || keyword.contains("  ") {
+        return invalid();
+    }
+    Ok(())
+}
+
+fn validate_language_tag(language_tag: &str) -> Result<(), TextChunkError> {
+    let is_valid = |ch: char| ch.is_ascii_alphanumeric() || ch == '-';
+    if !language_tag.chars().all(is_valid) {
+        return Err(TextChunkError::InvalidLanguageTag(language_tag.into()));
+    }
+    Ok(())
+}
+
+fn chunk_type(value: &str) -> ChunkType {
+    ChunkType::from_str(value).expect("textual chunk types are valid")
+}
+
+fn check_type(chunk: &Chunk, expected: &str) -> Result<(), TextChunkError> {
+    let actual = chunk.chunk_type().to_string();
+    match actual == expected {
+        true => Ok(()),
+        false => Err(TextChunkError::WrongChunkType(actual)),
+    }
+}
+
+fn check_compression_method(method: u8) -> Result<(), TextChunkError> {
+    match method {
+        0 => Ok(()),
+        _ => Err(TextChunkError::UnknownCompressionMethod(method)),
+    }
+}
+
+fn split_null(bytes: &[u8]) -> Result<(&[u8], &[u8]), TextChunkError> {
+    let position = bytes
+        .iter()
+        .position(|&b| b == 0)
+        .ok_or(TextChunkError::MissingNullSeparator)?;
+    Ok((&bytes[..position], &bytes[position + 1..]))
+}
+
+fn split_keyword(bytes: &[u8]) -> Result<(String, &[u8]), TextChunkError> {
+    let (keyword, rest) = split_null(bytes)?;
+    let keyword = decode_latin1(keyword);
+    validate_keyword(&keyword)?;
+    Ok((keyword, rest))
+}
+
+fn encode_latin1(value: &str) -> Result<Vec<u8>, TextChunkError> {
+    value
+        .chars()
+        .map(|ch| u8::try_from(u32::from(ch)).or(Err(TextChunkError::NotLatin1(ch))))
+        .collect()
+}
+
+fn decode_latin1(bytes: &[u8]) -> String {
+    bytes.iter().map(|&b| char::from(b)).collect()
+}
+
+fn compress(bytes: &[u8]) -> Vec<u8> {
+    let mut encoder = ZlibEncoder::new(Vec::new(), Compression::default());
+    encoder
+        .write_all(bytes)
+        .and_then(|_| encoder.finish())
+        .expect("writing to vector does not fail")
+}
+
+fn decompress(bytes: &[u8]) -> Result<Vec<u8>, TextChunkError> {
+    let mut text = Vec::new();
+    ZlibDecoder::new(bytes)
+        .take(MAX_TEXT_LENGTH as u64 + 1)
+        .read_to_end(&mut text)?;
+    if text.len() > MAX_TEXT_LENGTH {
+        return Err(TextChunkError::TooLong(MAX_TEXT_LENGTH));
+    }
+    Ok(text)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_text_chunk_round_trip() {
+        let text = TextChunk::new("Comment", "café au lait").unwrap();
+        let chunk = text.to_chunk().unwrap();
+        assert_eq!(chunk.chunk_type().to_string(), "tEXt");
+        assert_eq!(chunk.data()[..8], *b"Comment\0");
+        assert_eq!(chunk.data()[11], 0xE9); // Latin-1 'é'
+        assert_eq!(TextChunk::try_from(&chunk).unwrap(), text);
+    }
+
+    #[test]
+    fn test_decompression_limit() {
+        let bomb = compress(&vec![b'a'; MAX_TEXT_LENGTH + 1]);
+        assert!(bomb.len() < 100_000);
+        assert!(matches!(
+            decompress(&bomb),
+            Err(TextChunkError::TooLong(MAX_TEXT_LENGTH))
+        ));
+        let text = vec![b'a'; MAX_TEXT_LENGTH];
+        assert_eq!(decompress(&compress(&text)).unwrap(), text);
+    }
+
+    #[test]
+    fn test_text_chunk_rejects_non_latin1() {
+        assert!(TextChunk::new("Comment", "日本").is_err());
+    }
+
+    #[test]
+    fn test_compressed_text_chunk_round_trip() {
+        let text = "repeated ".repeat(100);
+        let ztxt = CompressedTextChunk::new("Description", &text).unwrap();
+        let chunk = ztxt.to_chunk().unwrap();
+        assert!(chunk.data().len() < text.len());
+        assert_eq!(chunk.data()[..13], *b"Description\0\0");
+        let parsed = CompressedTextChunk::try_from(&chunk).unwrap();
+        assert_eq!(parsed.text(), text);
+    }
+
+    #[test]
+    fn test_international_text_chunk_round_trip() {
+        for &compressed in [false, true].iter() {
+            let itxt = InternationalTextChunk::new(
+                "Title",
+                "日本語のテキスト",
+                "ja-JP",
+                "タイトル",
+                compressed,
+            )
+            .unwrap();
+            let chunk = itxt.to_chunk().unwrap();
+            let parsed = InternationalTextChunk::try_from(&chunk).unwrap();
+            assert_eq!(parsed, itxt);
+            assert_eq!(parsed.language_tag(), "ja-JP");
+            assert_eq!(parsed.translated_keyword(), "タイトル");
+            assert_eq!(parsed.is_compressed(), compressed);
+        }
+    }
+
+    #[test]
+    fn test_international_text_chunk_invalid_flag() {
+        let chunk = Chunk::new(chunk_type("iTXt"), b"Title\0\x02\0en\0\0text");
+        assert!(InternationalTextChunk::try_from(&chunk).is_err());
+        let chunk = Chunk::new(chunk_type("iTXt"), b"Title\0\0\0en");
+        assert!(InternationalTextChunk::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_keyword_validation() {
+        assert!(validate_keyword("Author").is_ok());
+        assert!(validate_keyword("Creation Time").is_ok());
+        assert!(validate_keyword("").is_err());
+        assert!(validate_keyword(" Author").is_err());
+        assert!(validate_keyword("Author ").is_err());
+        assert!(validate_keyword("Creation  Time").is_err());
+        assert!(validate_keyword(&"a".repeat(80)).is_err());
+        assert!(validate_keyword("Tab\there").is_err());
+    }
+
+    #[test]
+    fn test_missing_separator() {
+        let chunk = Chunk::new(chunk_type("tEXt"), b"no separator");
+        assert!(TextChunk::try_from(&chunk).is_err());
+    }
+
+    #[test]
+    fn test_textual_chunk_dispatch() {
+        let chunk = CompressedTextChunk::new("Author", "me")
+            .unwrap()
+            .to_chunk()
+            .unwrap();
+        assert!(TextualChunk::is_textual(&chunk));
+        let parsed = TextualChunk::try_from(&chunk).unwrap();
+        assert_eq!(parsed.keyword(), "Author");
+        assert_eq!(parsed.text(), "me");
+        let chunk = Chunk::new(chunk_type("RuSt"), b"Author\0me");
+        assert!(!TextualChunk::is_textual(&chunk));
+        assert!(TextualChunk::try_from(&chunk).is_err());
+    }
+}
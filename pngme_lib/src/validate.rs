@@ -0,0 +1,399 @@
+//! Checks chunk layout of a `Png` against the ordering rules of the PNG specification
+//!
+//! Placement and multiplicity of ancillary chunks come from the chunk type registry.
+use std::convert::TryFrom;
+
+use crate::chunk::Chunk;
+use crate::ihdr::{ColorType, Ihdr};
+use crate::png::Png;
+use crate::registry::{Multiplicity, Placement};
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Severity {
+    Error,
+    Warning,
+}
+
+impl std::fmt::Display for Severity {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::Error => write!(f, "error"),
+            Self::Warning => write!(f, "warning"),
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum DiagnosticKind {
+    MissingIhdr,
+    IhdrNotFirst,
+    MultipleIhdr,
+    InvalidIhdr(String),
+    MissingIend,
+    MultipleIend,
+    ChunkAfterIend(String),
+    MissingIdat,
+    NonConsecutiveIdat,
+    MissingPlte,
+    UnexpectedPlte,
+    MultiplePlte,
+    PlteAfterIdat,
+    UnknownCriticalChunk(String),
+    InvalidReservedBit(String),
+    MustPrecedePlte(String),
+    MustFollowPlte(String),
+    MustPrecedeIdat(String),
+    MultipleChunks(String),
+    ConflictingColorSpaces,
+}
+
+impl DiagnosticKind {
+    pub fn severity(&self) -> Severity {
+        match self {
+            Self::MustPrecedePlte(_)
+            | Self::MustFollowPlte(_)
+            | Self::MustPrecedeIdat(_)
+            | Self::MultipleChunks(_)
+            | Self::ConflictingColorSpaces => Severity::Warning,
+            _ => Severity::Error,
+        }
+    }
+}
+
+impl std::fmt::Display for DiagnosticKind {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self {
+            Self::MissingIhdr => write!(f, "IHDR chunk is missing"),
+            Self::IhdrNotFirst => write!(f, "IHDR must be the first chunk"),
+            Self::MultipleIhdr => write!(f, "IHDR must appear only once"),
+            Self::InvalidIhdr(reason) => write!(f, "IHDR is invalid: {}", reason),
+            Self::MissingIend => write!(f, "IEND chunk is missing"),
+            Self::MultipleIend => write!(f, "IEND must appear only once"),
+            Self::ChunkAfterIend(chunk_type) => write!(f, "{} appears after IEND", chunk_type),
+            Self::MissingIdat => write!(f, "IDAT chunk is missing"),
+            Self::NonConsecutiveIdat => write!(f, "IDAT chunks must be consecutive"),
+            Self::MissingPlte => write!(f, "PLTE is required for indexed-color images"),
+            Self::UnexpectedPlte => write!(f, "PLTE must not appear in grayscale images"),
+            Self::MultiplePlte => write!(f, "PLTE must appear only once"),
+            Self::PlteAfterIdat => write!(f, "PLTE must appear before IDAT"),
+            Self::UnknownCriticalChunk(chunk_type) => {
+                write!(f, "{} is an unknown critical chunk", chunk_type)
+            }
+            Self::InvalidReservedBit(chunk_type) => {
+                write!(f, "{} has reserved bit set", chunk_type)
+            }
+            Self::MustPrecedePlte(chunk_type) => {
+                write!(f, "{} must appear before PLTE and IDAT", chunk_type)
+            }
+            Self::MustFollowPlte(chunk_type) => {
+                write!(f, "{} must appear after PLTE and before IDAT", chunk_type)
+            }
+            Self::MustPrecedeIdat(chunk_type) => {
+                write!(f, "{} must appear before IDAT", chunk_type)
+            }
+            Self::MultipleChunks(chunk_type) => write!(f, "{} must appear only once", chunk_type),
+            Self::ConflictingColorSpaces => write!(f, "iCCP and sRGB should not both appear"),
+        }
+    }
+}
+
+/// Single problem found in a `Png`, optionally pointing at the offending chunk
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Diagnostic {
+    kind: DiagnosticKind,
+    chunk_index: Option<usize>,
+}
+
+impl Diagnostic {
+    fn new(kind: DiagnosticKind, chunk_index: Option<usize>) -> Self {
+        Self { kind, chunk_index }
+    }
+    pub fn kind(&self) -> &DiagnosticKind {
+        &self.kind
+    }
+    pub fn severity(&self) -> Severity {
+        self.kind.severity()
+    }
+    pub fn chunk_index(&self) -> Option<usize> {
+        self.chunk_index
+    }
+    pub fn is_error(&self) -> bool {
+        self.severity() == Severity::Error
+    }
+}
+
+impl std::fmt::Display for Diagnostic {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        match self.chunk_index {
+            Some(index) => write!(f, "{} [chunk {}]: {}", self.severity(), index, self.kind),
+            None => write!(f, "{}: {}", self.severity(), self.kind),
+        }
+    }
+}
+
+/// Returns every violation of the chunk ordering rules found in `png`
+pub fn validate(png: &Png) -> Vec<Diagnostic> {
+    let chunks = png.chunks();
+    let mut diagnostics = vec![];
+    let mut push = |kind, index| diagnostics.push(Diagnostic::new(kind, index));
+    let positions = |chunk_type: &[u8; 4]| -> Vec<usize> {
+        chunks
+            .iter()
+            .enumerate()
+            .filter(|(_, chunk)| chunk.chunk_type().bytes() == *chunk_type)
+            .map(|(i, _)| i)
+            .collect()
+    };
+    let ihdr = positions(b"IHDR");
+    let plte = positions(b"PLTE");
+    let idat = positions(b"IDAT");
+    let iend = positions(b"IEND");
+
+    let mut color_type = None;
+    match ihdr.first() {
+        None => push(DiagnosticKind::MissingIhdr, None),
+        Some(&index) => {
+            if index != 0 {
+                push(DiagnosticKind::IhdrNotFirst, Some(index));
+            }
+            match Ihdr::try_from(&chunks[index]) {
+                Ok(header) => color_type = Some(header.color_type()),
+                Err(e) => push(DiagnosticKind::InvalidIhdr(e.to_string()), Some(index)),
+            }
+        }
+    }
+    for &index in ihdr.iter().skip(1) {
+        push(DiagnosticKind::MultipleIhdr, Some(index));
+    }
+
+    match iend.first() {
+        None => push(DiagnosticKind::MissingIend, None),
+        Some(&index) => {
+            for (i, chunk) in chunks.iter().enumerate().skip(index + 1) {
+                let kind = match chunk.chunk_type().bytes() == *b"IEND" {
+                    true => DiagnosticKind::MultipleIend,
+                    false => DiagnosticKind::ChunkAfterIend(chunk.chunk_type().to_string()),
+                };
+                push(kind, Some(i));
+            }
+        }
+    }
+
+    match (idat.first(), idat.last()) {
+        (Some(&first), Some(&last)) => {
+            if last - first + 1 != idat.len() {
+                let gap = (first..=last).find(|i| !idat.contains(i));
+                push(DiagnosticKind::NonConsecutiveIdat, gap);
+            }
+        }
+        _ => push(DiagnosticKind::MissingIdat, None),
+    }
+
+    match (color_type, plte.first()) {
+        (Some(ColorType::Indexed), None) => push(DiagnosticKind::MissingPlte, None),
+        (Some(ColorType::Grayscale), Some(&index))
+        | (Some(ColorType::GrayscaleAlpha), Some(&index)) => {
+            push(DiagnosticKind::UnexpectedPlte, Some(index))
+        }
+        _ => {}
+    }
+    for &index in plte.iter().skip(1) {
+        push(DiagnosticKind::MultiplePlte, Some(index));
+    }
+    if let (Some(&plte), Some(&idat)) = (plte.first(), idat.first()) {
+        if plte > idat {
+            push(DiagnosticKind::PlteAfterIdat, Some(plte));
+        }
+    }
+
+    let first_plte = plte.first().copied();
+    let first_idat = idat.first().copied();
+    let is_after = |index: usize, anchor: Option<usize>| anchor.is_some_and(|a| index > a);
+    for (index, chunk) in chunks.iter().enumerate() {
+        let chunk_type = chunk.chunk_type();
+        let name = || chunk_type.to_string();
+        if !chunk_type.is_reserved_bit_valid() {
+            push(DiagnosticKind::InvalidReservedBit(name()), Some(index));
+        }
+        if chunk_type.is_critical() && !chunk_type.is_known() {
+            push(DiagnosticKind::UnknownCriticalChunk(name()), Some(index));
+        }
+        // Critical chunks have their own checks above
+        let spec = match chunk_type.spec() {
+            Some(spec) if !chunk_type.is_critical() => spec,
+            _ => continue,
+        };
+        match spec.placement() {
+            Placement::BeforePlte if is_after(index, first_plte) || is_after(index, first_idat) => {
+                push(DiagnosticKind::MustPrecedePlte(name()), Some(index));
+            }
+            Placement::AfterPlte => {
+                let before_plte = first_plte.is_some_and(|plte| index < plte);
+                if before_plte || is_after(index, first_idat) {
+                    push(DiagnosticKind::MustFollowPlte(name()), Some(index));
+                }
+            }
+            Placement::BeforeIdat if is_after(index, first_idat) => {
+                push(DiagnosticKind::MustPrecedeIdat(name()), Some(index));
+            }
+            _ => {}
+        }
+        if spec.multiplicity() == Multiplicity::AtMostOnce && has_earlier(chunks, index) {
+            push(DiagnosticKind::MultipleChunks(name()), Some(index));
+        }
+    }
+
+    if !positions(b"iCCP").is_empty() && !positions(b"sRGB").is_empty() {
+        push(DiagnosticKind::ConflictingColorSpaces, None);
+    }
+    diagnostics
+}
+
+fn has_earlier(chunks: &[Chunk], index: usize) -> bool {
+    let chunk_type = chunks[index].chunk_type();
+    chunks[..index]
+        .iter()
+        .any(|chunk| chunk.chunk_type() == chunk_type)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::str::FromStr;
+
+    use super::*;
+    use crate::chunk_type::ChunkType;
+
+    fn chunk(chunk_type: &str) -> Chunk {
+        let data: &[u8] = match chunk_type {
+            "IHDR" => &[0, 0, 0, 1, 0, 0, 0, 1, 8, 3, 0, 0, 0],
+            _ => &[],
+        };
+        Chunk::new(ChunkType::from_str(chunk_type).unwrap(), data)
+    }
+
+    fn kinds(types: &[&str]) -> Vec<DiagnosticKind> {
+        let png = Png::from_chunks(types.iter().map(|t| chunk(t)).collect());
+        validate(&png).into_iter().map(|d| d.kind).collect()
+    }
+
+    #[test]
+    fn test_valid_png() {
+        assert!(
+            kinds(&["IHDR", "gAMA", "PLTE", "tRNS", "IDAT", "IDAT", "tEXt", "IEND"]).is_empty()
+        );
+    }
+
+    #[test]
+    fn test_missing_critical_chunks() {
+        let found = kinds(&["gAMA"]);
+        assert!(found.contains(&DiagnosticKind::MissingIhdr));
+        assert!(found.contains(&DiagnosticKind::MissingIdat));
+        assert!(found.contains(&DiagnosticKind::MissingIend));
+        assert_eq!(
+            kinds(&["IHDR", "IDAT", "IEND"]),
+            [DiagnosticKind::MissingPlte]
+        );
+    }
+
+    #[test]
+    fn test_multiple_ihdr() {
+        let found = kinds(&["IHDR", "PLTE", "IHDR", "IDAT", "IEND"]);
+        assert_eq!(found, [DiagnosticKind::MultipleIhdr]);
+    }
+
+    #[test]
+    fn test_ihdr_not_first() {
+        let found = kinds(&["PLTE", "IHDR", "IDAT", "IEND"]);
+        assert_eq!(found, [DiagnosticKind::IhdrNotFirst]);
+    }
+
+    #[test]
+    fn test_plte_after_idat() {
+        let found = kinds(&["IHDR", "IDAT", "PLTE", "IEND"]);
+        assert_eq!(found, [DiagnosticKind::PlteAfterIdat]);
+    }
+
+    #[test]
+    fn test_non_consecutive_idat() {
+        let png = Png::from_chunks(
+            ["IHDR", "PLTE", "IDAT", "tEXt", "IDAT", "IEND"]
+                .iter()
+                .map(|t| chunk(t))
+                .collect(),
+        );
+        let diagnostics = validate(&png);
+        assert_eq!(diagnostics.len(), 1);
+        assert_eq!(diagnostics[0].kind(), &DiagnosticKind::NonConsecutiveIdat);
+        assert_eq!(diagnostics[0].chunk_index(), Some(3));
+        assert!(diagnostics[0].is_error());
+    }
+
+    #[test]
+    fn test_chunks_after_iend() {
+        let found = kinds(&["IHDR", "PLTE", "IDAT", "IEND", "ruSt", "IEND"]);
+        assert_eq!(
+            found,
+            [
+                DiagnosticKind::ChunkAfterIend("ruSt".into()),
+                DiagnosticKind::MultipleIend
+            ]
+        );
+    }
+
+    #[test]
+    fn test_ancillary_ordering_is_warning() {
+        let png = Png::from_chunks(
+            ["IHDR", "PLTE", "gAMA", "IDAT", "pHYs", "IEND"]
+                .iter()
+                .map(|t| chunk(t))
+                .collect(),
+        );
+        let diagnostics = validate(&png);
+        let found: Vec<_> = diagnostics.iter().map(|d| d.kind().clone()).collect();
+        assert_eq!(
+            found,
+            [
+                DiagnosticKind::MustPrecedePlte("gAMA".into()),
+                DiagnosticKind::MustPrecedeIdat("pHYs".into())
+            ]
+        );
+        assert!(diagnostics
+            .iter()
+            .all(|d| d.severity() == Severity::Warning));
+    }
+
+    #[test]
+    fn test_unknown_and_duplicate_chunks() {
+        let found = kinds(&["IHDR", "PLTE", "tIME", "tIME", "RUST", "IDAT", "IEND"]);
+        assert_eq!(
+            found,
+            [
+                DiagnosticKind::MultipleChunks("tIME".into()),
+                DiagnosticKind::UnknownCriticalChunk("RUST".into())
+            ]
+        );
+    }
+
+    #[test]
+    fn test_extension_chunks() {
+        let found = kinds(&[
+            "IHDR", "PLTE", "cICP", "acTL", "IDAT", "fdAT", "eXIf", "eXIf", "IEND",
+        ]);
+        assert_eq!(
+            found,
+            [
+                DiagnosticKind::MustPrecedePlte("cICP".into()),
+                DiagnosticKind::MustPrecedeIdat("eXIf".into()),
+                DiagnosticKind::MustPrecedeIdat("eXIf".into()),
+                DiagnosticKind::MultipleChunks("eXIf".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_image_file() {
+        let bytes = std::fs::read("../images/dice.png").unwrap();
+        let png = Png::try_from(&bytes[..]).unwrap();
+        assert!(validate(&png).is_empty());
+    }
+}